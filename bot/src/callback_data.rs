@@ -0,0 +1,412 @@
+use std::{fmt, str::FromStr};
+
+use db_pg::AnswerStyle;
+use localization::Lang;
+use teloxide::types::InlineKeyboardButton;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Telegram принимает callback_data длиной не больше 64 байт
+pub const MAX_CALLBACK_DATA_LEN: usize = 64;
+
+/// Параметр `set_lang` для сброса языка к языку Telegram
+const AUTO_LANGUAGE: &str = "auto";
+
+/// Данные inline-кнопок. Кодируются как `<тег>` или `<тег>:<параметр>`,
+/// UUID пишется без дефисов, чтобы укладываться в лимит Telegram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallbackData {
+    Faq,
+    Profits,
+    BackToFaq,
+    BackToMenu,
+    FileSharing,
+    /// Страница списка файлов в обменнике
+    FilesPage(usize),
+    File(Uuid),
+    FileShare(Uuid),
+    FileDelete(Uuid),
+    Settings,
+    SettingsLanguage,
+    SettingsStyle,
+    /// `None` — язык из Telegram
+    SetLanguage(Option<Lang>),
+    SetStyle(AnswerStyle),
+    ToggleNotifications,
+    Wallet,
+    MyRequests,
+    AllRequests,
+    AnsweredRequests,
+    AcceptedRequests,
+    /// Страница списка обращений пользователя
+    Page(usize),
+    /// Кнопка с номером текущей страницы, ничего не делает
+    CurrentPage,
+    Message(Uuid),
+    BackToPage(usize),
+    FeedbackYes(Uuid),
+    FeedbackNo(Uuid),
+    FeedbackEscalate(Uuid),
+    FeedbackThanks,
+    AdminPage(usize),
+    AdminMessage(Uuid),
+    AdminAccept(Uuid),
+    AdminReject(Uuid),
+    AdminAnswer(Uuid),
+}
+
+/// Вариант `CallbackData` без параметров, по нему выбирается обработчик
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallbackKind {
+    Faq,
+    Profits,
+    BackToFaq,
+    BackToMenu,
+    FileSharing,
+    FilesPage,
+    File,
+    FileShare,
+    FileDelete,
+    Settings,
+    SettingsLanguage,
+    SettingsStyle,
+    SetLanguage,
+    SetStyle,
+    ToggleNotifications,
+    Wallet,
+    MyRequests,
+    AllRequests,
+    AnsweredRequests,
+    AcceptedRequests,
+    Page,
+    CurrentPage,
+    Message,
+    BackToPage,
+    FeedbackYes,
+    FeedbackNo,
+    FeedbackEscalate,
+    FeedbackThanks,
+    AdminPage,
+    AdminMessage,
+    AdminAccept,
+    AdminReject,
+    AdminAnswer,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CallbackDataError {
+    #[error("unknown callback tag: {0}")]
+    UnknownTag(String),
+    #[error("callback {0} expects a parameter")]
+    MissingParam(&'static str),
+    #[error("callback {0} does not take a parameter")]
+    UnexpectedParam(&'static str),
+    #[error("invalid parameter for callback {0}")]
+    InvalidParam(&'static str),
+}
+
+impl CallbackData {
+    pub fn kind(&self) -> CallbackKind {
+        match self {
+            CallbackData::Faq => CallbackKind::Faq,
+            CallbackData::Profits => CallbackKind::Profits,
+            CallbackData::BackToFaq => CallbackKind::BackToFaq,
+            CallbackData::BackToMenu => CallbackKind::BackToMenu,
+            CallbackData::FileSharing => CallbackKind::FileSharing,
+            CallbackData::FilesPage(_) => CallbackKind::FilesPage,
+            CallbackData::File(_) => CallbackKind::File,
+            CallbackData::FileShare(_) => CallbackKind::FileShare,
+            CallbackData::FileDelete(_) => CallbackKind::FileDelete,
+            CallbackData::Settings => CallbackKind::Settings,
+            CallbackData::SettingsLanguage => CallbackKind::SettingsLanguage,
+            CallbackData::SettingsStyle => CallbackKind::SettingsStyle,
+            CallbackData::SetLanguage(_) => CallbackKind::SetLanguage,
+            CallbackData::SetStyle(_) => CallbackKind::SetStyle,
+            CallbackData::ToggleNotifications => CallbackKind::ToggleNotifications,
+            CallbackData::Wallet => CallbackKind::Wallet,
+            CallbackData::MyRequests => CallbackKind::MyRequests,
+            CallbackData::AllRequests => CallbackKind::AllRequests,
+            CallbackData::AnsweredRequests => CallbackKind::AnsweredRequests,
+            CallbackData::AcceptedRequests => CallbackKind::AcceptedRequests,
+            CallbackData::Page(_) => CallbackKind::Page,
+            CallbackData::CurrentPage => CallbackKind::CurrentPage,
+            CallbackData::Message(_) => CallbackKind::Message,
+            CallbackData::BackToPage(_) => CallbackKind::BackToPage,
+            CallbackData::FeedbackYes(_) => CallbackKind::FeedbackYes,
+            CallbackData::FeedbackNo(_) => CallbackKind::FeedbackNo,
+            CallbackData::FeedbackEscalate(_) => CallbackKind::FeedbackEscalate,
+            CallbackData::FeedbackThanks => CallbackKind::FeedbackThanks,
+            CallbackData::AdminPage(_) => CallbackKind::AdminPage,
+            CallbackData::AdminMessage(_) => CallbackKind::AdminMessage,
+            CallbackData::AdminAccept(_) => CallbackKind::AdminAccept,
+            CallbackData::AdminReject(_) => CallbackKind::AdminReject,
+            CallbackData::AdminAnswer(_) => CallbackKind::AdminAnswer,
+        }
+    }
+
+    /// Inline-кнопка с этими данными
+    pub fn button(self, text: impl Into<String>) -> InlineKeyboardButton {
+        InlineKeyboardButton::callback(text, self.to_string())
+    }
+}
+
+impl CallbackKind {
+    fn tag(self) -> &'static str {
+        match self {
+            CallbackKind::Faq => "faq",
+            CallbackKind::Profits => "profits",
+            CallbackKind::BackToFaq => "back_to_faq",
+            CallbackKind::BackToMenu => "back_to_menu",
+            CallbackKind::FileSharing => "file_sharing",
+            CallbackKind::FilesPage => "files_page",
+            CallbackKind::File => "file",
+            CallbackKind::FileShare => "file_share",
+            CallbackKind::FileDelete => "file_del",
+            CallbackKind::Settings => "settings",
+            CallbackKind::SettingsLanguage => "settings_lang",
+            CallbackKind::SettingsStyle => "settings_style",
+            CallbackKind::SetLanguage => "set_lang",
+            CallbackKind::SetStyle => "set_style",
+            CallbackKind::ToggleNotifications => "toggle_notify",
+            CallbackKind::Wallet => "wallet",
+            CallbackKind::MyRequests => "my_requests",
+            CallbackKind::AllRequests => "all_requests",
+            CallbackKind::AnsweredRequests => "answered_requests",
+            CallbackKind::AcceptedRequests => "accepted_requests",
+            CallbackKind::Page => "page",
+            CallbackKind::CurrentPage => "current_page",
+            CallbackKind::Message => "msg",
+            CallbackKind::BackToPage => "back_to_page",
+            CallbackKind::FeedbackYes => "fb_yes",
+            CallbackKind::FeedbackNo => "fb_no",
+            CallbackKind::FeedbackEscalate => "fb_escalate",
+            CallbackKind::FeedbackThanks => "fb_thanks",
+            CallbackKind::AdminPage => "adm_page",
+            CallbackKind::AdminMessage => "adm_msg",
+            CallbackKind::AdminAccept => "adm_accept",
+            CallbackKind::AdminReject => "adm_reject",
+            CallbackKind::AdminAnswer => "adm_answer",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Self> {
+        Some(match tag {
+            "faq" => CallbackKind::Faq,
+            "profits" => CallbackKind::Profits,
+            "back_to_faq" => CallbackKind::BackToFaq,
+            "back_to_menu" => CallbackKind::BackToMenu,
+            "file_sharing" => CallbackKind::FileSharing,
+            "files_page" => CallbackKind::FilesPage,
+            "file" => CallbackKind::File,
+            "file_share" => CallbackKind::FileShare,
+            "file_del" => CallbackKind::FileDelete,
+            "settings" => CallbackKind::Settings,
+            "settings_lang" => CallbackKind::SettingsLanguage,
+            "settings_style" => CallbackKind::SettingsStyle,
+            "set_lang" => CallbackKind::SetLanguage,
+            "set_style" => CallbackKind::SetStyle,
+            "toggle_notify" => CallbackKind::ToggleNotifications,
+            "wallet" => CallbackKind::Wallet,
+            "my_requests" => CallbackKind::MyRequests,
+            "all_requests" => CallbackKind::AllRequests,
+            "answered_requests" => CallbackKind::AnsweredRequests,
+            "accepted_requests" => CallbackKind::AcceptedRequests,
+            "page" => CallbackKind::Page,
+            "current_page" => CallbackKind::CurrentPage,
+            "msg" => CallbackKind::Message,
+            "back_to_page" => CallbackKind::BackToPage,
+            "fb_yes" => CallbackKind::FeedbackYes,
+            "fb_no" => CallbackKind::FeedbackNo,
+            "fb_escalate" => CallbackKind::FeedbackEscalate,
+            "fb_thanks" => CallbackKind::FeedbackThanks,
+            "adm_page" => CallbackKind::AdminPage,
+            "adm_msg" => CallbackKind::AdminMessage,
+            "adm_accept" => CallbackKind::AdminAccept,
+            "adm_reject" => CallbackKind::AdminReject,
+            "adm_answer" => CallbackKind::AdminAnswer,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for CallbackData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = self.kind().tag();
+        match self {
+            CallbackData::Page(number)
+            | CallbackData::BackToPage(number)
+            | CallbackData::AdminPage(number)
+            | CallbackData::FilesPage(number) => {
+                write!(f, "{}:{}", tag, number)
+            }
+            CallbackData::Message(id)
+            | CallbackData::FeedbackYes(id)
+            | CallbackData::FeedbackNo(id)
+            | CallbackData::FeedbackEscalate(id)
+            | CallbackData::AdminMessage(id)
+            | CallbackData::AdminAccept(id)
+            | CallbackData::AdminReject(id)
+            | CallbackData::AdminAnswer(id)
+            | CallbackData::File(id)
+            | CallbackData::FileShare(id)
+            | CallbackData::FileDelete(id) => write!(f, "{}:{}", tag, id.simple()),
+            CallbackData::SetLanguage(lang) => write!(f, "{}:{}", tag, lang.map_or(AUTO_LANGUAGE, Lang::code)),
+            CallbackData::SetStyle(style) => write!(f, "{}:{}", tag, style.code()),
+            _ => f.write_str(tag),
+        }
+    }
+}
+
+impl FromStr for CallbackData {
+    type Err = CallbackDataError;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        let (tag, param) = match data.split_once(':') {
+            Some((tag, param)) => (tag, Some(param)),
+            None => (data, None),
+        };
+        let kind = CallbackKind::from_tag(tag).ok_or_else(|| CallbackDataError::UnknownTag(tag.to_string()))?;
+        let tag = kind.tag();
+
+        let number = || -> Result<usize, CallbackDataError> {
+            param
+                .ok_or(CallbackDataError::MissingParam(tag))?
+                .parse()
+                .map_err(|_| CallbackDataError::InvalidParam(tag))
+        };
+        let uuid = || -> Result<Uuid, CallbackDataError> {
+            Uuid::try_parse(param.ok_or(CallbackDataError::MissingParam(tag))?)
+                .map_err(|_| CallbackDataError::InvalidParam(tag))
+        };
+        let lang = || -> Result<Option<Lang>, CallbackDataError> {
+            match param.ok_or(CallbackDataError::MissingParam(tag))? {
+                AUTO_LANGUAGE => Ok(None),
+                code => Lang::from_code(code).map(Some).ok_or(CallbackDataError::InvalidParam(tag)),
+            }
+        };
+        let style = || -> Result<AnswerStyle, CallbackDataError> {
+            AnswerStyle::from_code(param.ok_or(CallbackDataError::MissingParam(tag))?)
+                .ok_or(CallbackDataError::InvalidParam(tag))
+        };
+        let plain = |data: CallbackData| -> Result<CallbackData, CallbackDataError> {
+            match param {
+                Some(_) => Err(CallbackDataError::UnexpectedParam(tag)),
+                None => Ok(data),
+            }
+        };
+
+        match kind {
+            CallbackKind::Faq => plain(CallbackData::Faq),
+            CallbackKind::Profits => plain(CallbackData::Profits),
+            CallbackKind::BackToFaq => plain(CallbackData::BackToFaq),
+            CallbackKind::BackToMenu => plain(CallbackData::BackToMenu),
+            CallbackKind::FileSharing => plain(CallbackData::FileSharing),
+            CallbackKind::Settings => plain(CallbackData::Settings),
+            CallbackKind::SettingsLanguage => plain(CallbackData::SettingsLanguage),
+            CallbackKind::SettingsStyle => plain(CallbackData::SettingsStyle),
+            CallbackKind::ToggleNotifications => plain(CallbackData::ToggleNotifications),
+            CallbackKind::Wallet => plain(CallbackData::Wallet),
+            CallbackKind::SetLanguage => Ok(CallbackData::SetLanguage(lang()?)),
+            CallbackKind::SetStyle => Ok(CallbackData::SetStyle(style()?)),
+            CallbackKind::MyRequests => plain(CallbackData::MyRequests),
+            CallbackKind::AllRequests => plain(CallbackData::AllRequests),
+            CallbackKind::AnsweredRequests => plain(CallbackData::AnsweredRequests),
+            CallbackKind::AcceptedRequests => plain(CallbackData::AcceptedRequests),
+            CallbackKind::CurrentPage => plain(CallbackData::CurrentPage),
+            CallbackKind::FeedbackThanks => plain(CallbackData::FeedbackThanks),
+            CallbackKind::Page => Ok(CallbackData::Page(number()?)),
+            CallbackKind::BackToPage => Ok(CallbackData::BackToPage(number()?)),
+            CallbackKind::AdminPage => Ok(CallbackData::AdminPage(number()?)),
+            CallbackKind::FilesPage => Ok(CallbackData::FilesPage(number()?)),
+            CallbackKind::File => Ok(CallbackData::File(uuid()?)),
+            CallbackKind::FileShare => Ok(CallbackData::FileShare(uuid()?)),
+            CallbackKind::FileDelete => Ok(CallbackData::FileDelete(uuid()?)),
+            CallbackKind::Message => Ok(CallbackData::Message(uuid()?)),
+            CallbackKind::FeedbackYes => Ok(CallbackData::FeedbackYes(uuid()?)),
+            CallbackKind::FeedbackNo => Ok(CallbackData::FeedbackNo(uuid()?)),
+            CallbackKind::FeedbackEscalate => Ok(CallbackData::FeedbackEscalate(uuid()?)),
+            CallbackKind::AdminMessage => Ok(CallbackData::AdminMessage(uuid()?)),
+            CallbackKind::AdminAccept => Ok(CallbackData::AdminAccept(uuid()?)),
+            CallbackKind::AdminReject => Ok(CallbackData::AdminReject(uuid()?)),
+            CallbackKind::AdminAnswer => Ok(CallbackData::AdminAnswer(uuid()?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_variants() -> Vec<CallbackData> {
+        let id = Uuid::new_v4();
+        vec![
+            CallbackData::Faq,
+            CallbackData::Profits,
+            CallbackData::BackToFaq,
+            CallbackData::BackToMenu,
+            CallbackData::FileSharing,
+            CallbackData::FilesPage(usize::MAX),
+            CallbackData::File(id),
+            CallbackData::FileShare(id),
+            CallbackData::FileDelete(id),
+            CallbackData::Settings,
+            CallbackData::SettingsLanguage,
+            CallbackData::SettingsStyle,
+            CallbackData::SetLanguage(None),
+            CallbackData::SetLanguage(Some(Lang::En)),
+            CallbackData::SetStyle(AnswerStyle::Creative),
+            CallbackData::ToggleNotifications,
+            CallbackData::Wallet,
+            CallbackData::MyRequests,
+            CallbackData::AllRequests,
+            CallbackData::AnsweredRequests,
+            CallbackData::AcceptedRequests,
+            CallbackData::Page(usize::MAX),
+            CallbackData::CurrentPage,
+            CallbackData::Message(id),
+            CallbackData::BackToPage(usize::MAX),
+            CallbackData::FeedbackYes(id),
+            CallbackData::FeedbackNo(id),
+            CallbackData::FeedbackEscalate(id),
+            CallbackData::FeedbackThanks,
+            CallbackData::AdminPage(usize::MAX),
+            CallbackData::AdminMessage(id),
+            CallbackData::AdminAccept(id),
+            CallbackData::AdminReject(id),
+            CallbackData::AdminAnswer(id),
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        for data in all_variants() {
+            assert_eq!(data.to_string().parse::<CallbackData>(), Ok(data));
+        }
+    }
+
+    #[test]
+    fn fits_telegram_limit() {
+        for data in all_variants() {
+            let encoded = data.to_string();
+            assert!(encoded.len() <= MAX_CALLBACK_DATA_LEN, "{} is {} bytes", encoded, encoded.len());
+        }
+    }
+
+    #[test]
+    fn accepts_hyphenated_uuid() {
+        let id = Uuid::new_v4();
+        assert_eq!(format!("msg:{}", id).parse::<CallbackData>(), Ok(CallbackData::Message(id)));
+    }
+
+    #[test]
+    fn rejects_malformed_data() {
+        assert_eq!("".parse::<CallbackData>(), Err(CallbackDataError::UnknownTag(String::new())));
+        assert_eq!("msg_123".parse::<CallbackData>(), Err(CallbackDataError::UnknownTag("msg_123".to_string())));
+        assert_eq!("page".parse::<CallbackData>(), Err(CallbackDataError::MissingParam("page")));
+        assert_eq!("page:abc".parse::<CallbackData>(), Err(CallbackDataError::InvalidParam("page")));
+        assert_eq!("fb_yes:not-a-uuid".parse::<CallbackData>(), Err(CallbackDataError::InvalidParam("fb_yes")));
+        assert_eq!("set_lang:de".parse::<CallbackData>(), Err(CallbackDataError::InvalidParam("set_lang")));
+        assert_eq!("set_style:wild".parse::<CallbackData>(), Err(CallbackDataError::InvalidParam("set_style")));
+        assert_eq!("faq:1".parse::<CallbackData>(), Err(CallbackDataError::UnexpectedParam("faq")));
+    }
+}
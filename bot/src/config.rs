@@ -0,0 +1,233 @@
+use std::{env, net::SocketAddr, path::PathBuf, time::Duration};
+
+use grpc_service::client::ai_service_url;
+
+use crate::storage::S3Config;
+
+/// Where dialogue FSM states are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DialogueStorageKind {
+    /// States are lost on restart
+    #[default]
+    InMemory,
+    /// States survive restarts and deploys (table `dialogues`)
+    Postgres,
+}
+
+impl DialogueStorageKind {
+    fn from_env_value(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "memory" | "inmemory" | "in_memory" => Some(Self::InMemory),
+            "postgres" | "pg" => Some(Self::Postgres),
+            _ => None,
+        }
+    }
+}
+
+/// Where contents of uploaded files are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileStorageKind {
+    /// Directory `file_storage_path`, only for a single bot instance
+    #[default]
+    Local,
+    /// S3-compatible bucket configured by `S3_*` vars
+    S3,
+}
+
+impl FileStorageKind {
+    fn from_env_value(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "local" | "disk" => Some(Self::Local),
+            "s3" | "minio" => Some(Self::S3),
+            _ => None,
+        }
+    }
+}
+
+/// Limits of the file sharing menu for one user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileQuota {
+    pub max_files: i64,
+    pub max_bytes: i64,
+}
+
+impl FileQuota {
+    /// Quota from `<prefix>_FILES` and `<prefix>_MB`
+    fn from_env(prefix: &str, max_files: i64, max_mb: i64) -> Self {
+        let var = |name: &str, default: i64| {
+            env::var(format!("{}_{}", prefix, name))
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            max_files: var("FILES", max_files),
+            max_bytes: var("MB", max_mb) * 1024 * 1024,
+        }
+    }
+}
+
+/// MIME types accepted by default, `type/*` allows the whole type
+const DEFAULT_MIME_TYPES: &[&str] = &[
+    "application/pdf",
+    "application/msword",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.ms-excel",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/vnd.ms-powerpoint",
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    "application/vnd.oasis.opendocument.text",
+    "application/vnd.oasis.opendocument.spreadsheet",
+    "application/rtf",
+    "application/json",
+    "application/zip",
+    "application/x-7z-compressed",
+    "application/x-rar-compressed",
+    "application/vnd.rar",
+    "text/*",
+    "image/*",
+    "audio/*",
+    "video/*",
+];
+
+/// Bot configuration with .env parse
+#[derive(Debug, Clone)]
+pub struct BotConfig {
+    pub dialogue_storage: DialogueStorageKind,
+    /// Address of the Qortex AI gRPC service
+    pub ai_service_url: String,
+    /// How long to wait for Qortex AI before giving up on a question
+    pub ai_timeout: Duration,
+    /// Minimal pause between edits of a streamed AI answer, keeps us within Throttle limits
+    pub stream_edit_interval: Duration,
+    /// How many previous question/answer pairs are sent to AI as context
+    pub history_turns: usize,
+    /// Credits charged for one AI answer
+    pub ai_answer_price: i64,
+    /// Credits every user gets once
+    pub welcome_credits: i64,
+    pub file_storage: FileStorageKind,
+    /// Directory with files uploaded to the file sharing menu
+    pub file_storage_path: PathBuf,
+    /// Bucket for `FileStorageKind::S3`
+    pub s3: Option<S3Config>,
+    /// Lifetime of a new share link, None for links that never expire
+    pub share_link_ttl: Option<Duration>,
+    /// Downloads allowed by a new share link, None for unlimited
+    pub share_link_max_downloads: Option<i32>,
+    /// How often expired share links are closed
+    pub share_cleanup_interval: Duration,
+    /// Quota of regular users
+    pub file_quota_default: FileQuota,
+    /// Quota of users with access, administrators have no quota
+    pub file_quota_with_access: FileQuota,
+    /// MIME types that can be uploaded, `type/*` allows the whole type
+    pub allowed_mime_types: Vec<String>,
+    /// Address of the HTTP server for share links, None keeps it disabled
+    pub download_server_addr: Option<SocketAddr>,
+    /// External URL of the HTTP server, browser links are sent only when it is set
+    pub public_base_url: Option<String>,
+    /// How long a user role is cached, changes in Postgres reset it earlier
+    pub role_cache_ttl: Duration,
+    /// JSON Lines file for incoming updates, None keeps recording disabled
+    pub update_record_path: Option<PathBuf>,
+    /// Key for pseudonymized user ids in recordings, a random one is used per run when missing
+    pub update_record_key: Option<String>,
+}
+
+/// BotConfig Default implimentation using env vars
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            dialogue_storage: env::var("DIALOGUE_STORAGE")
+                .ok()
+                .and_then(|v| DialogueStorageKind::from_env_value(&v))
+                .unwrap_or_default(),
+            ai_service_url: ai_service_url(),
+            ai_timeout: Duration::from_secs(
+                env::var("AI_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(60),
+            ),
+            // tokio::time::interval panics on zero, 0 falls back to the default
+            stream_edit_interval: Duration::from_millis(
+                env::var("AI_STREAM_EDIT_INTERVAL_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|&ms| ms > 0)
+                    .unwrap_or(1500),
+            ),
+            history_turns: env::var("AI_HISTORY_TURNS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5),
+            ai_answer_price: env::var("AI_ANSWER_PRICE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+            welcome_credits: env::var("WALLET_WELCOME_CREDITS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+            file_storage: env::var("FILE_STORAGE")
+                .ok()
+                .and_then(|v| FileStorageKind::from_env_value(&v))
+                .unwrap_or_default(),
+            s3: S3Config::from_env(),
+            file_storage_path: env::var("FILE_STORAGE_PATH")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| PathBuf::from("storage")),
+            // 0 disables the limit
+            share_link_ttl: match env::var("SHARE_LINK_TTL_HOURS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(24u64)
+            {
+                0 => None,
+                hours => Some(Duration::from_secs(hours * 3600)),
+            },
+            share_link_max_downloads: match env::var("SHARE_LINK_MAX_DOWNLOADS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10)
+            {
+                max if max > 0 => Some(max),
+                _ => None,
+            },
+            // tokio::time::interval panics on zero, 0 falls back to the default
+            share_cleanup_interval: Duration::from_secs(
+                env::var("SHARE_CLEANUP_INTERVAL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|&secs| secs > 0)
+                    .unwrap_or(600),
+            ),
+            file_quota_default: FileQuota::from_env("FILE_QUOTA_DEFAULT", 20, 100),
+            file_quota_with_access: FileQuota::from_env("FILE_QUOTA_WITH_ACCESS", 200, 2048),
+            allowed_mime_types: env::var("ALLOWED_MIME_TYPES")
+                .map(|v| v.split(',').map(|m| m.trim().to_lowercase()).filter(|m| !m.is_empty()).collect())
+                .unwrap_or_else(|_| DEFAULT_MIME_TYPES.iter().map(|m| m.to_string()).collect()),
+            download_server_addr: env::var("DOWNLOAD_SERVER_ADDR")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            public_base_url: env::var("PUBLIC_BASE_URL")
+                .ok()
+                .map(|v| v.trim().trim_end_matches('/').to_string())
+                .filter(|v| !v.is_empty()),
+            role_cache_ttl: Duration::from_secs(
+                env::var("ROLE_CACHE_TTL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(300),
+            ),
+            update_record_path: env::var("UPDATE_RECORD_PATH")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .map(PathBuf::from),
+            update_record_key: env::var("UPDATE_RECORD_KEY")
+                .ok()
+                .filter(|v| !v.is_empty()),
+        }
+    }
+}
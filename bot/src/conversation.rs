@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use db::{collections::history::HistoryMessage, Database};
+use grpc_service::server::proto::ChatMessage;
+use logging::log_error;
+
+use crate::types::HandlerResult;
+
+/// Conversation context for Qortex AI backed by UserHistory.
+/// Without Mongo every question is answered without context
+pub struct Conversation {
+    db: Option<Arc<Database>>,
+    /// How many question/answer pairs are sent to AI
+    max_turns: usize,
+}
+
+impl Conversation {
+    pub fn new(db: Option<Arc<Database>>, max_turns: usize) -> Self {
+        Self { db, max_turns }
+    }
+
+    /// Last turns of the dialogue in the form AI service expects
+    pub async fn context(&self, telegram_id: i64) -> Vec<ChatMessage> {
+        let Some(db) = &self.db else {
+            return Vec::new();
+        };
+
+        match db.last_history(telegram_id, self.max_turns * 2).await {
+            Ok(messages) => messages
+                .into_iter()
+                .map(|m| ChatMessage { role: m.role, content: m.content })
+                .collect(),
+            Err(e) => {
+                log_error!("Не удалось загрузить историю диалога {}: {}", telegram_id, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Saves question and AI answer as one turn
+    pub async fn record_turn(&self, telegram_id: i64, question: String, answer: String) {
+        let Some(db) = &self.db else {
+            return;
+        };
+
+        let turn = vec![HistoryMessage::user(question), HistoryMessage::assistant(answer)];
+        if let Err(e) = db.append_history(telegram_id, turn).await {
+            log_error!("Не удалось сохранить историю диалога {}: {}", telegram_id, e);
+        }
+    }
+
+    /// Starts a fresh context
+    pub async fn reset(&self, telegram_id: i64) -> HandlerResult {
+        if let Some(db) = &self.db {
+            db.clear_history(telegram_id).await?;
+        }
+        Ok(())
+    }
+}
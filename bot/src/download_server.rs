@@ -0,0 +1,400 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::Utc;
+use db_pg::{ShareRedeem, StoredFile};
+use hmac::{Hmac, Mac};
+use logging::{log_error, log_info};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::Sha256;
+
+use crate::TelegramBot;
+
+/// Символы, которые можно оставить в `filename*` без кодирования (attr-char из RFC 5987)
+const ATTR_CHAR: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!').remove(b'#').remove(b'$').remove(b'&').remove(b'+').remove(b'-')
+    .remove(b'.').remove(b'^').remove(b'_').remove(b'`').remove(b'|').remove(b'~');
+
+/// Что отдавать по заголовку Range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// Файл целиком: Range нет, он некорректен или просит несколько частей
+    Full,
+    /// Байты `start..=end`
+    Partial(u64, u64),
+    /// Запрошенная часть за пределами файла
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Разбор `Range: bytes=...` для файла длиной `len`, поддерживается одна часть
+    pub fn parse(header: Option<&str>, len: u64) -> Self {
+        let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+            return Self::Full;
+        };
+        if spec.contains(',') {
+            return Self::Full;
+        }
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return Self::Full;
+        };
+
+        let (start, end) = match (start.trim(), end.trim()) {
+            // bytes=-500, последние 500 байт
+            ("", suffix) => match suffix.parse::<u64>() {
+                Ok(0) => return Self::Unsatisfiable,
+                Ok(suffix) => (len.saturating_sub(suffix), len.saturating_sub(1)),
+                Err(_) => return Self::Full,
+            },
+            (start, "") => match start.parse::<u64>() {
+                Ok(start) => (start, len.saturating_sub(1)),
+                Err(_) => return Self::Full,
+            },
+            (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+                (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+                _ => return Self::Full,
+            },
+        };
+        if len == 0 || start >= len {
+            return Self::Unsatisfiable;
+        }
+        Self::Partial(start, end)
+    }
+}
+
+/// Cookie сессии скачивания
+const SESSION_COOKIE: &str = "share_session";
+/// Сколько после засчитанного скачивания его можно докачивать без нового списания
+const SESSION_TTL: Duration = Duration::from_secs(6 * 3600);
+
+/// Сессии скачивания. Засчитанный GET выдаёт cookie, подписанную ключом сервера,
+/// и запросы с ней (докачка, повтор после пробного `bytes=0-0`) лимит не расходуют.
+/// Ключ живёт в памяти процесса, после перезапуска скачивание засчитывается заново
+pub struct DownloadSessions {
+    key: Vec<u8>,
+}
+
+impl DownloadSessions {
+    pub fn new() -> Self {
+        Self { key: uuid::Uuid::new_v4().as_bytes().to_vec() }
+    }
+
+    fn mac(&self, token: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(format!("{}.{}", token, expires).as_bytes());
+        mac
+    }
+
+    /// Значение cookie для ссылки `token`, действует `SESSION_TTL` с момента `now`
+    pub fn issue(&self, token: &str, now: i64) -> String {
+        let expires = now + SESSION_TTL.as_secs() as i64;
+        format!("{}.{}", expires, hex::encode(self.mac(token, expires).finalize().into_bytes()))
+    }
+
+    /// Выдана ли сессия для этой ссылки и не истекла ли она
+    pub fn verify(&self, token: &str, value: &str, now: i64) -> bool {
+        let Some((expires, signature)) = value.split_once('.') else {
+            return false;
+        };
+        let (Ok(expires), Ok(signature)) = (expires.parse::<i64>(), hex::decode(signature)) else {
+            return false;
+        };
+        expires > now && self.mac(token, expires).verify_slice(&signature).is_ok()
+    }
+
+    /// Сессия из заголовков запроса
+    fn in_headers(&self, token: &str, headers: &HeaderMap, now: i64) -> bool {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|cookies| cookies.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .any(|(name, value)| name == SESSION_COOKIE && self.verify(token, value, now))
+    }
+}
+
+impl Default for DownloadSessions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Состояние HTTP сервера ссылок
+#[derive(Clone)]
+struct ServerState {
+    bots: Arc<TelegramBot>,
+    sessions: Arc<DownloadSessions>,
+}
+
+/// `Content-Disposition` с ASCII именем для старых клиентов и полным именем в `filename*`
+pub fn content_disposition(file_name: &str) -> String {
+    let fallback: String = file_name
+        .chars()
+        .map(|c| if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' { c } else { '_' })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback,
+        utf8_percent_encode(file_name, ATTR_CHAR)
+    )
+}
+
+/// Тип файла: сохранённый при загрузке, по расширению или бинарный
+fn content_type(file: &StoredFile) -> String {
+    file.mime_type
+        .clone()
+        .unwrap_or_else(|| mime_guess::from_path(&file.original_name).first_or_octet_stream().to_string())
+}
+
+/// Маршруты HTTP сервера ссылок
+pub fn router(bots: Arc<TelegramBot>) -> Router {
+    Router::new()
+        .route("/share/{token}", get(download))
+        .with_state(ServerState { bots, sessions: Arc::new(DownloadSessions::new()) })
+}
+
+/// HTTP сервер для скачивания по ссылкам обменника, работает в процессе бота
+pub async fn run_download_server(bots: Arc<TelegramBot>, addr: SocketAddr) {
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            log_error!("Не удалось запустить сервер ссылок на {}: {}", addr, e);
+            return;
+        }
+    };
+    log_info!("Сервер ссылок на файлы слушает {}", addr);
+    if let Err(e) = axum::serve(listener, router(bots)).await {
+        log_error!("Сервер ссылок на файлы остановлен: {}", e);
+    }
+}
+
+async fn download(
+    State(state): State<ServerState>,
+    Path(token): Path<String>,
+    method: Method,
+    headers: HeaderMap,
+) -> Response {
+    match serve_file(&state, &token, &method, &headers).await {
+        Ok(response) => response,
+        Err(e) => {
+            log_error!("Ошибка при скачивании по ссылке {}: {}", token, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn redeem_status(redeem: &ShareRedeem) -> StatusCode {
+    match redeem {
+        ShareRedeem::Redeemed(_) => StatusCode::OK,
+        ShareRedeem::NotFound => StatusCode::NOT_FOUND,
+        ShareRedeem::Expired | ShareRedeem::LimitReached => StatusCode::GONE,
+    }
+}
+
+async fn serve_file(
+    state: &ServerState,
+    token: &str,
+    method: &Method,
+    headers: &HeaderMap,
+) -> crate::types::HandlerResult<Response> {
+    if token.is_empty() || !token.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let bots = &state.bots;
+    // В сессии скачивание уже засчитано, иначе ссылка проверяется со всеми ограничениями.
+    // Счётчик не трогаем, пока не ясно, что просят: длина файла нужна для Range
+    let now = Utc::now().timestamp();
+    let in_session = state.sessions.in_headers(token, headers, now);
+    let redeem = if in_session {
+        bots.files.resume_download(token).await?
+    } else {
+        bots.files.resolve_download(token, false).await?
+    };
+    let file = match redeem {
+        ShareRedeem::Redeemed(file) => file,
+        redeem => return Ok(redeem_status(&redeem).into_response()),
+    };
+    let len = file.size as u64;
+    let range = ByteRange::parse(headers.get(header::RANGE).and_then(|v| v.to_str().ok()), len);
+    if range == ByteRange::Unsatisfiable {
+        return Ok((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{}", len))],
+        ).into_response());
+    }
+
+    // HEAD отдаёт только заголовки. GET без сессии засчитывается как новое скачивание,
+    // с какого бы байта он ни начинался, а сессия выдаётся в ответ
+    let (file, session) = if method == Method::GET && !in_session {
+        match bots.files.resolve_download(token, true).await? {
+            ShareRedeem::Redeemed(file) => (file, Some(state.sessions.issue(token, now))),
+            redeem => return Ok(redeem_status(&redeem).into_response()),
+        }
+    } else {
+        (file, None)
+    };
+
+    let (status, part) = match range {
+        ByteRange::Partial(start, end) => (StatusCode::PARTIAL_CONTENT, Some((start, end))),
+        _ => (StatusCode::OK, None),
+    };
+    let Some(stream) = bots.files.stream_file(&file, part).await? else {
+        log_error!("Содержимое файла {} не найдено в хранилище", file.id);
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut response = Response::new(Body::from_stream(stream));
+    *response.status_mut() = status;
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(&content_type(&file))?);
+    response_headers.insert(header::CONTENT_DISPOSITION, HeaderValue::from_str(&content_disposition(&file.original_name))?);
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    response_headers.insert(header::ETAG, HeaderValue::from_str(&format!("\"{}\"", file.checksum))?);
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+    if let Some(session) = session {
+        let cookie = format!(
+            "{}={}; Path=/share/{}; Max-Age={}; HttpOnly; SameSite=Lax",
+            SESSION_COOKIE, session, token, SESSION_TTL.as_secs()
+        );
+        response_headers.insert(header::SET_COOKIE, HeaderValue::from_str(&cookie)?);
+    }
+    match part {
+        Some((start, end)) => {
+            response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(end - start + 1));
+            response_headers.insert(header::CONTENT_RANGE, HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end, len))?);
+        }
+        None => {
+            response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+        }
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_header() {
+        assert_eq!(ByteRange::parse(None, 100), ByteRange::Full);
+        assert_eq!(ByteRange::parse(Some("bytes=0-9"), 100), ByteRange::Partial(0, 9));
+        assert_eq!(ByteRange::parse(Some("bytes=90-"), 100), ByteRange::Partial(90, 99));
+        assert_eq!(ByteRange::parse(Some("bytes=-10"), 100), ByteRange::Partial(90, 99));
+        assert_eq!(ByteRange::parse(Some("bytes=-500"), 100), ByteRange::Partial(0, 99));
+        assert_eq!(ByteRange::parse(Some("bytes=50-500"), 100), ByteRange::Partial(50, 99));
+        assert_eq!(ByteRange::parse(Some("bytes=100-"), 100), ByteRange::Unsatisfiable);
+        assert_eq!(ByteRange::parse(Some("bytes=0-"), 0), ByteRange::Unsatisfiable);
+        // Несколько частей и мусор отдаются целым файлом
+        assert_eq!(ByteRange::parse(Some("bytes=0-1,5-6"), 100), ByteRange::Full);
+        assert_eq!(ByteRange::parse(Some("bytes=9-0"), 100), ByteRange::Full);
+        assert_eq!(ByteRange::parse(Some("items=0-9"), 100), ByteRange::Full);
+    }
+
+    #[test]
+    fn sessions_are_bound_to_link() {
+        let sessions = DownloadSessions::new();
+        let session = sessions.issue("abc", 1_000);
+        assert!(sessions.verify("abc", &session, 1_000));
+        assert!(!sessions.verify("abd", &session, 1_000));
+        assert!(!sessions.verify("abc", &session, 1_000 + SESSION_TTL.as_secs() as i64));
+        assert!(!DownloadSessions::new().verify("abc", &session, 1_000));
+        assert!(!sessions.verify("abc", "99999999999.00", 1_000));
+    }
+
+    /// Сервер ссылок на базе в памяти с одним файлом и ссылкой на одно скачивание
+    async fn serve_one_download() -> (String, String, tempfile::TempDir) {
+        use teloxide::{adaptors::throttle::Limits, dispatching::dialogue::{InMemStorage, Storage}, prelude::*};
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(crate::storage::LocalStorage::new(dir.path()).await.unwrap());
+        let config = crate::config::BotConfig { share_link_max_downloads: Some(1), ..Default::default() };
+        let dialogues: Arc<crate::types::MyStorage> = InMemStorage::<crate::state::State>::new().erase();
+        let bot = Bot::new("0:test").throttle(Limits::default());
+        let bots = TelegramBot::with_parts(bot, Arc::new(db_pg::MemoryStore::new()), dialogues, storage, None, &config);
+
+        let file = bots.files.save_user_file(1, db_pg::UserRole::Default, "hello.txt", Some("text/plain"), b"hello world".to_vec())
+            .await
+            .unwrap()
+            .unwrap();
+        let link = bots.files.generate_share_link(1, file.id).await.unwrap().unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/share/{}", listener.local_addr().unwrap(), link.token);
+        tokio::spawn(async move { axum::serve(listener, router(bots)).await });
+        (url, link.token, dir)
+    }
+
+    #[tokio::test]
+    async fn ranges_without_session_use_up_the_limit() {
+        let (url, _token, _dir) = serve_one_download().await;
+        let client = reqwest::Client::new();
+
+        let first = client.get(&url).send().await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.bytes().await.unwrap().as_ref(), b"hello world");
+
+        // Лимит исчерпан, докачка без сессии не отдаёт даже хвост файла
+        let tail = client.get(&url).header(header::RANGE, "bytes=1-").send().await.unwrap();
+        assert_eq!(tail.status(), StatusCode::GONE);
+        let full = client.get(&url).send().await.unwrap();
+        assert_eq!(full.status(), StatusCode::GONE);
+    }
+
+    #[tokio::test]
+    async fn head_without_session_respects_the_limit() {
+        let (url, _token, _dir) = serve_one_download().await;
+        let client = reqwest::Client::new();
+
+        let head = client.head(&url).send().await.unwrap();
+        assert_eq!(head.status(), StatusCode::OK);
+        let download = client.get(&url).send().await.unwrap();
+        let cookie = download.headers()[header::SET_COOKIE].to_str().unwrap().split(';').next().unwrap().to_string();
+
+        // Имя и размер файла по исчерпанной ссылке не раскрываются
+        let head = client.head(&url).send().await.unwrap();
+        assert_eq!(head.status(), StatusCode::GONE);
+        assert!(!head.headers().contains_key(header::CONTENT_DISPOSITION));
+        let head = client.head(&url).header(header::COOKIE, &cookie).send().await.unwrap();
+        assert_eq!(head.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn probe_and_download_count_once() {
+        let (url, _token, _dir) = serve_one_download().await;
+        let client = reqwest::Client::new();
+
+        let probe = client.get(&url).header(header::RANGE, "bytes=0-0").send().await.unwrap();
+        assert_eq!(probe.status(), StatusCode::PARTIAL_CONTENT);
+        let cookie = probe.headers()[header::SET_COOKIE].to_str().unwrap().split(';').next().unwrap().to_string();
+        assert_eq!(probe.bytes().await.unwrap().as_ref(), b"h");
+
+        // Скачивание в той же сессии не расходует единственное скачивание ещё раз
+        let download = client.get(&url).header(header::COOKIE, &cookie).send().await.unwrap();
+        assert_eq!(download.status(), StatusCode::OK);
+        assert!(!download.headers().contains_key(header::SET_COOKIE));
+        assert_eq!(download.bytes().await.unwrap().as_ref(), b"hello world");
+        let resumed = client.get(&url).header(header::COOKIE, &cookie).header(header::RANGE, "bytes=6-").send().await.unwrap();
+        assert_eq!(resumed.bytes().await.unwrap().as_ref(), b"world");
+
+        let other = client.get(&url).send().await.unwrap();
+        assert_eq!(other.status(), StatusCode::GONE);
+    }
+
+    #[test]
+    fn disposition_keeps_unicode_names() {
+        assert_eq!(content_disposition("report.pdf"), "attachment; filename=\"report.pdf\"; filename*=UTF-8''report.pdf");
+        assert_eq!(
+            content_disposition("Отчёт \"Q1\".pdf"),
+            "attachment; filename=\"_____ _Q1_.pdf\"; filename*=UTF-8''%D0%9E%D1%82%D1%87%D1%91%D1%82%20%22Q1%22.pdf"
+        );
+    }
+}
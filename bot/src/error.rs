@@ -1,20 +1,94 @@
-use std::io;
+use std::error::Error;
 
+use db_pg::DbError;
+use localization::{t, Lang};
+use logging::log_error;
+use teloxide::{prelude::Requester, types::{ChatId, User}, RequestError};
 use thiserror::Error;
 
+use crate::{storage::StorageError, types::HandlerResult, TelegramBot};
+
+/// Ошибка обработчика апдейта, по ней выбирается сообщение пользователю
 #[derive(Error, Debug)]
 pub enum BotError {
-    #[error("data store disconnected")]
-    Disconnect(#[from] io::Error),
-    #[error("the data for key `{0}` is not available")]
-    Redaction(String),
-    #[error("invalid header (expected {expected:?}, found {found:?})")]
-    InvalidHeader {
-        expected: String,
-        found: String,
-    },
-    #[error("None option while return message from data")]
-    UnvialabelFromData,
-    #[error("unknown data store error")]
-    Unknown,
-}
\ No newline at end of file
+    #[error(transparent)]
+    Db(#[from] DbError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Telegram(#[from] RequestError),
+    #[error(transparent)]
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl BotError {
+    /// Восстанавливает тип ошибки из `HandlerResult`
+    pub fn from_handler(error: Box<dyn Error + Send + Sync>) -> Self {
+        let error = match error.downcast::<DbError>() {
+            Ok(e) => return Self::Db(*e),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<StorageError>() {
+            Ok(e) => return Self::Storage(*e),
+            Err(error) => error,
+        };
+        match error.downcast::<RequestError>() {
+            Ok(e) => Self::Telegram(*e),
+            Err(error) => Self::Other(error),
+        }
+    }
+
+    /// Ключ сообщения для пользователя, None если сообщать нечего
+    pub fn message_key(&self) -> Option<&'static str> {
+        match self {
+            Self::Db(DbError::NotFound) => Some("errors.not_found"),
+            Self::Db(DbError::Constraint { .. }) => Some("errors.conflict"),
+            Self::Db(e) if e.is_transient() => Some("errors.unavailable"),
+            Self::Storage(_) => Some("errors.storage"),
+            // Ответ пошёл бы через тот же недоступный Telegram
+            Self::Telegram(_) => None,
+            Self::Db(_) | Self::Other(_) => Some("errors.internal"),
+        }
+    }
+}
+
+/// Логирует ошибку обработчика и сообщает о ней пользователю.
+/// Ошибка возвращается дальше, чтобы её видел `replay`, диспетчер её не логирует повторно
+pub async fn report(bots: &TelegramBot, chat_id: ChatId, user: Option<&User>, result: HandlerResult) -> HandlerResult {
+    let Err(e) = result else {
+        return Ok(());
+    };
+    let error = BotError::from_handler(e);
+    log_error!("Ошибка обработки апдейта в чате {}: {}", chat_id.0, error);
+
+    if let Some(key) = error.message_key() {
+        let lang = match user {
+            Some(user) => bots.lang(user).await,
+            None => Lang::default(),
+        };
+        if let Err(e) = bots.bot.send_message(chat_id, t(lang, key)).await {
+            log_error!("Не удалось сообщить об ошибке в чат {}: {}", chat_id.0, e);
+        }
+    }
+    Err(Box::new(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handler_errors_keep_their_type() {
+        let error: Box<dyn Error + Send + Sync> = Box::new(DbError::NotFound);
+        assert_eq!(BotError::from_handler(error).message_key(), Some("errors.not_found"));
+
+        let error: Box<dyn Error + Send + Sync> = Box::new(DbError::from(sqlx::Error::PoolTimedOut));
+        assert_eq!(BotError::from_handler(error).message_key(), Some("errors.unavailable"));
+
+        let error: Box<dyn Error + Send + Sync> = Box::new(StorageError::InvalidKey("..".to_string()));
+        assert_eq!(BotError::from_handler(error).message_key(), Some("errors.storage"));
+
+        let error: Box<dyn Error + Send + Sync> = "no such file".into();
+        assert_eq!(BotError::from_handler(error).message_key(), Some("errors.internal"));
+    }
+}
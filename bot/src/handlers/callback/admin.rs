@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use db_pg::{Message, MessageStatus};
+use localization::{t, t_args, t_plural, Lang};
+use teloxide::{payloads::{EditMessageTextSetters, SendMessageSetters}, prelude::Requester, types::{ChatId, ParseMode}, utils::markdown::escape};
+use crate::{callback_data::CallbackData, i18n::status_label, handlers::callback::{CallbackContext, CallbackHandler}, keyboards::admin::{admin_answer_cancel, admin_request_actions, admin_requests}, permissions::{allows, Roles, ADMINS}, state::State, types::HandlerResult, TelegramBot};
+
+/// Обращения, которые ждут действий администратора
+pub const OPEN_STATUSES: [MessageStatus; 2] = [MessageStatus::Pending, MessageStatus::Accepted];
+
+pub struct AdminRequestsPage;
+pub struct AdminRequestView;
+pub struct AdminAnswerRequest;
+pub struct AdminSetStatus {
+    pub status: MessageStatus,
+}
+
+pub async fn is_admin(bots: &TelegramBot, telegram_id: i64) -> HandlerResult<bool> {
+    Ok(allows(ADMINS, bots.role(telegram_id).await?))
+}
+
+pub fn admin_requests_text(lang: Lang, messages: &[Message]) -> String {
+    let pending = messages.iter().filter(|m| m.status == MessageStatus::Pending).count() as u64;
+    let accepted = messages.iter().filter(|m| m.status == MessageStatus::Accepted).count() as u64;
+    format!(
+        "{}\n{}\n{}",
+        t(lang, "admin.requests_title"),
+        t_plural(lang, "admin.pending", pending, &[]),
+        t_plural(lang, "admin.accepted", accepted, &[])
+    )
+}
+
+pub fn admin_request_text(lang: Lang, message: &Message) -> String {
+    let mut text = t_args(lang, "admin.card", &[
+        ("uid", &escape(&message.id.to_string())),
+        ("from", &message.telegram_id.to_string()),
+        ("time", &escape(&message.created_at.to_string())),
+        ("status", &escape(&status_label(lang, message.status))),
+        ("text", &escape(&message.text)),
+    ]);
+    if let Some(answer) = &message.answer {
+        text.push_str(&t_args(lang, "requests.answer", &[("answer", &escape(answer))]));
+    }
+    text
+}
+
+/// Отправляет новым сообщением список открытых обращений
+pub async fn send_admin_requests(bots: &Arc<TelegramBot>, chat_id: ChatId, lang: Lang, page: usize) -> HandlerResult {
+    let messages = bots.db.get_messages_by_statuses(&OPEN_STATUSES).await?;
+    bots.bot.send_message(chat_id, admin_requests_text(lang, &messages))
+        .parse_mode(ParseMode::MarkdownV2)
+        .reply_markup(admin_requests(&messages, page))
+        .await?;
+    Ok(())
+}
+
+async fn current_page(ctx: &CallbackContext) -> HandlerResult<usize> {
+    Ok(match ctx.dialogue.get().await? {
+        Some(State::AdminViewingRequests { current_page }) => current_page,
+        Some(State::AdminAnswering { back_page, .. }) => back_page,
+        _ => 0,
+    })
+}
+
+async fn show_requests_page(ctx: &CallbackContext, page: usize) -> HandlerResult {
+    let messages = ctx.bots.db.get_messages_by_statuses(&OPEN_STATUSES).await?;
+    ctx.dialogue.update(State::AdminViewingRequests { current_page: page }).await?;
+
+    if let Some(msg) = ctx.query.regular_message() {
+        ctx.bots.bot.edit_message_text(msg.chat.id, msg.id, admin_requests_text(ctx.lang, &messages))
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(admin_requests(&messages, page))
+            .await?;
+    }
+    Ok(())
+}
+
+async fn show_request(ctx: &CallbackContext, message: &Message) -> HandlerResult {
+    let back_page = current_page(ctx).await?;
+    ctx.dialogue.update(State::AdminViewingRequests { current_page: back_page }).await?;
+
+    if let Some(msg) = ctx.query.regular_message() {
+        ctx.bots.bot.edit_message_text(msg.chat.id, msg.id, admin_request_text(ctx.lang, message))
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(admin_request_actions(ctx.lang, message, back_page))
+            .await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl CallbackHandler for AdminRequestsPage {
+    fn required_roles(&self) -> Roles {
+        ADMINS
+    }
+
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let CallbackData::AdminPage(page) = ctx.data else {
+            return Ok(());
+        };
+
+        show_requests_page(ctx, page).await
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for AdminRequestView {
+    fn required_roles(&self) -> Roles {
+        ADMINS
+    }
+
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let CallbackData::AdminMessage(id) = ctx.data else {
+            return Ok(());
+        };
+
+        match ctx.bots.db.get_message_by_id(id).await? {
+            Some(message) => show_request(ctx, &message).await,
+            None => show_requests_page(ctx, current_page(ctx).await?).await,
+        }
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for AdminSetStatus {
+    fn required_roles(&self) -> Roles {
+        ADMINS
+    }
+
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let (CallbackData::AdminAccept(id) | CallbackData::AdminReject(id)) = ctx.data else {
+            return Ok(());
+        };
+
+        // Ответ, если он уже был, остаётся у обращения
+        ctx.bots.db.set_message_status(id, self.status).await?;
+
+        // Принятое обращение остаётся открытым, отклонённое пропадает из списка
+        match ctx.bots.db.get_message_by_id(id).await? {
+            Some(message) if self.status == MessageStatus::Accepted => show_request(ctx, &message).await,
+            _ => show_requests_page(ctx, current_page(ctx).await?).await,
+        }
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for AdminAnswerRequest {
+    fn required_roles(&self) -> Roles {
+        ADMINS
+    }
+
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let CallbackData::AdminAnswer(id) = ctx.data else {
+            return Ok(());
+        };
+        let Some(message) = ctx.bots.db.get_message_by_id(id).await? else {
+            return Ok(());
+        };
+
+        let back_page = current_page(ctx).await?;
+        ctx.dialogue.update(State::AdminAnswering { message_id: id, back_page }).await?;
+
+        if let Some(msg) = ctx.query.regular_message() {
+            ctx.bots.bot.edit_message_text(msg.chat.id, msg.id, format!("{}{}", admin_request_text(ctx.lang, &message), t(ctx.lang, "admin.write_answer")))
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_markup(admin_answer_cancel(ctx.lang, &message))
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
 use async_trait::async_trait;
-use teloxide::{payloads::SendMessageSetters, prelude::Requester, types::{CallbackQuery, ParseMode}, utils::markdown::escape};
+use localization::{t, t_args};
+use teloxide::{payloads::SendMessageSetters, prelude::Requester, types::ParseMode, utils::markdown::escape};
 
-use crate::{handlers::callback::{CallbackContext, CallbackHandler}, keyboards::faqkb::{faq, profits}, state::State, types::{HandlerResult, MyDialogue}, TelegramBot};
+use crate::{handlers::callback::{CallbackContext, CallbackHandler}, keyboards::faqkb::{faq, profits}, state::State, types::HandlerResult};
 
 pub struct FaqSend;
 pub struct Q1;
@@ -12,16 +13,13 @@ impl CallbackHandler for FaqSend {
         ctx.bots.bot.delete_message(ctx.query.from.id, ctx.query.regular_message().unwrap().id).await?;
         
         let mut first_name = escape(&ctx.query.from.first_name);
-        if first_name != "" {
+        if !first_name.is_empty() {
             first_name.push(' ');
         }
-        let text = format!(
-            "*FAQ ℹ️*\n\n*{}*Если вы не нашли ответ на свой вопрос, то можете написать прямо сюда в чат 💭 и *Axiobot* 🤖 поможет ответить на ваш вопрос 😊",
-            first_name
-        );
+        let text = t_args(ctx.lang, "faq.text", &[("name", &first_name)]);
         
         ctx.bots.bot.send_message(ctx.query.from.id, text)
-            .reply_markup(faq())
+            .reply_markup(faq(ctx.lang))
             .parse_mode(ParseMode::MarkdownV2)
             .await?;
             
@@ -35,10 +33,7 @@ impl CallbackHandler for FaqSend {
 impl CallbackHandler for Q1 {
     async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
         ctx.bots.bot.delete_message(ctx.query.from.id, ctx.query.regular_message().unwrap().id).await?;
-        let text = format!(
-            "*🤔 Чем полезен этот бот?*\n{}",
-            escape("Да всем...")
-        );
+        let text = t(ctx.lang, "faq.profits");
         ctx.bots.bot.send_message(ctx.query.from.id, text)
             .reply_markup(profits())
             .parse_mode(ParseMode::MarkdownV2)
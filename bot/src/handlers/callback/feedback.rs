@@ -0,0 +1,62 @@
+use async_trait::async_trait;
+use localization::t_args;
+use teloxide::{payloads::{EditMessageReplyMarkupSetters, SendMessageSetters}, prelude::Requester, types::ParseMode, utils::markdown::escape};
+use crate::{callback_data::CallbackData, handlers::callback::{CallbackContext, CallbackHandler}, keyboards::faqkb::{feedback_escalate, feedback_thanks}, types::HandlerResult};
+
+/// Оценка ответа Qortex AI: `CallbackData::FeedbackYes` / `CallbackData::FeedbackNo`
+pub struct FeedbackHandler {
+    pub helpful: bool,
+}
+
+/// Передача вопроса администратору после отрицательной оценки: `CallbackData::FeedbackEscalate`
+pub struct EscalateQuestion;
+
+#[async_trait]
+impl CallbackHandler for FeedbackHandler {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let (CallbackData::FeedbackYes(answer_id) | CallbackData::FeedbackNo(answer_id)) = ctx.data else {
+            return Ok(());
+        };
+
+        let user_id: i64 = ctx.query.from.id.0.try_into().unwrap();
+        if ctx.bots.db.rate_ai_answer(answer_id, user_id, self.helpful).await?.is_none() {
+            return Ok(());
+        }
+
+        let keyboard = if self.helpful { feedback_thanks(ctx.lang) } else { feedback_escalate(ctx.lang, answer_id) };
+        if let Some(msg) = ctx.query.regular_message() {
+            ctx.bots.bot.edit_message_reply_markup(msg.chat.id, msg.id)
+                .reply_markup(keyboard)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for EscalateQuestion {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let CallbackData::FeedbackEscalate(answer_id) = ctx.data else {
+            return Ok(());
+        };
+
+        let user_id: i64 = ctx.query.from.id.0.try_into().unwrap();
+        // Повторное нажатие, даже одновременное, не создаёт ещё одно обращение
+        let Some(message_uuid) = ctx.bots.db.escalate_ai_feedback(answer_id, user_id).await? else {
+            return Ok(());
+        };
+
+        if let Some(msg) = ctx.query.regular_message() {
+            ctx.bots.bot.edit_message_reply_markup(msg.chat.id, msg.id)
+                .reply_markup(feedback_thanks(ctx.lang))
+                .await?;
+        }
+
+        ctx.bots.bot.send_message(ctx.query.from.id, t_args(ctx.lang, "feedback.escalated", &[("uid", &escape(&message_uuid.to_string()))]))
+            .parse_mode(ParseMode::MarkdownV2)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use async_trait::async_trait;
+use db_pg::{ShareLink, StoredFile};
+use localization::{t_args, t_plural, Lang};
+use logging::log_info;
+use teloxide::{payloads::{EditMessageTextSetters, SendMessageSetters}, prelude::Requester, types::ParseMode, utils::markdown::escape};
+
+use crate::{callback_data::CallbackData, handlers::{callback::{CallbackContext, CallbackHandler}, file_manager::{share_link, web_link}}, keyboards::{file_sharing::{file_actions, files}, requests::{total_pages, ITEMS_PER_PAGE}}, types::HandlerResult};
+
+pub struct FilesMenu;
+pub struct FileView;
+pub struct FileShare;
+pub struct FileDelete;
+
+fn user_id(ctx: &CallbackContext) -> i64 {
+    ctx.query.from.id.0.try_into().unwrap()
+}
+
+/// Размер файла в байтах, КБ или МБ
+pub fn format_size(size: i64) -> String {
+    const KB: f64 = 1024.0;
+    let size = size as f64;
+    if size < KB {
+        format!("{} B", size)
+    } else if size < KB * KB {
+        format!("{:.1} KB", size / KB)
+    } else {
+        format!("{:.1} MB", size / KB / KB)
+    }
+}
+
+fn file_text(lang: Lang, file: &StoredFile) -> String {
+    t_args(lang, "files.file", &[
+        ("name", &escape(&file.original_name)),
+        ("size", &escape(&format_size(file.size))),
+        ("date", &escape(&file.created_at.format("%d.%m.%Y %H:%M").to_string())),
+    ])
+}
+
+fn link_text(lang: Lang, file: &StoredFile, link: &ShareLink, url: &str, web_url: Option<&str>) -> String {
+    let mut limits = String::new();
+    if let Some(expires_at) = link.expires_at {
+        limits.push_str(&t_args(lang, "files.link_expires", &[("date", &escape(&expires_at.format("%d.%m.%Y %H:%M UTC").to_string()))]));
+    }
+    if let Some(max) = link.max_downloads {
+        limits.push_str(&t_args(lang, "files.link_max_downloads", &[("max", &max.to_string())]));
+    }
+    let web = web_url
+        .map(|web_url| t_args(lang, "files.link_web", &[("link", &escape(web_url))]))
+        .unwrap_or_default();
+    t_args(lang, "files.link", &[("name", &escape(&file.original_name)), ("link", &escape(url)), ("limits", &limits), ("web", &web)])
+}
+
+/// Показывает страницу файлов пользователя вместо текущего сообщения
+async fn show_files(ctx: &CallbackContext, page: usize) -> HandlerResult {
+    let user_id = user_id(ctx);
+    let usage = ctx.bots.db.get_file_usage(user_id).await?;
+    let total_pages = total_pages(usage.files as usize);
+    let page = page.min(total_pages - 1);
+    let page_files = ctx.bots.db.get_user_files(user_id, (page * ITEMS_PER_PAGE) as i64, ITEMS_PER_PAGE as i64).await?;
+
+    // Занятое место показываем только тем, у кого есть квота
+    let used = match ctx.bots.files.quota(ctx.bots.role(user_id).await?) {
+        Some(quota) => t_args(ctx.lang, "files.usage", &[
+            ("used", &escape(&format_size(usage.bytes))),
+            ("max", &escape(&format_size(quota.max_bytes))),
+        ]),
+        None => String::new(),
+    };
+    let text = t_args(ctx.lang, "files.text", &[
+        ("count", &t_plural(ctx.lang, "files.count", usage.files as u64, &[])),
+        ("usage", &used),
+    ]);
+    if let Some(msg) = ctx.query.regular_message() {
+        ctx.bots.bot.edit_message_text(msg.chat.id, msg.id, text)
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(files(&page_files, page, total_pages))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Файл из кнопки, если он всё ещё есть и принадлежит пользователю
+async fn own_file(ctx: &CallbackContext, file_id: uuid::Uuid) -> HandlerResult<Option<StoredFile>> {
+    let file = ctx.bots.db.get_file(file_id).await?;
+    Ok(file.filter(|file| file.owner_id == user_id(ctx)))
+}
+
+#[async_trait]
+impl CallbackHandler for FilesMenu {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let page = match ctx.data {
+            CallbackData::FilesPage(page) => page,
+            _ => 0,
+        };
+        show_files(ctx, page).await
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for FileView {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let CallbackData::File(file_id) = ctx.data else {
+            return Ok(());
+        };
+        let Some(file) = own_file(ctx, file_id).await? else {
+            return show_files(ctx, 0).await;
+        };
+
+        if let Some(msg) = ctx.query.regular_message() {
+            ctx.bots.bot.edit_message_text(msg.chat.id, msg.id, file_text(ctx.lang, &file))
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_markup(file_actions(ctx.lang, file.id))
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for FileShare {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let CallbackData::FileShare(file_id) = ctx.data else {
+            return Ok(());
+        };
+        let Some(file) = own_file(ctx, file_id).await? else {
+            return show_files(ctx, 0).await;
+        };
+        let Some(link) = ctx.bots.files.generate_share_link(user_id(ctx), file.id).await? else {
+            return show_files(ctx, 0).await;
+        };
+
+        let url = share_link(ctx.bots.username().await?, &link.token);
+        // Ссылка для браузера, только если HTTP сервер доступен снаружи
+        let web_url = ctx.bots.config.public_base_url.as_deref().map(|base| web_link(base, &link.token));
+        // Отдельным сообщением, чтобы ссылку было удобно переслать
+        ctx.bots.bot.send_message(ctx.query.from.id, link_text(ctx.lang, &file, &link, &url, web_url.as_deref()))
+            .parse_mode(ParseMode::MarkdownV2)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for FileDelete {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let CallbackData::FileDelete(file_id) = ctx.data else {
+            return Ok(());
+        };
+        if ctx.bots.files.delete_user_file(user_id(ctx), file_id).await? {
+            log_info!("Пользователь {} удалил файл {}", user_id(ctx), file_id);
+        }
+        show_files(ctx, 0).await
+    }
+}
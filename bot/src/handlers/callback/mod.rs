@@ -1,49 +1,72 @@
+pub mod admin;
 pub mod faq;
+pub mod feedback;
+pub mod files;
 pub mod requests;
+pub mod settings;
+pub mod wallet;
 
 use std::{collections::HashMap, sync::Arc};
 
+use localization::{t, t_args, Lang};
 use logging::log_info;
-use teloxide::{payloads::{SendMessageSetters, SendPhotoSetters}, prelude::Requester, types::{CallbackQuery, InputFile, ParseMode}, utils::markdown::escape};
+use teloxide::{payloads::{AnswerCallbackQuerySetters, SendMessageSetters}, prelude::Requester, types::{CallbackQuery, InputFile, ParseMode}, utils::markdown::escape};
 use async_trait::async_trait;
-use crate::{handlers::callback::{faq::{FaqSend, Q1}, requests::{AllMessages, BackToPageHandler, MessageHandler, MyRequests}}, keyboards::{faqkb::faq, menu::menu}, state::State, types::{HandlerResult, MyDialogue}, TelegramBot};
+use db_pg::MessageStatus;
+use crate::{callback_data::{CallbackData, CallbackKind}, handlers::callback::{admin::{AdminAnswerRequest, AdminRequestView, AdminRequestsPage, AdminSetStatus}, faq::{FaqSend, Q1}, feedback::{EscalateQuestion, FeedbackHandler}, files::{FileDelete, FileShare, FileView, FilesMenu}, requests::{AllMessages, BackToPageHandler, MessageHandler, MyRequests}, settings::{LanguageMenu, SetLanguage, SetStyle, SettingsMenu, StyleMenu, ToggleNotifications}, wallet::WalletScreen}, keyboards::menu::menu, permissions::{Roles, EVERYONE}, types::{HandlerResult, MyDialogue}, TelegramBot};
 
 pub struct CallbackContext {
     pub bots: Arc<TelegramBot>,
     pub dialogue: MyDialogue,
     pub query: CallbackQuery,
+    /// Разобранный `query.data`
+    pub data: CallbackData,
+    pub lang: Lang,
 }
 
 #[async_trait]
 pub trait CallbackHandler: Send + Sync {
     async fn handle(&self, ctx: &CallbackContext) -> HandlerResult;
+
+    /// Роли, которым доступна кнопка, проверяются до `handle`
+    fn required_roles(&self) -> Roles {
+        EVERYONE
+    }
 }
 
-pub struct CallbackHandlerFactory {
-    handlers: HashMap<String, Arc<dyn CallbackHandler + Send + Sync>>,
+/// Обработчики кнопок по варианту `CallbackData`
+pub struct CallbackRegistry {
+    handlers: HashMap<CallbackKind, Arc<dyn CallbackHandler + Send + Sync>>,
 }
 
 pub struct BackToMenu;
 
+/// Кнопки без действия, например номер текущей страницы
+pub struct Noop;
+
+#[async_trait]
+impl CallbackHandler for Noop {
+    async fn handle(&self, _ctx: &CallbackContext) -> HandlerResult {
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl CallbackHandler for BackToMenu {
     async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
         ctx.bots.bot.delete_message(ctx.query.from.id, ctx.query.message.clone().unwrap().regular_message().unwrap().id).await?;
 
         let path = concat!(env!("CARGO_MANIFEST_DIR"), "/static/aw_logo.png");
-        let image = InputFile::file(path);
-        let text = format!(
-            "*{}* привет\nМы команда разработчиков *Axiowel*, занимаемся разработкой эффективного и отказоустойчевого программного обеспечения основоного на ИИ модели *Axiowel AI*\n\nНаш бот достататочно функционален, можете подробнее узнать в /faq",
-            escape(&ctx.query.from.first_name)
-        );
+        let _image = InputFile::file(path);
+        let text = t_args(ctx.lang, "start.greeting", &[("name", &escape(&ctx.query.from.first_name))]);
         // ctx.bots.bot.send_photo(ctx.query.from.id, image)
         //     .caption(text)
-        //     .reply_markup(menu())
+        //     .reply_markup(menu(ctx.lang))
         //     .parse_mode(ParseMode::MarkdownV2)
         //     .await?;
 
         ctx.bots.bot.send_message(ctx.query.from.id, text)
-            .reply_markup(menu())
+            .reply_markup(menu(ctx.lang))
             .parse_mode(ParseMode::MarkdownV2)
             .await?;
 
@@ -51,51 +74,74 @@ impl CallbackHandler for BackToMenu {
     }
 }
 
-impl CallbackHandlerFactory {
+impl CallbackRegistry {
     pub fn new() -> Self {
-        let mut handlers = HashMap::new();
-        
-        handlers.insert(
-            "faq".to_string(),
-            Arc::new(FaqSend) as Arc<dyn CallbackHandler + Send + Sync>
-        );
-        handlers.insert(
-            "profits".to_string(),
-            Arc::new(Q1) as Arc<dyn CallbackHandler + Send + Sync>
-        );
-        handlers.insert(
-            "back_to_faq".to_string(),
-            Arc::new(FaqSend) as Arc<dyn CallbackHandler + Send + Sync>
-        );
-        handlers.insert(
-            "all_requests".to_string(),
-            Arc::new(AllMessages) as Arc<dyn CallbackHandler + Send + Sync>
-        );
-        handlers.insert(
-            "my_requests".to_string(),
-            Arc::new(MyRequests) as Arc<dyn CallbackHandler + Send + Sync>
-        );
-        handlers.insert(
-            "back_to_menu".to_string(),
-            Arc::new(BackToMenu) as Arc<dyn CallbackHandler + Send + Sync>
-        );
-        handlers.insert(
-            "msg_".to_string(), // Общая префикс-обработка для сообщений
-            Arc::new(MessageHandler) as Arc<dyn CallbackHandler + Send + Sync>
-        );
-
-        handlers.insert(
-            "back_to_page_".to_string(), // Общая префикс-обработка для кнопок возврата
-            Arc::new(BackToPageHandler) as Arc<dyn CallbackHandler + Send + Sync>
-        );
-        // handlers.insert("back_to_faq".to_string(), Arc::new(BackToFaqHandler));
-        // Добавляем другие обработчики
-        
-        Self { handlers }
+        let mut registry = Self { handlers: HashMap::new() };
+
+        registry.register(CallbackKind::Faq, FaqSend);
+        registry.register(CallbackKind::BackToFaq, FaqSend);
+        registry.register(CallbackKind::Profits, Q1);
+        registry.register(CallbackKind::BackToMenu, BackToMenu);
+        registry.register(CallbackKind::CurrentPage, Noop);
+        registry.register(CallbackKind::FeedbackThanks, Noop);
+
+        // Настройки
+        registry.register(CallbackKind::Settings, SettingsMenu);
+        registry.register(CallbackKind::SettingsLanguage, LanguageMenu);
+        registry.register(CallbackKind::SettingsStyle, StyleMenu);
+        registry.register(CallbackKind::SetLanguage, SetLanguage);
+        registry.register(CallbackKind::SetStyle, SetStyle);
+        registry.register(CallbackKind::ToggleNotifications, ToggleNotifications);
+        registry.register(CallbackKind::Wallet, WalletScreen);
+
+        // Обменник
+        registry.register(CallbackKind::FileSharing, FilesMenu);
+        registry.register(CallbackKind::FilesPage, FilesMenu);
+        registry.register(CallbackKind::File, FileView);
+        registry.register(CallbackKind::FileShare, FileShare);
+        registry.register(CallbackKind::FileDelete, FileDelete);
+
+        // Обращения пользователя
+        registry.register(CallbackKind::MyRequests, MyRequests);
+        registry.register(CallbackKind::AllRequests, AllMessages);
+        registry.register(CallbackKind::AnsweredRequests, AllMessages);
+        registry.register(CallbackKind::AcceptedRequests, AllMessages);
+        registry.register(CallbackKind::Page, AllMessages);
+        registry.register(CallbackKind::Message, MessageHandler);
+        registry.register(CallbackKind::BackToPage, BackToPageHandler);
+
+        // Оценка ответа AI
+        registry.register(CallbackKind::FeedbackYes, FeedbackHandler { helpful: true });
+        registry.register(CallbackKind::FeedbackNo, FeedbackHandler { helpful: false });
+        registry.register(CallbackKind::FeedbackEscalate, EscalateQuestion);
+
+        // Модерация обращений
+        registry.register(CallbackKind::AdminPage, AdminRequestsPage);
+        registry.register(CallbackKind::AdminMessage, AdminRequestView);
+        registry.register(CallbackKind::AdminAccept, AdminSetStatus { status: MessageStatus::Accepted });
+        registry.register(CallbackKind::AdminReject, AdminSetStatus { status: MessageStatus::Rejected });
+        registry.register(CallbackKind::AdminAnswer, AdminAnswerRequest);
+
+        registry
     }
-    
-    pub fn get_handler(&self, callback_data: &str) -> Option<&Arc<dyn CallbackHandler + Send + Sync>> {
-        self.handlers.get(callback_data)
+
+    pub fn register(&mut self, kind: CallbackKind, handler: impl CallbackHandler + 'static) {
+        self.handlers.insert(kind, Arc::new(handler));
+    }
+
+    pub fn get_handler(&self, data: &CallbackData) -> Option<&Arc<dyn CallbackHandler + Send + Sync>> {
+        self.handlers.get(&data.kind())
+    }
+
+    /// Роли для кнопки, кнопки без обработчика доступны всем
+    pub fn required_roles(&self, data: &CallbackData) -> Roles {
+        self.get_handler(data).map_or(EVERYONE, |handler| handler.required_roles())
+    }
+}
+
+impl Default for CallbackRegistry {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -105,43 +151,36 @@ pub async fn callback_handler(
     q: CallbackQuery,
 ) -> HandlerResult {
     let bot = &bots.bot;
+    let raw = q.data.clone().unwrap_or_default();
+    let lang = bots.lang(&q.from).await;
+
+    // Кнопки старых версий бота или с повреждёнными данными
+    let data = match raw.parse::<CallbackData>() {
+        Ok(data) => data,
+        Err(e) => {
+            log_info!("Unknown callback from {} with data {:?}: {}", q.from.id.0, raw, e);
+            bot.answer_callback_query(q.id.clone())
+                .text(t(lang, "callback.expired"))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let Some(handler) = bots.callback_handlers.get_handler(&data) else {
+        bot.answer_callback_query(q.id.clone())
+            .text(t(lang, "callback.in_progress"))
+            .await?;
+        return Ok(());
+    };
+
     bot.answer_callback_query(q.id.clone()).await?;
 
     let ctx = CallbackContext {
         bots: bots.clone(),
         dialogue,
         query: q,
+        data,
+        lang,
     };
-
-    if let Some(ref data) = ctx.query.data {
-        if data.starts_with("page_") {
-            if let Some(handler) = bots.callback_handlers.get_handler("all_requests") {
-                handler.handle(&ctx).await?;
-                return Ok(());
-            }
-        }
-
-        // Обработка отдельных сообщений
-        if data.starts_with("msg_") {
-            if let Some(handler) = bots.callback_handlers.get_handler("msg_") {
-                handler.handle(&ctx).await?;
-                return Ok(());
-            }
-        }
-
-        // Обработка возврата
-        if data.starts_with("back_to_page_") {
-            if let Some(handler) = bots.callback_handlers.get_handler("back_to_page_") {
-                handler.handle(&ctx).await?;
-                return Ok(());
-            }
-        }
-
-        match bots.callback_handlers.get_handler(&data) {
-            Some(handler) => handler.handle(&ctx).await?,
-            None => log_info!("Unknown callback from {} with data: {}", ctx.query.from.id.0, data),
-        }
-    }
-
-    Ok(())
-}
\ No newline at end of file
+    handler.handle(&ctx).await
+}
@@ -1,22 +1,70 @@
 use async_trait::async_trait;
-use teloxide::{dispatching::dialogue, payloads::{EditMessageReplyMarkupSetters, EditMessageTextSetters, SendMessageSetters}, prelude::Requester, types::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup, ParseMode}, utils::markdown::escape};
-use uuid::Uuid;
+use db_pg::{MessageStatus, PageCursor};
+use localization::{t, t_args, Lang};
+use teloxide::{payloads::{EditMessageTextSetters, SendMessageSetters}, prelude::Requester, types::{InlineKeyboardMarkup, ParseMode}, utils::markdown::escape};
 
-use crate::{handlers::callback::{CallbackContext, CallbackHandler}, keyboards::{faqkb::{faq, profits}, requests::{all_messages, create_navigation_row, history, ITEMS_PER_PAGE}}, state::State, types::{HandlerResult, MyDialogue}, TelegramBot};
+use crate::{callback_data::CallbackData, i18n::status_label, handlers::callback::{CallbackContext, CallbackHandler}, keyboards::requests::{all_messages, history, total_pages, ITEMS_PER_PAGE}, state::State, types::HandlerResult};
 
 pub struct MyRequests;
 pub struct AllMessages;
 pub struct MessageHandler;
 pub struct BackToPageHandler;
 
+fn list_title(lang: Lang, filter: Option<MessageStatus>, is_empty: bool) -> String {
+    let title = t(lang, match filter {
+        None => "requests.title.all",
+        Some(MessageStatus::Answered) => "requests.title.answered",
+        Some(MessageStatus::Accepted) => "requests.title.accepted",
+        Some(MessageStatus::Pending) => "requests.title.pending",
+        Some(MessageStatus::Rejected) => "requests.title.rejected",
+    });
+    if is_empty {
+        format!("{}\n{}", title, t(lang, "requests.empty"))
+    } else {
+        title
+    }
+}
+
+/// Загружает одну страницу обращений из БД, сохраняет её позицию в состоянии и показывает список
+async fn show_page(ctx: &CallbackContext, filter: Option<MessageStatus>, page: usize, cursor: PageCursor) -> HandlerResult {
+    let user_id: i64 = ctx.query.from.id.0.try_into().unwrap();
+    let limit = ITEMS_PER_PAGE as i64;
+
+    let mut page = page;
+    let mut result = ctx.bots.db.get_user_messages_page(user_id, filter, cursor, limit).await?;
+    // Страница могла опустеть (обращения удалены), начинаем сначала
+    if result.messages.is_empty() && cursor != PageCursor::First {
+        page = 0;
+        result = ctx.bots.db.get_user_messages_page(user_id, filter, PageCursor::First, limit).await?;
+    }
+
+    let total_pages = total_pages(result.total as usize);
+    let page = page.min(total_pages - 1);
+    ctx.dialogue.update(State::ViewingMessages {
+        current_page: page,
+        filter,
+        first: result.first_cursor(),
+        last: result.last_cursor(),
+    }).await?;
+
+    if let Some(msg) = ctx.query.regular_message() {
+        ctx.bots.bot.edit_message_text(msg.chat.id, msg.id, list_title(ctx.lang, filter, result.messages.is_empty()))
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(all_messages(&result.messages, page, total_pages))
+            .await?;
+    }
+
+    Ok(())
+}
+
 #[async_trait]
 impl CallbackHandler for MyRequests {
     async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
         ctx.bots.bot.delete_message(ctx.query.from.id, ctx.query.regular_message().unwrap().id).await?;
 
-        ctx.bots.bot.send_message(ctx.query.from.id, "*Выберете:*")
+        ctx.bots.bot.send_message(ctx.query.from.id, t(ctx.lang, "requests.choose"))
             .parse_mode(ParseMode::MarkdownV2)
-            .reply_markup(history())
+            .reply_markup(history(ctx.lang))
             .await?;
         Ok(())
     }
@@ -25,96 +73,81 @@ impl CallbackHandler for MyRequests {
 #[async_trait]
 impl CallbackHandler for AllMessages {
     async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
-        let data = ctx.query.data.as_ref().unwrap();
-        let mut state = ctx.dialogue.get().await?.unwrap_or_default();
-
         // Обработка действий
-        match data.as_ref() {
-            // Начальная загрузка сообщений
-            "all_requests" => {
-                let user_id: i64 = ctx.query.from.id.0.try_into().unwrap();
-                let messages = ctx.bots.db.get_user_messages(user_id).await?;
-                
-                state = State::ViewingMessages {
-                    messages,
-                    current_page: 0,
-                };
-                ctx.dialogue.update(state.clone()).await?;
-            },
-            
-            // Обработка пагинации
-            d if d.starts_with("page_") => {
-                if let State::ViewingMessages { messages, current_page } = &state {
-                    let new_page = d["page_".len()..].parse::<usize>().unwrap_or(*current_page);
-                    let total_pages = (messages.len() + ITEMS_PER_PAGE - 1) / ITEMS_PER_PAGE;
-                    let valid_page = new_page.min(total_pages.saturating_sub(1));
-
-                    state = State::ViewingMessages { 
-                        messages: messages.clone(), 
-                        current_page: valid_page 
-                    };
-                    ctx.dialogue.update(state.clone()).await?;
-                } else {
-                    eprintln!("Received page callback with invalid state");
-                    return Ok(());
+        match ctx.data {
+            // Начальная загрузка сообщений, с фильтром по статусу или без
+            CallbackData::AllRequests => show_page(ctx, None, 0, PageCursor::First).await,
+            CallbackData::AnsweredRequests => show_page(ctx, Some(MessageStatus::Answered), 0, PageCursor::First).await,
+            CallbackData::AcceptedRequests => show_page(ctx, Some(MessageStatus::Accepted), 0, PageCursor::First).await,
+
+            // Обработка пагинации: соседние страницы по курсорам текущей
+            CallbackData::Page(new_page) => {
+                match ctx.dialogue.get().await?.unwrap_or_default() {
+                    State::ViewingMessages { current_page, filter, first, last } => {
+                        let cursor = match (first, last) {
+                            (_, Some(last)) if new_page == current_page + 1 => PageCursor::After(last),
+                            (Some(first), _) if new_page + 1 == current_page => PageCursor::Before(first),
+                            (Some(first), _) if new_page == current_page => PageCursor::From(first),
+                            _ => return show_page(ctx, filter, 0, PageCursor::First).await,
+                        };
+                        show_page(ctx, filter, new_page, cursor).await
+                    }
+                    // Состояние потеряно (например, после перезапуска), показываем первую страницу
+                    _ => show_page(ctx, None, 0, PageCursor::First).await,
                 }
             },
-            
-            // Неизвестный callback
-            _ => return Ok(()),
-        }
-
-        // Обновляем клавиатуру
-        if let State::ViewingMessages { messages, current_page } = &state {
-            let keyboard = all_messages(messages.clone(), *current_page);
 
-            if let Some(message) = ctx.query.message.as_ref() {
-                let msg = message.regular_message().unwrap();
-                ctx.bots.bot.edit_message_reply_markup(msg.chat.id, message.id())
-                    .reply_markup(keyboard)
-                    .await?;
-            }
+            _ => Ok(()),
         }
-
-        Ok(())
     }
 }
 
 #[async_trait]
 impl CallbackHandler for MessageHandler {
     async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
-        let data = ctx.query.data.as_ref().unwrap();
-        
-        if let Some(message_id) = data.strip_prefix("msg_") {
-            let state = ctx.dialogue.get().await?.unwrap_or_default();
-
-            if let State::ViewingMessages { messages, current_page } = &state {
-                // Найдите сообщение по ID
-                if let Some(message) = messages.iter().find(|m| m.id.to_string() == message_id) {
-                    // Переключитесь в режим просмотра одного сообщения
-                    let new_state = State::ViewingSingleMessage {
-                        message: message.clone(),
-                        back_page: *current_page,
-                    };
-                    ctx.dialogue.update(new_state).await?;
-
-                    // Отправьте сообщение с кнопкой "Назад"
-                    let keyboard = InlineKeyboardMarkup::default()
-                        .append_row(vec![InlineKeyboardButton::callback(
-                            "⬅️ Назад",
-                            format!("back_to_page_{}", current_page),
-                        )]);
-
-                    if let Some(msg) = ctx.query.message.as_ref() {
-                        let msg = msg.regular_message().unwrap();
-                        ctx.bots.bot
-                            .edit_message_text(msg.chat.id, msg.id, format!("**Сообщение:**\n{}\n*UID:* `{}`\n*Время обращения:* {}\n*Статус:* {}", escape(&message.text), escape(&message.id.to_string()), escape(&message.created_at.to_string()), escape(&message.status.to_string())))
-                            .parse_mode(ParseMode::MarkdownV2)
-                            .reply_markup(keyboard)
-                            .await?;
-                    }
-                }
-            }
+        let CallbackData::Message(message_id) = ctx.data else {
+            return Ok(());
+        };
+
+        let user_id: i64 = ctx.query.from.id.0.try_into().unwrap();
+        // Найдите сообщение по ID, чужие обращения не показываем
+        let Some(message) = ctx.bots.db.get_message_by_id(message_id).await?.filter(|m| m.telegram_id == user_id) else {
+            return Ok(());
+        };
+
+        let (back_page, filter, page_start) = match ctx.dialogue.get().await?.unwrap_or_default() {
+            State::ViewingMessages { current_page, filter, first, .. } => (current_page, filter, first),
+            _ => (0, None, None),
+        };
+
+        // Переключитесь в режим просмотра одного сообщения
+        ctx.dialogue.update(State::ViewingSingleMessage {
+            message_id,
+            back_page,
+            filter,
+            page_start,
+        }).await?;
+
+        // Отправьте сообщение с кнопкой "Назад"
+        let keyboard = InlineKeyboardMarkup::default()
+            .append_row(vec![CallbackData::BackToPage(back_page).button(t(ctx.lang, "requests.back_button"))]);
+
+        let mut text = t_args(ctx.lang, "requests.card", &[
+            ("text", &escape(&message.text)),
+            ("uid", &escape(&message.id.to_string())),
+            ("time", &escape(&message.created_at.to_string())),
+            ("status", &escape(&status_label(ctx.lang, message.status))),
+        ]);
+        if let Some(answer) = &message.answer {
+            text.push_str(&t_args(ctx.lang, "requests.answer", &[("answer", &escape(answer))]));
+        }
+
+        if let Some(msg) = ctx.query.regular_message() {
+            ctx.bots.bot
+                .edit_message_text(msg.chat.id, msg.id, text)
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_markup(keyboard)
+                .await?;
         }
 
         Ok(())
@@ -124,36 +157,13 @@ impl CallbackHandler for MessageHandler {
 #[async_trait]
 impl CallbackHandler for BackToPageHandler {
     async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
-        let data = ctx.query.data.as_ref().unwrap();
-        
-        if let Some(page_str) = data.strip_prefix("back_to_page_") {
-            let page = page_str.parse::<usize>().unwrap_or(0);
-            
-            let state = ctx.dialogue.get().await?.unwrap_or_default();
-
-            if let State::ViewingSingleMessage { message, back_page } = &state {
-                // Вернитесь к списку сообщений
-                let user_id: i64 = ctx.query.from.id.0.try_into().unwrap();
-                let messages = ctx.bots.db.get_user_messages(user_id).await?;
-                let new_state = State::ViewingMessages {
-                    messages: messages.clone(),
-                    current_page: *back_page,
-                };
-                ctx.dialogue.update(new_state).await?;
-
-                // Обновите клавиатуру
-                let keyboard = all_messages(messages, *back_page);
-                if let Some(msg) = ctx.query.message.as_ref() {
-                    let msg = msg.regular_message().unwrap();
-                    ctx.bots.bot
-                        .edit_message_text(msg.chat.id, msg.id, "*Выберете:*")
-                        .parse_mode(ParseMode::MarkdownV2)
-                        .reply_markup(keyboard)
-                        .await?;
-                }
+        // Вернитесь к списку сообщений, на ту же страницу если она известна
+        match ctx.dialogue.get().await?.unwrap_or_default() {
+            State::ViewingSingleMessage { back_page, filter, page_start: Some(start), .. } => {
+                show_page(ctx, filter, back_page, PageCursor::From(start)).await
             }
+            State::ViewingSingleMessage { filter, .. } => show_page(ctx, filter, 0, PageCursor::First).await,
+            _ => show_page(ctx, None, 0, PageCursor::First).await,
         }
-
-        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use db_pg::AnswerStyle;
+use grpc_service::client::GenerationParams;
+use localization::{t, t_args, Lang};
+use teloxide::{payloads::EditMessageTextSetters, prelude::Requester, types::{InlineKeyboardMarkup, ParseMode}};
+
+use crate::{callback_data::CallbackData, handlers::callback::{CallbackContext, CallbackHandler}, i18n::{language_label, style_label}, keyboards::settings::{settings, settings_language, settings_style}, types::HandlerResult};
+
+pub struct SettingsMenu;
+pub struct LanguageMenu;
+pub struct StyleMenu;
+pub struct SetLanguage;
+pub struct SetStyle;
+pub struct ToggleNotifications;
+
+/// Параметры генерации Qortex AI для выбранного стиля ответов
+pub fn generation_params(style: AnswerStyle) -> GenerationParams {
+    match style {
+        AnswerStyle::Precise => GenerationParams { temperature: 0.3, top_p: 0.8 },
+        AnswerStyle::Balanced => GenerationParams::default(),
+        AnswerStyle::Creative => GenerationParams { temperature: 1.0, top_p: 0.95 },
+    }
+}
+
+fn user_id(ctx: &CallbackContext) -> i64 {
+    ctx.query.from.id.0.try_into().unwrap()
+}
+
+async fn edit(ctx: &CallbackContext, text: String, keyboard: InlineKeyboardMarkup) -> HandlerResult {
+    if let Some(msg) = ctx.query.regular_message() {
+        ctx.bots.bot.edit_message_text(msg.chat.id, msg.id, text)
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(keyboard)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Экран настроек с текущими значениями, `lang` может отличаться от ctx.lang сразу после смены языка
+async fn show_settings(ctx: &CallbackContext, lang: Lang) -> HandlerResult {
+    let user_id = user_id(ctx);
+    let user_settings = ctx.bots.db.get_user_settings(user_id).await?;
+    let language = ctx.bots.stored_lang(user_id).await;
+
+    let notifications_key = if user_settings.status_notifications { "settings.on" } else { "settings.off" };
+    let text = t_args(lang, "settings.text", &[
+        ("language", &language_label(lang, language)),
+        ("style", &style_label(lang, user_settings.answer_style)),
+        ("notifications", &t(lang, notifications_key)),
+    ]);
+    edit(ctx, text, settings(lang, user_settings.status_notifications)).await
+}
+
+#[async_trait]
+impl CallbackHandler for SettingsMenu {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        show_settings(ctx, ctx.lang).await
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for LanguageMenu {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let current = ctx.bots.stored_lang(user_id(ctx)).await;
+        edit(ctx, t(ctx.lang, "settings.choose_language"), settings_language(ctx.lang, current)).await
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for StyleMenu {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let current = ctx.bots.db.get_user_settings(user_id(ctx)).await?.answer_style;
+        edit(ctx, t(ctx.lang, "settings.choose_style"), settings_style(ctx.lang, current)).await
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for SetLanguage {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let CallbackData::SetLanguage(choice) = ctx.data else {
+            return Ok(());
+        };
+        ctx.bots.db.set_user_language(user_id(ctx), choice.map(Lang::code)).await?;
+
+        let lang = ctx.bots.lang(&ctx.query.from).await;
+        show_settings(ctx, lang).await
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for SetStyle {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let CallbackData::SetStyle(style) = ctx.data else {
+            return Ok(());
+        };
+        ctx.bots.db.set_answer_style(user_id(ctx), style).await?;
+
+        show_settings(ctx, ctx.lang).await
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for ToggleNotifications {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        ctx.bots.db.toggle_status_notifications(user_id(ctx)).await?;
+
+        show_settings(ctx, ctx.lang).await
+    }
+}
@@ -0,0 +1,107 @@
+use async_trait::async_trait;
+use db_pg::{LedgerEntry, LedgerKind, UserRole};
+use localization::{t, t_args, t_plural, Lang};
+use teloxide::{payloads::EditMessageTextSetters, prelude::Requester, types::ParseMode, utils::markdown::escape};
+use uuid::Uuid;
+
+use crate::{handlers::callback::{CallbackContext, CallbackHandler}, keyboards::wallet::wallet, types::HandlerResult, TelegramBot};
+
+/// Сколько последних операций показывается в кошельке
+const WALLET_HISTORY: i64 = 5;
+
+pub struct WalletScreen;
+
+/// Результат оплаты ответа AI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnswerCharge {
+    /// Роль с безлимитным доступом, ничего не списано
+    Exempt,
+    Charged,
+    Insufficient { balance: i64 },
+}
+
+pub async fn is_exempt(bots: &TelegramBot, telegram_id: i64) -> HandlerResult<bool> {
+    Ok(matches!(bots.role(telegram_id).await?, UserRole::Admin | UserRole::WithAccess))
+}
+
+/// Приветственные кредиты начисляются при первом обращении к кошельку
+async fn ensure_welcome_credits(bots: &TelegramBot, telegram_id: i64) -> HandlerResult {
+    if bots.config.welcome_credits > 0 {
+        bots.db.add_welcome_credits(telegram_id, bots.config.welcome_credits).await?;
+    }
+    Ok(())
+}
+
+/// Списывает стоимость ответа заранее, `answer_id` связывает списание с ответом
+pub async fn charge_for_answer(bots: &TelegramBot, telegram_id: i64, answer_id: Uuid) -> HandlerResult<AnswerCharge> {
+    if is_exempt(bots, telegram_id).await? {
+        return Ok(AnswerCharge::Exempt);
+    }
+    ensure_welcome_credits(bots, telegram_id).await?;
+
+    match bots.db.charge(telegram_id, bots.config.ai_answer_price, answer_id).await? {
+        Some(_) => Ok(AnswerCharge::Charged),
+        None => Ok(AnswerCharge::Insufficient { balance: bots.db.get_balance(telegram_id).await? }),
+    }
+}
+
+pub fn credits(lang: Lang, amount: i64) -> String {
+    t_plural(lang, "wallet.credits", amount.unsigned_abs(), &[])
+}
+
+fn entry_line(lang: Lang, entry: &LedgerEntry) -> String {
+    let kind = t(lang, match entry.kind {
+        LedgerKind::Welcome => "wallet.kind.welcome",
+        LedgerKind::Grant => "wallet.kind.grant",
+        LedgerKind::Charge => "wallet.kind.charge",
+        LedgerKind::Refund => "wallet.kind.refund",
+    });
+    let amount = if entry.amount >= 0 { format!("+{}", entry.amount) } else { entry.amount.to_string() };
+    let mut line = format!(
+        "`{}` *{}* {}",
+        escape(&entry.created_at.format("%d.%m.%Y %H:%M").to_string()),
+        escape(&amount),
+        escape(&kind)
+    );
+    if let Some(comment) = &entry.comment {
+        line.push_str(&escape(&format!(" ({})", comment)));
+    }
+    line
+}
+
+async fn wallet_text(bots: &TelegramBot, lang: Lang, telegram_id: i64) -> HandlerResult<String> {
+    if is_exempt(bots, telegram_id).await? {
+        return Ok(t(lang, "wallet.unlimited"));
+    }
+    ensure_welcome_credits(bots, telegram_id).await?;
+
+    let balance = bots.db.get_balance(telegram_id).await?;
+    let entries = bots.db.get_ledger(telegram_id, WALLET_HISTORY).await?;
+    let history = if entries.is_empty() {
+        t(lang, "wallet.no_entries")
+    } else {
+        entries.iter().map(|e| entry_line(lang, e)).collect::<Vec<_>>().join("\n")
+    };
+
+    Ok(t_args(lang, "wallet.text", &[
+        ("balance", &credits(lang, balance)),
+        ("price", &credits(lang, bots.config.ai_answer_price)),
+        ("history", &history),
+    ]))
+}
+
+#[async_trait]
+impl CallbackHandler for WalletScreen {
+    async fn handle(&self, ctx: &CallbackContext) -> HandlerResult {
+        let user_id: i64 = ctx.query.from.id.0.try_into().unwrap();
+        let text = wallet_text(&ctx.bots, ctx.lang, user_id).await?;
+
+        if let Some(msg) = ctx.query.regular_message() {
+            ctx.bots.bot.edit_message_text(msg.chat.id, msg.id, text)
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_markup(wallet())
+                .await?;
+        }
+        Ok(())
+    }
+}
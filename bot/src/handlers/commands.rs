@@ -1,11 +1,11 @@
-use std::{sync::Arc, time::Duration};
-use db_pg::User;
+use std::sync::Arc;
+use db_pg::{DbError, ShareRedeem, User};
+use localization::{t, t_args, Lang};
 use logging::{log_error, log_info};
-use teloxide::{payloads::{SendMessageSetters, SendPhotoSetters}, prelude::Requester, types::{InputFile, Message, ParseMode}, utils::{command::BotCommands, markdown::escape}};
-use tokio::time::sleep;
+use teloxide::{payloads::{SendMessageSetters, SendPhotoSetters}, prelude::Requester, types::{ChatId, InputFile, Message, ParseMode}, utils::{command::BotCommands, markdown::escape}};
 use uuid::Uuid;
 
-use crate::{keyboards::{faqkb::faq, menu::menu}, state::State, types::{HandlerResult, MyDialogue}, TelegramBot};
+use crate::{handlers::{file_manager::share_token, users::{self, RoleAction}, callback::{admin::send_admin_requests, wallet::credits}}, keyboards::{faqkb::faq, menu::menu}, permissions::{Roles, ADMINS, EVERYONE}, state::State, types::{HandlerResult, MyDialogue}, TelegramBot};
 
 /// Commands for bot
 #[derive(BotCommands, Clone)]
@@ -14,11 +14,43 @@ pub enum Commander {
     #[command(description = "/faq - вам поможет")]
     Help,
     #[command(description = "Запуск бота")]
-    Start,
+    Start(String),
     #[command(description = "Оставить сообщение администратору",)]
     Send(String),
     #[command(description = "FAQ ℹ️ бота",)]
     Faq,
+    #[command(description = "Начать новый диалог с Qortex AI")]
+    Reset,
+    #[command(description = "Обращения пользователей (для администраторов)")]
+    Admin,
+    #[command(description = "Начислить кредиты: /grant <telegram_id> <количество> [комментарий]")]
+    Grant(String),
+    #[command(description = "Профиль пользователя: /user <@username | telegram_id | uuid>")]
+    User(String),
+    #[command(description = "Повысить роль: /promote <пользователь> [причина]")]
+    Promote(String),
+    #[command(description = "Понизить роль: /demote <пользователь> [причина]")]
+    Demote(String),
+    #[command(description = "Заблокировать: /ban <пользователь> [причина]")]
+    Ban(String),
+    #[command(description = "Разблокировать: /unban <пользователь> [причина]")]
+    Unban(String),
+}
+
+impl Commander {
+    /// Роли, которым доступна команда, проверяются до `command_handler`
+    pub fn required_roles(&self) -> Roles {
+        match self {
+            Self::Help | Self::Start(_) | Self::Send(_) | Self::Faq | Self::Reset => EVERYONE,
+            Self::Admin
+            | Self::Grant(_)
+            | Self::User(_)
+            | Self::Promote(_)
+            | Self::Demote(_)
+            | Self::Ban(_)
+            | Self::Unban(_) => ADMINS,
+        }
+    }
 }
 
 pub async fn command_handler(bots: Arc<TelegramBot>, dialogue: MyDialogue, msg: Message, cmd: Commander) -> HandlerResult {
@@ -27,75 +59,65 @@ pub async fn command_handler(bots: Arc<TelegramBot>, dialogue: MyDialogue, msg:
         log_info!("ChatId not eq UserId");
         return Ok(());
     }
+    let lang = match &msg.from {
+        Some(user) => bots.lang(user).await,
+        None => Lang::default(),
+    };
     match cmd {
-        Commander::Help => bot.send_message(msg.chat.id, Commander::descriptions().to_string()).await?,
-        Commander::Start => {
+        Commander::Help => bot.send_message(msg.chat.id, t(lang, "help.text")).await?,
+        Commander::Start(payload) => {
             let new_user = User {
                 telegram_id: msg.chat.id.0,
                 username: msg.from.unwrap().username,
                 uuid: Uuid::new_v4(),
                 role: db_pg::UserRole::Default,
+                language: None,
             };
 
-            let pool = bots.db.pool.clone();
-            tokio::spawn(async move {
-                let user = new_user;
-                sleep(Duration::from_secs(5)).await;
-                let res: Result<_, sqlx::Error> = 
-                sqlx::query(
-                    r#"
-                    INSERT INTO users (telegram_id, username, uuid, role)
-                    VALUES ($1, $2, $3, $4)
-                    ON CONFLICT (telegram_id) DO NOTHING
-                    "#
-                )
-                .bind(user.telegram_id)
-                .bind(&user.username.clone().unwrap_or("None".to_string()))
-                .bind(user.uuid)
-                .bind(user.role)
-                .execute(&pool)
-                .await;
-                if let Err(e) = res {
-                    log_error!("Error while insert user into db: {:?}", e);
-                } else {
-                    log_info!("It's ok!");
-                }
-                
-            });
+            // Повторный /start не меняет уже сохранённого пользователя
+            if let Err(e) = bots.db.add_user(&new_user).await {
+                log_error!("Error while insert user into db: {:?}", e);
+            }
+
+            // Переход по ссылке на файл из обменника
+            if let Some(token) = share_token(&payload) {
+                let key = match bots.files.handle_shared_link(token, bot, msg.chat.id, lang).await? {
+                    ShareRedeem::Redeemed(_) => return Ok(()),
+                    ShareRedeem::NotFound => "files.link_invalid",
+                    ShareRedeem::Expired => "files.link_expired",
+                    ShareRedeem::LimitReached => "files.link_limit",
+                };
+                bot.send_message(msg.chat.id, t(lang, key)).await?;
+                return Ok(());
+            }
 
             let path = concat!(env!("CARGO_MANIFEST_DIR"), "/static/aw_logo.png");
             let image = InputFile::file(path);
-            let text = format!(
-                "*{}* привет\nМы команда разработчиков *Axiowel*, занимаемся разработкой эффективного и отказоустойчевого программного обеспечения основоного на ИИ модели *Axiowel AI*\n\nНаш бот достататочно функционален, можете подробнее узнать в /faq",
-                escape(&msg.chat.first_name().unwrap_or(""))
-            );
+            let text = t_args(lang, "start.greeting", &[("name", &escape(msg.chat.first_name().unwrap_or("")))]);
             bot.send_message(msg.chat.id, text)
                 .parse_mode(ParseMode::MarkdownV2)
-                .reply_markup(menu())
-                .await;
+                .reply_markup(menu(lang))
+                .await?;
 
             return Ok(());
         }
         Commander::Send(message) => {
             let message_uuid = bots.db.add_message(msg.chat.id.0, &message).await?;
-            bot.send_message(msg.chat.id, format!("Сообщение с уникальным номером: `{}` отправлено, ожидвйте ответа\\!", escape(message_uuid.to_string().as_str())))
+            bot.send_message(msg.chat.id, t_args(lang, "send.sent", &[("uid", &escape(&message_uuid.to_string()))]))
                 .parse_mode(ParseMode::MarkdownV2)
                 .await?;
 
             return Ok(());
         },
         Commander::Faq => {
-            let mut first_name = escape(&msg.chat.first_name().unwrap_or(""));
-            if first_name != "" {
+            let mut first_name = escape(msg.chat.first_name().unwrap_or(""));
+            if !first_name.is_empty() {
                 first_name.push(' ');
             }
-            let text = format!(
-                "*FAQ ℹ️*\n\n*{}*Если вы не нашли ответ на свой вопрос, то можете написать прямо сюда в чат 💭 и *Axiobot* 🤖 поможет ответить на ваш вопрос 😊",
-                first_name
-            );
+            let text = t_args(lang, "faq.text", &[("name", &first_name)]);
             
             bot.send_message(msg.chat.id, text)
-                .reply_markup(faq())
+                .reply_markup(faq(lang))
                 .parse_mode(ParseMode::MarkdownV2)
                 .await?;
                 
@@ -103,7 +125,92 @@ pub async fn command_handler(bots: Arc<TelegramBot>, dialogue: MyDialogue, msg:
 
             return Ok(())
         }
+        Commander::Reset => {
+            bots.conversation.reset(msg.chat.id.0).await?;
+            bot.send_message(msg.chat.id, t(lang, "ai.reset"))
+                .parse_mode(ParseMode::MarkdownV2)
+                .await?;
+
+            dialogue.update(State::WaitQuestion).await?;
+
+            return Ok(())
+        }
+        Commander::Admin => {
+            send_admin_requests(&bots, msg.chat.id, lang, 0).await?;
+            dialogue.update(State::AdminViewingRequests { current_page: 0 }).await?;
+
+            return Ok(())
+        }
+        Commander::Grant(args) => {
+            let Some((telegram_id, amount, comment)) = parse_grant(&args) else {
+                bot.send_message(msg.chat.id, t(lang, "wallet.grant_usage")).await?;
+                return Ok(());
+            };
+
+            // В группе id чата не id администратора
+            let Some(admin_id) = msg.from.as_ref().map(|from| from.id.0 as i64) else {
+                return Ok(());
+            };
+            let balance = match bots.db.grant_credits(telegram_id, amount, admin_id, comment).await {
+                Ok(balance) => balance,
+                Err(DbError::NotFound) => {
+                    bot.send_message(msg.chat.id, t(lang, "users.not_found")).await?;
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            };
+            log_info!("Администратор {} начислил {} кредитов пользователю {}", admin_id, amount, telegram_id);
+            bot.send_message(msg.chat.id, t_args(lang, "wallet.granted", &[
+                ("user", &telegram_id.to_string()),
+                ("amount", &credits(lang, amount)),
+                ("balance", &credits(lang, balance)),
+            ])).await?;
+
+            // Получатель мог ни разу не писать боту, тогда уведомление не дойдёт
+            let user_lang = bots.stored_lang(telegram_id).await.unwrap_or_default();
+            let notice = t_args(user_lang, "wallet.received", &[
+                ("amount", &credits(user_lang, amount)),
+                ("balance", &credits(user_lang, balance)),
+            ]);
+            if let Err(e) = bot.send_message(ChatId(telegram_id), notice).await {
+                log_error!("Не удалось уведомить {} о начислении кредитов: {}", telegram_id, e);
+            }
+
+            return Ok(())
+        }
+        Commander::User(args) => {
+            users::show_user(&bots, &msg, lang, &args).await?;
+            return Ok(())
+        }
+        Commander::Promote(args) => return users::change_role(&bots, &msg, lang, RoleAction::Promote, &args).await,
+        Commander::Demote(args) => return users::change_role(&bots, &msg, lang, RoleAction::Demote, &args).await,
+        Commander::Ban(args) => return users::change_role(&bots, &msg, lang, RoleAction::Ban, &args).await,
+        Commander::Unban(args) => return users::change_role(&bots, &msg, lang, RoleAction::Unban, &args).await,
     };
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Разбирает `<telegram_id> <количество> [комментарий]`, количество должно быть положительным
+fn parse_grant(args: &str) -> Option<(i64, i64, Option<&str>)> {
+    let mut parts = args.trim().splitn(3, char::is_whitespace);
+    let telegram_id = parts.next()?.parse().ok()?;
+    let amount = parts.next()?.parse().ok().filter(|amount: &i64| *amount > 0)?;
+    let comment = parts.next().map(str::trim).filter(|c| !c.is_empty());
+    Some((telegram_id, amount, comment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_grant;
+
+    #[test]
+    fn grant_arguments() {
+        assert_eq!(parse_grant("42 10"), Some((42, 10, None)));
+        assert_eq!(parse_grant(" 42 10  за тестирование "), Some((42, 10, Some("за тестирование"))));
+        assert_eq!(parse_grant("42 0"), None);
+        assert_eq!(parse_grant("42 -5"), None);
+        assert_eq!(parse_grant("42"), None);
+        assert_eq!(parse_grant("abc 10"), None);
+    }
+}
@@ -1,110 +1,394 @@
-use std::collections::HashMap;
-use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use chrono::Utc;
+use db_pg::{FileUsage, NewFile, ShareLink, ShareRedeem, Store, StoredFile, UserRole};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
-use tokio::fs;
-use teloxide::{prelude::*, types::InputFile};
+use localization::{t_args, Lang};
+use logging::{log_error, log_info};
+use teloxide::{payloads::SendDocumentSetters, prelude::*, types::InputFile};
 
-// Структура для управления файлами
+use crate::{config::{BotConfig, FileQuota}, storage::{ByteStream, FileStorage}, types::{HandlerResult, MyBot}, TelegramBot};
+
+/// Префикс параметра /start для ссылок на файлы
+pub const SHARE_PREFIX: &str = "share_";
+
+/// MIME тип документов, для которых Telegram его не прислал
+const UNKNOWN_MIME_TYPE: &str = "application/octet-stream";
+
+// Структура для управления файлами, метаданные и ссылки хранятся в Postgres.
+// Содержимое лежит в хранилище под ключом `<владелец>/<id файла>`, имя от пользователя только в метаданных
 pub struct FileManager {
-    storage_path: PathBuf,
-    share_links: Mutex<HashMap<String, SharedFile>>,
+    storage: Arc<dyn FileStorage>,
+    db: Arc<dyn Store>,
+    /// Время жизни новых ссылок, None без ограничения
+    link_ttl: Option<Duration>,
+    /// Лимит скачиваний по новой ссылке, None без ограничения
+    max_downloads: Option<i32>,
+    quota_default: FileQuota,
+    quota_with_access: FileQuota,
+    allowed_mime_types: Vec<String>,
 }
 
-#[derive(Clone)]
-struct SharedFile {
-    owner_id: i64,
-    file_path: PathBuf,
-    file_name: String,
+/// Причина, по которой файл не принят
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadRejection {
+    MimeType,
+    /// Файл с тем же именем ещё загружается
+    InProgress,
+    FileCount { max: i64 },
+    Storage { used: i64, max: i64 },
 }
 
 impl FileManager {
     // Создает новый экземпляр менеджера
-    pub async fn new(base_path: impl AsRef<Path>) -> std::io::Result<Self> {
-        let storage_path = base_path.as_ref().to_path_buf();
-        fs::create_dir_all(&storage_path).await?;
-        
-        Ok(Self {
-            storage_path,
-            share_links: Mutex::new(HashMap::new()),
-        })
-    }
-
-    // Создает папку для пользователя
-    pub async fn create_user_dir(&self, user_id: i64) -> std::io::Result<PathBuf> {
-        let user_dir = self.storage_path.join(user_id.to_string());
-        if !user_dir.exists() {
-            fs::create_dir(&user_dir).await?;
+    pub fn new(storage: Arc<dyn FileStorage>, db: Arc<dyn Store>, config: &BotConfig) -> Self {
+        Self {
+            storage,
+            db,
+            link_ttl: config.share_link_ttl,
+            max_downloads: config.share_link_max_downloads,
+            quota_default: config.file_quota_default,
+            quota_with_access: config.file_quota_with_access,
+            allowed_mime_types: config.allowed_mime_types.clone(),
+        }
+    }
+
+    /// Квота роли, у администраторов её нет, заблокированным ничего нельзя загружать
+    pub fn quota(&self, role: UserRole) -> Option<FileQuota> {
+        match role {
+            UserRole::Admin => None,
+            UserRole::WithAccess => Some(self.quota_with_access),
+            UserRole::Default => Some(self.quota_default),
+            UserRole::Banned => Some(FileQuota { max_files: 0, max_bytes: 0 }),
         }
-        Ok(user_dir)
     }
 
-    // Сохраняет файл пользователя
+    // Проверяет тип и квоту до скачивания файла с серверов Telegram
+    pub async fn check_upload(
+        &self,
+        user_id: i64,
+        role: UserRole,
+        file_name: &str,
+        mime_type: Option<&str>,
+        size: i64,
+    ) -> HandlerResult<Option<UploadRejection>> {
+        if !mime_allowed(&self.allowed_mime_types, mime_type.unwrap_or(UNKNOWN_MIME_TYPE)) {
+            return Ok(Some(UploadRejection::MimeType));
+        }
+        let Some(quota) = self.quota(role) else {
+            return Ok(None);
+        };
+
+        let usage = self.db.get_file_usage(user_id).await?;
+        let replaced = self.db.get_file_by_name(user_id, file_name).await?.map(|file| file.size);
+        Ok(check_quota(quota, usage, replaced, size))
+    }
+
+    // Ключ содержимого файла в хранилище, не зависит от имени файла
+    pub fn storage_key(file: &StoredFile) -> String {
+        object_key(file.owner_id, file.id)
+    }
+
+    // Содержимое файла, None если его нет в хранилище
+    pub async fn read_file(&self, file: &StoredFile) -> HandlerResult<Option<Vec<u8>>> {
+        Ok(self.storage.get(&Self::storage_key(file)).await?)
+    }
+
+    // Поток содержимого файла или его части, None если его нет в хранилище
+    pub async fn stream_file(&self, file: &StoredFile, range: Option<(u64, u64)>) -> HandlerResult<Option<ByteStream>> {
+        Ok(self.storage.stream(&Self::storage_key(file), range).await?)
+    }
+
+    // Сохраняет файл пользователя и его метаданные. Загрузки файла с тем же именем идут
+    // по очереди, квота проверяется ещё раз по настоящему размеру содержимого вместе
+    // с другими незавершёнными загрузками владельца
     pub async fn save_user_file(
-        &self, 
-        user_id: i64, 
-        file_name: &str, 
-        content: &[u8]
-    ) -> std::io::Result<PathBuf> {
-        let user_dir = self.create_user_dir(user_id).await?;
-        let file_path = user_dir.join(file_name);
-        fs::write(&file_path, content).await?;
-        Ok(file_path)
-    }
-
-    // Генерирует ссылку для делегирования
+        &self,
+        user_id: i64,
+        role: UserRole,
+        file_name: &str,
+        mime_type: Option<&str>,
+        content: Vec<u8>
+    ) -> HandlerResult<Result<StoredFile, UploadRejection>> {
+        let Some(claim) = self.db.claim_file_upload(user_id, file_name, content.len() as i64).await? else {
+            return Ok(Err(UploadRejection::InProgress));
+        };
+        let result = self.save_claimed(user_id, role, file_name, mime_type, content, claim.usage).await;
+        if let Err(e) = self.db.release_file_upload(user_id, file_name, claim.token).await {
+            log_error!("Не удалось освободить загрузку {} пользователя {}: {}", file_name, user_id, e);
+        }
+        result
+    }
+
+    async fn save_claimed(
+        &self,
+        user_id: i64,
+        role: UserRole,
+        file_name: &str,
+        mime_type: Option<&str>,
+        content: Vec<u8>,
+        usage: FileUsage,
+    ) -> HandlerResult<Result<StoredFile, UploadRejection>> {
+        let existing = self.db.get_file_by_name(user_id, file_name).await?;
+        let size = content.len() as i64;
+        if let Some(quota) = self.quota(role)
+            && let Some(rejection) = check_quota(quota, usage, existing.as_ref().map(|file| file.size), size)
+        {
+            return Ok(Err(rejection));
+        }
+
+        // Файл с тем же именем заменяется и сохраняет id, а с ним и ссылки
+        let id = existing.as_ref().map_or_else(Uuid::new_v4, |file| file.id);
+        let key = object_key(user_id, id);
+        let checksum = hex::encode(Sha256::digest(&content));
+        let saved = match self.storage.put(&key, content, mime_type).await {
+            Ok(()) => self.db.upsert_file(NewFile {
+                id,
+                owner_id: user_id,
+                original_name: file_name,
+                size,
+                mime_type,
+                checksum: &checksum,
+            }).await.map_err(Into::into),
+            Err(e) => Err(e.into()),
+        };
+
+        // Содержимое нового файла без метаданных никому не видно и только занимает место
+        if saved.is_err() && existing.is_none() && let Err(e) = self.storage.delete(&key).await {
+            log_error!("Не удалось удалить содержимое несохранённого файла {}: {}", key, e);
+        }
+        saved.map(Ok)
+    }
+
+    // Удаляет файл владельца вместе со ссылками, false если файла нет
+    pub async fn delete_user_file(&self, user_id: i64, file_id: Uuid) -> HandlerResult<bool> {
+        let Some(file) = self.db.delete_file(user_id, file_id).await? else {
+            return Ok(false);
+        };
+        self.storage.delete(&Self::storage_key(&file)).await?;
+        Ok(true)
+    }
+
+    // Создает ссылку для делегирования файла владельцем, None если файл не его
     pub async fn generate_share_link(
-        &self, 
-        owner_id: i64, 
-        file_name: &str
-    ) -> std::io::Result<String> {
-        let token = Uuid::new_v4().to_string();
-        let user_dir = self.create_user_dir(owner_id).await?;
-        let file_path = user_dir.join(file_name);
-        
-        if file_path.exists() {
-            self.share_links.lock().await.insert(
-                token.clone(),
-                SharedFile {
-                    owner_id,
-                    file_path,
-                    file_name: file_name.to_string(),
-                }
-            );
-            
-            Ok(format!("https://yourdomain.com/share/ {}", token))
-        } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound, 
-                "File not found"
-            ))
+        &self,
+        owner_id: i64,
+        file_id: Uuid
+    ) -> HandlerResult<Option<ShareLink>> {
+        let Some(file) = self.db.get_file(file_id).await? else {
+            return Ok(None);
+        };
+        if file.owner_id != owner_id {
+            return Ok(None);
         }
+
+        let token = Uuid::new_v4().simple().to_string();
+        let expires_at = match self.link_ttl {
+            Some(ttl) => Some(Utc::now() + chrono::Duration::from_std(ttl)?),
+            None => None,
+        };
+        let link = self.db.create_share_link(&token, file.id, owner_id, expires_at, self.max_downloads).await?;
+        Ok(Some(link))
     }
 
-    // Обрабатывает запрос по ссылке
+    // Обрабатывает запрос по ссылке, файл отправляется только если ссылка действительна
     pub async fn handle_shared_link(
-        &self, 
+        &self,
         token: &str,
-        bot: &Bot,
-        chat_id: ChatId
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(shared) = self.share_links.lock().await.get(token) {
-            let file = InputFile::file(&shared.file_path);
+        bot: &MyBot,
+        chat_id: ChatId,
+        lang: Lang,
+    ) -> HandlerResult<ShareRedeem> {
+        let redeem = self.db.redeem_share_link(token).await?;
+        if let ShareRedeem::Redeemed(shared) = &redeem {
+            let Some(content) = self.read_file(shared).await? else {
+                return Err(format!("Содержимое файла {} не найдено в хранилище", shared.id).into());
+            };
+            let file = InputFile::memory(content).file_name(shared.original_name.clone());
             bot.send_document(chat_id, file)
-                .caption(format!("Файл от пользователя {}", shared.owner_id))
+                .caption(t_args(lang, "files.shared_caption", &[("owner", &shared.owner_id.to_string())]))
                 .await?;
-            Ok(())
+        }
+        Ok(redeem)
+    }
+
+    // Проверяет ссылку для скачивания по HTTP, скачивание засчитывается только при `count`.
+    // Без него ссылка проверяется по тем же правилам, так читаются заголовки
+    pub async fn resolve_download(&self, token: &str, count: bool) -> HandlerResult<ShareRedeem> {
+        let redeem = if count {
+            self.db.redeem_share_link(token).await?
         } else {
-            Err("Ссылка недействительна или истекло время действия".into())
+            self.db.peek_share_link(token, false).await?
+        };
+        Ok(redeem)
+    }
+
+    // Докачка в сессии уже засчитанного скачивания (см. download_server::DownloadSessions):
+    // проверяется лишь срок ссылки, лимит скачиваний уже учтён
+    pub async fn resume_download(&self, token: &str) -> HandlerResult<ShareRedeem> {
+        Ok(self.db.peek_share_link(token, true).await?)
+    }
+
+    // Помечает ссылки с истекшим сроком или исчерпанным лимитом
+    pub async fn cleanup_expired_links(&self) -> HandlerResult<u64> {
+        Ok(self.db.expire_share_links().await?)
+    }
+}
+
+/// Периодически закрывает устаревшие ссылки, запускается вместе с ботом
+pub async fn run_link_cleanup(bots: Arc<TelegramBot>) {
+    let mut interval = tokio::time::interval(bots.config.share_cleanup_interval);
+    loop {
+        interval.tick().await;
+        match bots.files.cleanup_expired_links().await {
+            Ok(0) => {}
+            Ok(expired) => log_info!("Закрыто устаревших ссылок на файлы: {}", expired),
+            Err(e) => log_error!("Ошибка при закрытии устаревших ссылок на файлы: {}", e),
         }
     }
+}
+
+fn object_key(owner_id: i64, file_id: Uuid) -> String {
+    format!("{}/{}", owner_id, file_id.simple())
+}
 
-    // Очищает устаревшие ссылки (можно запускать периодически)
-    pub async fn cleanup_expired_links(&self) {
-        self.share_links.lock().await.retain(|_, _| {
-            // Здесь можно добавить логику проверки времени жизни ссылок
-            true // Пока оставляем все ссылки
-        });
+/// Разрешён ли MIME тип списком, `type/*` разрешает весь тип
+pub fn mime_allowed(allowed: &[String], mime_type: &str) -> bool {
+    let mime_type = mime_type.trim().to_lowercase();
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    allowed.iter().any(|allowed| match allowed.strip_suffix("/*") {
+        Some(kind) => essence.split_once('/').is_some_and(|(k, _)| k == kind),
+        None => allowed == essence,
+    })
+}
+
+/// Проверка квоты, `replaced` — размер файла с тем же именем, который будет заменён
+pub fn check_quota(quota: FileQuota, usage: FileUsage, replaced: Option<i64>, size: i64) -> Option<UploadRejection> {
+    let (files, bytes) = match replaced {
+        Some(old_size) => (usage.files, usage.bytes - old_size + size),
+        None => (usage.files + 1, usage.bytes + size),
+    };
+    if files > quota.max_files {
+        return Some(UploadRejection::FileCount { max: quota.max_files });
+    }
+    if bytes > quota.max_bytes {
+        return Some(UploadRejection::Storage { used: usage.bytes, max: quota.max_bytes });
     }
-}
\ No newline at end of file
+    None
+}
+
+/// Deep link вида `https://t.me/<bot>?start=share_<token>`, открывает бота с командой /start
+pub fn share_link(bot_username: &str, token: &str) -> String {
+    format!("https://t.me/{}?start={}{}", bot_username, SHARE_PREFIX, token)
+}
+
+/// Ссылка на скачивание через встроенный HTTP сервер
+pub fn web_link(base_url: &str, token: &str) -> String {
+    format!("{}/share/{}", base_url.trim_end_matches('/'), token)
+}
+
+/// Токен из параметра /start, если это ссылка на файл
+pub fn share_token(payload: &str) -> Option<&str> {
+    payload
+        .trim()
+        .strip_prefix(SHARE_PREFIX)
+        .filter(|token| !token.is_empty() && token.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn share_link_round_trip() {
+        let token = Uuid::new_v4().simple().to_string();
+        let link = share_link("qortex_bot", &token);
+        assert_eq!(link, format!("https://t.me/qortex_bot?start=share_{}", token));
+
+        // Telegram ограничивает параметр start 64 символами
+        let payload = link.split_once("start=").unwrap().1;
+        assert!(payload.len() <= 64);
+        assert_eq!(share_token(payload), Some(token.as_str()));
+
+        assert_eq!(web_link("https://files.example.com/", &token), format!("https://files.example.com/share/{}", token));
+    }
+
+    #[tokio::test]
+    async fn share_links_without_postgres() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(crate::storage::LocalStorage::new(dir.path()).await.unwrap());
+        let config = BotConfig { share_link_max_downloads: Some(1), ..BotConfig::default() };
+        let files = FileManager::new(storage, Arc::new(db_pg::MemoryStore::new()), &config);
+
+        let file = files.save_user_file(1, UserRole::Default, "report.pdf", Some("application/pdf"), b"first".to_vec()).await.unwrap().unwrap();
+        let replaced = files.save_user_file(1, UserRole::Default, "report.pdf", Some("application/pdf"), b"second".to_vec()).await.unwrap().unwrap();
+        assert_eq!(replaced.id, file.id);
+        assert_eq!(files.read_file(&replaced).await.unwrap(), Some(b"second".to_vec()));
+
+        assert!(files.generate_share_link(2, file.id).await.unwrap().is_none());
+        let link = files.generate_share_link(1, file.id).await.unwrap().unwrap();
+        assert!(matches!(files.resolve_download(&link.token, true).await.unwrap(), ShareRedeem::Redeemed(_)));
+        assert!(matches!(files.resolve_download(&link.token, true).await.unwrap(), ShareRedeem::LimitReached));
+        assert!(matches!(files.resolve_download(&link.token, false).await.unwrap(), ShareRedeem::LimitReached));
+        // Докачка уже засчитанного скачивания
+        assert!(matches!(files.resume_download(&link.token).await.unwrap(), ShareRedeem::Redeemed(_)));
+
+        assert!(files.delete_user_file(1, file.id).await.unwrap());
+        assert!(matches!(files.resume_download(&link.token).await.unwrap(), ShareRedeem::NotFound));
+    }
+
+    #[tokio::test]
+    async fn uploads_are_checked_again_before_saving() {
+        use db_pg::FileStore;
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(crate::storage::LocalStorage::new(dir.path()).await.unwrap());
+        let store = Arc::new(db_pg::MemoryStore::new());
+        let config = BotConfig { file_quota_default: FileQuota { max_files: 5, max_bytes: 10 }, ..BotConfig::default() };
+        let files = FileManager::new(storage, store.clone(), &config);
+
+        // Telegram сообщил меньший размер, чем пришло на самом деле
+        let saved = files.save_user_file(1, UserRole::Default, "big.bin", None, vec![0; 11]).await.unwrap();
+        assert_eq!(saved.unwrap_err(), UploadRejection::Storage { used: 0, max: 10 });
+
+        let claim = store.claim_file_upload(1, "report.pdf", 4).await.unwrap().unwrap();
+        let saved = files.save_user_file(1, UserRole::Default, "report.pdf", None, b"text".to_vec()).await.unwrap();
+        assert_eq!(saved.unwrap_err(), UploadRejection::InProgress);
+        // Незавершённая загрузка другого файла тоже занимает квоту
+        let saved = files.save_user_file(1, UserRole::Default, "notes.txt", None, vec![0; 7]).await.unwrap();
+        assert_eq!(saved.unwrap_err(), UploadRejection::Storage { used: 4, max: 10 });
+        store.release_file_upload(1, "report.pdf", claim.token).await.unwrap();
+        assert!(files.save_user_file(1, UserRole::Default, "report.pdf", None, b"text".to_vec()).await.unwrap().is_ok());
+    }
+
+    #[test]
+    fn mime_allowlist() {
+        let allowed = vec!["application/pdf".to_string(), "image/*".to_string()];
+        assert!(mime_allowed(&allowed, "application/pdf"));
+        assert!(mime_allowed(&allowed, "Application/PDF; charset=binary"));
+        assert!(mime_allowed(&allowed, "image/png"));
+        assert!(!mime_allowed(&allowed, "imagery/png"));
+        assert!(!mime_allowed(&allowed, "application/x-msdownload"));
+        assert!(!mime_allowed(&allowed, UNKNOWN_MIME_TYPE));
+    }
+
+    #[test]
+    fn quota_limits() {
+        let quota = FileQuota { max_files: 2, max_bytes: 100 };
+        let usage = FileUsage { files: 1, bytes: 60 };
+        assert_eq!(check_quota(quota, usage, None, 40), None);
+        assert_eq!(check_quota(quota, usage, None, 41), Some(UploadRejection::Storage { used: 60, max: 100 }));
+        assert_eq!(check_quota(quota, FileUsage { files: 2, bytes: 60 }, None, 1), Some(UploadRejection::FileCount { max: 2 }));
+        // Замена файла не добавляет новый и освобождает место старого
+        assert_eq!(check_quota(quota, FileUsage { files: 2, bytes: 60 }, Some(50), 90), None);
+    }
+
+    #[test]
+    fn ignores_other_start_payloads() {
+        assert_eq!(share_token(""), None);
+        assert_eq!(share_token("share_"), None);
+        assert_eq!(share_token("promo_123"), None);
+        assert_eq!(share_token("share_../etc"), None);
+    }
+}
@@ -1,55 +1,276 @@
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
-use grpc_service::client::spawn_client_request_with_callback;
-use logging::log_info;
-use teloxide::{payloads::{EditMessageTextSetters, SendMessageSetters}, prelude::Requester, types::{Message, ParseMode}};
-use tokio::{sync::oneshot, time};
+use db_pg::MessageStatus;
+use grpc_service::client::spawn_client_stream_request;
+use localization::{t, t_args, Lang};
+use logging::{log_error, log_info};
+use teloxide::{net::Download, payloads::{EditMessageTextSetters, SendMessageSetters}, prelude::Requester, types::{ChatId, Document, Message, MessageId, ParseMode}, utils::markdown::escape};
+use tokio::{sync::mpsc, time::{self, MissedTickBehavior}};
+use uuid::Uuid;
 
-use crate::{keyboards::faqkb::feedback_ai, state::State, types::{HandlerResult, MyDialogue}, TelegramBot};
+use crate::{handlers::{callback::{admin::{is_admin, send_admin_requests}, files::format_size, settings::generation_params, wallet::{charge_for_answer, credits, AnswerCharge}}, file_manager::UploadRejection}, keyboards::{faqkb::feedback_ai, file_sharing::open_files, wallet::open_wallet}, state::State, types::{HandlerResult, MyDialogue}, TelegramBot};
 
+/// Лимит Telegram на длину сообщения 4096, оставляем запас под разметку
+const MAX_ANSWER_CHARS: usize = 3500;
 
+/// Bot API отдаёт ботам файлы не больше 20 МБ
+const MAX_DOCUMENT_MB: u32 = 20;
+
+/// Вопрос, ответ на который собирается в сообщение-заглушку
+struct PendingAnswer {
+    chat_id: ChatId,
+    message_id: MessageId,
+    lang: Lang,
+    /// Общий id для ai_feedback и списания в кошельке
+    answer_id: Uuid,
+    /// Ответ оплачен и при неудаче списание возвращается
+    charged: bool,
+    question: String,
+}
 
 pub async fn default_messages(bots: Arc<TelegramBot>, dialogue: MyDialogue, msg: Message) -> HandlerResult {
     let bot = &bots.bot;
 
-    if let Some(state) = dialogue.get().await? {
-        match state {
-            State::WaitQuestion => {
-                if let Some(question) = msg.text() {
-                    log_info!("Пользователь {} обратился за помощью к Qortex AI с вопросом: {}", msg.chat.first_name().unwrap_or(msg.chat.id.0.to_string().as_str()), question);
-                    let message = bot.send_message(msg.chat.id, "*Qortex AI*\n_Думаю над ответом\\.\\.\\._")
-                        .parse_mode(ParseMode::MarkdownV2)
-                        .await?;
+    let lang = match &msg.from {
+        Some(user) => bots.lang(user).await,
+        None => Lang::default(),
+    };
+    // Документы в любом состоянии загружаются в обменник
+    if let Some(document) = msg.document() {
+        return save_document(&bots, msg.chat.id, document, lang).await;
+    }
+    let Some(text) = msg.text() else {
+        return Ok(());
+    };
+
+    match dialogue.get().await? {
+        Some(State::WaitQuestion) => {
+            let question = text;
+            log_info!("Пользователь {} обратился за помощью к Qortex AI с вопросом: {}", msg.chat.first_name().unwrap_or(msg.chat.id.0.to_string().as_str()), question);
 
-                    // Обработка через ИИ
-                    time::sleep(Duration::from_secs(1)).await;
-                    log_info!("Ответ от AI получен");
+            let params = generation_params(bots.db.get_user_settings(msg.chat.id.0).await?.answer_style);
 
-                    bot.edit_message_text(msg.chat.id, message.id, "*Ваш ответ на вопрос:*\nОтвет на вопрос\n\n_Вы удволетворены ответом?_")
+            // Ответ оплачивается заранее, если AI не ответит списание вернётся
+            let answer_id = Uuid::new_v4();
+            let charged = match charge_for_answer(&bots, msg.chat.id.0, answer_id).await? {
+                AnswerCharge::Exempt => false,
+                AnswerCharge::Charged => true,
+                AnswerCharge::Insufficient { balance } => {
+                    bot.send_message(msg.chat.id, t_args(lang, "ai.no_credits", &[
+                        ("price", &credits(lang, bots.config.ai_answer_price)),
+                        ("balance", &credits(lang, balance)),
+                    ]))
                         .parse_mode(ParseMode::MarkdownV2)
-                        .reply_markup(feedback_ai())
+                        .reply_markup(open_wallet(lang))
                         .await?;
+                    return Ok(());
+                }
+            };
+
+            let message = match bot.send_message(msg.chat.id, t(lang, "ai.thinking"))
+                .parse_mode(ParseMode::MarkdownV2)
+                .await
+            {
+                Ok(message) => message,
+                Err(e) => {
+                    // Без заглушки ответ показать некуда
+                    if charged {
+                        refund_answer(&bots, msg.chat.id, answer_id).await;
+                    }
+                    return Err(e.into());
                 }
+            };
+
+            // Обработка через ИИ с контекстом прошлых вопросов, ответ ждём в отдельной задаче чтобы не блокировать чат
+            let history = bots.conversation.context(msg.chat.id.0).await;
+            let (tx, rx) = mpsc::channel(32);
+            spawn_client_stream_request(bots.config.ai_service_url.clone(), tx, question.to_string(), history, params);
+
+            let pending = PendingAnswer {
+                chat_id: msg.chat.id,
+                message_id: message.id,
+                lang,
+                answer_id,
+                charged,
+                question: question.to_string(),
+            };
+            tokio::spawn(stream_answer(bots.clone(), pending, rx));
+        }
+        Some(State::AdminAnswering { message_id, back_page }) => {
+            if !is_admin(&bots, msg.chat.id.0).await? {
+                dialogue.update(State::OnWaiting).await?;
+                return Ok(());
             }
-            _ => {}
+
+            bots.db.update_message_status(message_id, MessageStatus::Answered, Some(text)).await?;
+            bot.send_message(msg.chat.id, t_args(lang, "admin.answer_saved", &[("uid", &escape(&message_id.to_string()))]))
+                .parse_mode(ParseMode::MarkdownV2)
+                .await?;
+
+            send_admin_requests(&bots, msg.chat.id, lang, back_page).await?;
+            dialogue.update(State::AdminViewingRequests { current_page: back_page }).await?;
         }
+        _ => {}
     }
 
-    // if let Some(text) = msg.text() {
-    //     bot.send_message(msg.chat.id, "Думаю над ответом...").await?;
-        
-    //     let (tx, rx) = oneshot::channel();
-    //     log_info!("Старт запроса");
-    //     // spawn_client_request_with_callback(tx, text.to_string());
-
-    //     let bot_clone = bot.clone();
-    //     tokio::spawn(async move {
-    //         match rx.await {
-    //             Ok(Ok(text)) => bot_clone.send_message(msg.chat.id, text).await.unwrap(),
-    //             Ok(Err(e)) => bot_clone.send_message(msg.chat.id, e).await.unwrap(),
-    //             Err(_) => bot_clone.send_message(msg.chat.id, "Ошибка связи с сервером").await.unwrap(),
-    //         }
-    //     });
-    // }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Скачивает документ с серверов Telegram и сохраняет его в папку пользователя
+async fn save_document(bots: &TelegramBot, chat_id: ChatId, document: &Document, lang: Lang) -> HandlerResult {
+    let bot = &bots.bot;
+    if document.file.size > MAX_DOCUMENT_MB * 1024 * 1024 {
+        bot.send_message(chat_id, t_args(lang, "files.too_large", &[("size", &MAX_DOCUMENT_MB.to_string())])).await?;
+        return Ok(());
+    }
+
+    let name = document.file_name.clone().unwrap_or_else(|| document.file.unique_id.to_string());
+    let mime_type = document.mime_type.as_ref().map(|mime| mime.to_string());
+    let role = bots.role(chat_id.0).await?;
+    if let Some(rejection) = bots.files.check_upload(chat_id.0, role, &name, mime_type.as_deref(), document.file.size as i64).await? {
+        log_info!("Файл {} от пользователя {} не принят: {:?}", name, chat_id.0, rejection);
+        bot.send_message(chat_id, rejection_text(lang, rejection, mime_type.as_deref()))
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(open_files(lang))
+            .await?;
+        return Ok(());
+    }
+
+    let file = bot.get_file(document.file.id.clone()).await?;
+    let mut content = Vec::with_capacity(document.file.size as usize);
+    bot.inner().download_file(&file.path, &mut content).await?;
+    let size = content.len();
+    // Telegram мог сообщить неточный размер, а за время скачивания могли прийти другие файлы
+    if let Err(rejection) = bots.files.save_user_file(chat_id.0, role, &name, mime_type.as_deref(), content).await? {
+        log_info!("Файл {} от пользователя {} не сохранён: {:?}", name, chat_id.0, rejection);
+        bot.send_message(chat_id, rejection_text(lang, rejection, mime_type.as_deref()))
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(open_files(lang))
+            .await?;
+        return Ok(());
+    }
+    log_info!("Пользователь {} загрузил файл {} ({} байт)", chat_id.0, name, size);
+
+    bot.send_message(chat_id, t_args(lang, "files.saved", &[("name", &escape(&name))]))
+        .parse_mode(ParseMode::MarkdownV2)
+        .reply_markup(open_files(lang))
+        .await?;
+    Ok(())
+}
+
+fn rejection_text(lang: Lang, rejection: UploadRejection, mime_type: Option<&str>) -> String {
+    match rejection {
+        UploadRejection::MimeType => t_args(lang, "files.mime_rejected", &[("mime", &escape(mime_type.unwrap_or("?")))]),
+        UploadRejection::InProgress => t(lang, "files.upload_in_progress"),
+        UploadRejection::FileCount { max } => t_args(lang, "files.quota_files", &[("max", &max.to_string())]),
+        UploadRejection::Storage { used, max } => t_args(lang, "files.quota_bytes", &[
+            ("used", &escape(&format_size(used))),
+            ("max", &escape(&format_size(max))),
+        ]),
+    }
+}
+
+/// Собирает потоковый ответ AI и редактирует сообщение-заглушку не чаще stream_edit_interval
+async fn stream_answer(
+    bots: Arc<TelegramBot>,
+    pending: PendingAnswer,
+    mut rx: mpsc::Receiver<Result<String, String>>,
+) {
+    let bot = &bots.bot;
+    let PendingAnswer { chat_id, message_id, lang, answer_id, charged, question } = pending;
+
+    let deadline = time::sleep(bots.config.ai_timeout);
+    tokio::pin!(deadline);
+    let mut ticker = time::interval(bots.config.stream_edit_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut answer = String::new();
+    let mut shown_len = 0;
+    let mut interrupted = false;
+    let mut timed_out = false;
+
+    loop {
+        tokio::select! {
+            chunk = rx.recv() => match chunk {
+                Some(Ok(text)) => answer.push_str(&text),
+                Some(Err(_)) => {
+                    interrupted = true;
+                    break;
+                }
+                None => break,
+            },
+            _ = ticker.tick() => {
+                if answer.len() != shown_len && !answer.trim().is_empty() {
+                    shown_len = answer.len();
+                    let text = t_args(lang, "ai.partial", &[("answer", &escape(&format!("{}…", truncate_answer(&answer))))]);
+                    if let Err(e) = bot.edit_message_text(chat_id, message_id, text)
+                        .parse_mode(ParseMode::MarkdownV2)
+                        .await
+                    {
+                        log_error!("Ошибка при обновлении ответа AI в чате {}: {}", chat_id.0, e);
+                    }
+                }
+            },
+            _ = &mut deadline => {
+                timed_out = true;
+                break;
+            }
+        }
+    }
+
+    let result = if !answer.trim().is_empty() {
+        // Ответ сохраняем, чтобы пользователь мог его оценить
+        if let Err(e) = bots.db.add_ai_answer(answer_id, chat_id.0, &question, &answer).await {
+            log_error!("Не удалось сохранить ответ AI для чата {}: {}", chat_id.0, e);
+        }
+
+        let note = if interrupted || timed_out { t(lang, "ai.interrupted") } else { String::new() };
+        bot.edit_message_text(chat_id, message_id, t_args(lang, "ai.answer", &[("answer", &escape(&truncate_answer(&answer))), ("note", &note)]))
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(feedback_ai(answer_id))
+            .await
+    } else if timed_out {
+        log_error!("Qortex AI не ответил за {:?} для чата {}", bots.config.ai_timeout, chat_id.0);
+        bot.edit_message_text(chat_id, message_id, t(lang, "ai.timeout"))
+            .parse_mode(ParseMode::MarkdownV2)
+            .await
+    } else if interrupted {
+        bot.edit_message_text(chat_id, message_id, t(lang, "ai.unavailable"))
+            .parse_mode(ParseMode::MarkdownV2)
+            .await
+    } else {
+        bot.edit_message_text(chat_id, message_id, t(lang, "ai.empty"))
+            .parse_mode(ParseMode::MarkdownV2)
+            .await
+    };
+
+    if let Err(e) = result {
+        log_error!("Ошибка при отправке ответа AI в чат {}: {}", chat_id.0, e);
+    }
+
+    // За пустой ответ кредиты не берём
+    if charged && answer.trim().is_empty() {
+        refund_answer(&bots, chat_id, answer_id).await;
+    }
+
+    // В контекст попадают только полные ответы
+    if !interrupted && !timed_out && !answer.trim().is_empty() {
+        bots.conversation.record_turn(chat_id.0, question, answer).await;
+    }
+}
+
+/// Возвращает списание за ответ, ошибка только логируется
+async fn refund_answer(bots: &TelegramBot, chat_id: ChatId, answer_id: Uuid) {
+    if let Err(e) = bots.db.refund(chat_id.0, answer_id).await {
+        log_error!("Не удалось вернуть кредиты за ответ {} в чате {}: {}", answer_id, chat_id.0, e);
+    }
+}
+
+fn truncate_answer(answer: &str) -> String {
+    if answer.chars().count() <= MAX_ANSWER_CHARS {
+        return answer.to_string();
+    }
+    let mut truncated = answer.chars().take(MAX_ANSWER_CHARS).collect::<String>();
+    truncated.push('…');
+    truncated
+}
@@ -1,4 +1,5 @@
 pub mod callback;
 pub mod commands;
 pub mod messages;
-pub mod file_manager;
\ No newline at end of file
+pub mod file_manager;
+pub mod users;
\ No newline at end of file
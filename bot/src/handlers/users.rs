@@ -0,0 +1,236 @@
+use db_pg::{RoleAuditEntry, User, UserRole};
+use localization::{t, t_args, Lang};
+use logging::{log_error, log_info};
+use teloxide::{payloads::SendMessageSetters, prelude::Requester, types::{ChatId, Message, ParseMode}, utils::markdown::escape};
+use uuid::Uuid;
+
+use crate::{handlers::callback::wallet::credits, i18n::{language_label, role_label}, types::HandlerResult, TelegramBot};
+
+/// Сколько последних изменений роли показывается в профиле
+const ROLE_HISTORY: i64 = 5;
+
+/// Пользователь в аргументах админских команд
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserQuery {
+    TelegramId(i64),
+    Uuid(Uuid),
+    Username(String),
+}
+
+impl UserQuery {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Ok(telegram_id) = value.parse() {
+            return Some(Self::TelegramId(telegram_id));
+        }
+        if let Ok(uuid) = Uuid::parse_str(value) {
+            return Some(Self::Uuid(uuid));
+        }
+        let username = value.strip_prefix('@').unwrap_or(value);
+        let valid = (1..=32).contains(&username.len())
+            && username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        valid.then(|| Self::Username(username.to_string()))
+    }
+}
+
+/// Команды смены роли
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoleAction {
+    Promote,
+    Demote,
+    Ban,
+    Unban,
+}
+
+impl RoleAction {
+    /// Новая роль, None если команда к текущей роли неприменима.
+    /// Заблокированного пользователя сначала нужно разблокировать
+    pub fn apply(self, role: UserRole) -> Option<UserRole> {
+        match (self, role) {
+            (Self::Promote, UserRole::Default) => Some(UserRole::WithAccess),
+            (Self::Promote, UserRole::WithAccess) => Some(UserRole::Admin),
+            (Self::Demote, UserRole::Admin) => Some(UserRole::WithAccess),
+            (Self::Demote, UserRole::WithAccess) => Some(UserRole::Default),
+            (Self::Ban, UserRole::Banned) => None,
+            (Self::Ban, _) => Some(UserRole::Banned),
+            (Self::Unban, UserRole::Banned) => Some(UserRole::Default),
+            _ => None,
+        }
+    }
+
+    fn command(self) -> &'static str {
+        match self {
+            Self::Promote => "promote",
+            Self::Demote => "demote",
+            Self::Ban => "ban",
+            Self::Unban => "unban",
+        }
+    }
+}
+
+/// Разбирает `<пользователь> [причина]`
+fn parse_target(args: &str) -> Option<(UserQuery, Option<&str>)> {
+    let args = args.trim();
+    let (query, reason) = match args.split_once(char::is_whitespace) {
+        Some((query, reason)) => (query, Some(reason.trim()).filter(|r| !r.is_empty())),
+        None => (args, None),
+    };
+    Some((UserQuery::parse(query)?, reason))
+}
+
+pub async fn find_user(bots: &TelegramBot, query: &UserQuery) -> HandlerResult<Option<User>> {
+    let user = match query {
+        UserQuery::TelegramId(telegram_id) => bots.db.get_user_by_telegram_id(*telegram_id).await?,
+        UserQuery::Uuid(uuid) => bots.db.get_user(*uuid).await?,
+        UserQuery::Username(username) => bots.db.find_by_username(username).await?,
+    };
+    Ok(user)
+}
+
+fn audit_line(lang: Lang, entry: &RoleAuditEntry) -> String {
+    let reason = entry.reason
+        .as_deref()
+        .map(|reason| t_args(lang, "users.reason", &[("reason", &escape(reason))]))
+        .unwrap_or_default();
+    t_args(lang, "users.history_entry", &[
+        ("date", &escape(&entry.created_at.format("%d.%m.%Y %H:%M").to_string())),
+        ("old", &escape(&role_label(lang, entry.old_role))),
+        ("new", &escape(&role_label(lang, entry.new_role))),
+        ("by", &entry.changed_by.to_string()),
+        ("reason", &reason),
+    ])
+}
+
+fn profile_text(lang: Lang, user: &User, balance: i64, audit: &[RoleAuditEntry]) -> String {
+    let username = match user.username.as_deref() {
+        Some(username) if username != "None" => format!("@{}", username),
+        _ => t(lang, "users.no_username"),
+    };
+    let history = if audit.is_empty() {
+        String::new()
+    } else {
+        let entries: String = audit.iter().map(|entry| audit_line(lang, entry)).collect();
+        t_args(lang, "users.history", &[("entries", &entries)])
+    };
+    t_args(lang, "users.card", &[
+        ("id", &user.telegram_id.to_string()),
+        ("username", &escape(&username)),
+        ("uuid", &user.uuid.to_string()),
+        ("role", &escape(&role_label(lang, user.role))),
+        ("language", &escape(&language_label(lang, user.language.as_deref().and_then(Lang::from_code)))),
+        ("balance", &escape(&credits(lang, balance))),
+        ("history", &history),
+    ])
+}
+
+/// /user: профиль пользователя с последними изменениями роли
+pub async fn show_user(bots: &TelegramBot, msg: &Message, lang: Lang, args: &str) -> HandlerResult {
+    let Some((query, _)) = parse_target(args) else {
+        bots.bot.send_message(msg.chat.id, t_args(lang, "users.usage", &[("command", "user")])).await?;
+        return Ok(());
+    };
+    let Some(user) = find_user(bots, &query).await? else {
+        bots.bot.send_message(msg.chat.id, t(lang, "users.not_found")).await?;
+        return Ok(());
+    };
+
+    let balance = bots.db.get_balance(user.telegram_id).await?;
+    let audit = bots.db.get_role_audit(user.telegram_id, ROLE_HISTORY).await?;
+    bots.bot.send_message(msg.chat.id, profile_text(lang, &user, balance, &audit))
+        .parse_mode(ParseMode::MarkdownV2)
+        .await?;
+    Ok(())
+}
+
+/// /promote, /demote, /ban, /unban: меняет роль и пишет изменение в журнал
+pub async fn change_role(bots: &TelegramBot, msg: &Message, lang: Lang, action: RoleAction, args: &str) -> HandlerResult {
+    // В группе id чата не id администратора: и проверка прав, и журнал идут по отправителю
+    let Some(admin_id) = msg.from.as_ref().map(|from| from.id.0 as i64) else {
+        return Ok(());
+    };
+    let Some((query, reason)) = parse_target(args) else {
+        bots.bot.send_message(msg.chat.id, t_args(lang, "users.usage", &[("command", action.command())])).await?;
+        return Ok(());
+    };
+    let Some(user) = find_user(bots, &query).await? else {
+        bots.bot.send_message(msg.chat.id, t(lang, "users.not_found")).await?;
+        return Ok(());
+    };
+    // Администратор не может случайно лишить доступа сам себя
+    if user.telegram_id == admin_id {
+        bots.bot.send_message(msg.chat.id, t(lang, "users.self_change")).await?;
+        return Ok(());
+    }
+    let Some(role) = action.apply(user.role) else {
+        bots.bot.send_message(msg.chat.id, t_args(lang, "users.unchanged", &[("role", &role_label(lang, user.role))])).await?;
+        return Ok(());
+    };
+
+    // Роль сменится, только если под блокировкой строки она всё ещё та, от которой считали новую
+    let old_role = match bots.db.set_role(user.telegram_id, user.role, role, admin_id, reason).await? {
+        Some(old_role) if old_role == user.role => old_role,
+        Some(current) => {
+            bots.bot.send_message(msg.chat.id, t_args(lang, "users.unchanged", &[("role", &role_label(lang, current))])).await?;
+            return Ok(());
+        }
+        None => {
+            bots.bot.send_message(msg.chat.id, t(lang, "users.not_found")).await?;
+            return Ok(());
+        }
+    };
+    // Триггер в Postgres тоже сбросит кэш, но ответ администратору не должен его ждать
+    bots.roles.invalidate(user.telegram_id);
+    log_info!("Администратор {} изменил роль пользователя {}: {:?} -> {:?}", admin_id, user.telegram_id, old_role, role);
+
+    bots.bot.send_message(msg.chat.id, t_args(lang, "users.changed", &[
+        ("id", &user.telegram_id.to_string()),
+        ("old", &escape(&role_label(lang, old_role))),
+        ("new", &escape(&role_label(lang, role))),
+    ]))
+        .parse_mode(ParseMode::MarkdownV2)
+        .await?;
+
+    // Заблокированному о блокировке скажет сам бот при следующем сообщении
+    if role != UserRole::Banned {
+        let user_lang = bots.stored_lang(user.telegram_id).await.unwrap_or_default();
+        let notice = t_args(user_lang, "users.notice", &[("role", &role_label(user_lang, role))]);
+        if let Err(e) = bots.bot.send_message(ChatId(user.telegram_id), notice).await {
+            log_error!("Не удалось уведомить {} о смене роли: {}", user.telegram_id, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_queries() {
+        assert_eq!(UserQuery::parse("42"), Some(UserQuery::TelegramId(42)));
+        assert_eq!(UserQuery::parse("@qortex_user"), Some(UserQuery::Username("qortex_user".to_string())));
+        assert_eq!(UserQuery::parse("qortex_user"), Some(UserQuery::Username("qortex_user".to_string())));
+        let uuid = Uuid::new_v4();
+        assert_eq!(UserQuery::parse(&uuid.to_string()), Some(UserQuery::Uuid(uuid)));
+        assert_eq!(UserQuery::parse(""), None);
+        assert_eq!(UserQuery::parse("@"), None);
+        assert_eq!(UserQuery::parse("@bad-name"), None);
+
+        assert_eq!(parse_target("@qortex_user  спам в чате "), Some((UserQuery::Username("qortex_user".to_string()), Some("спам в чате"))));
+        assert_eq!(parse_target("42"), Some((UserQuery::TelegramId(42), None)));
+    }
+
+    #[test]
+    fn role_actions() {
+        assert_eq!(RoleAction::Promote.apply(UserRole::Default), Some(UserRole::WithAccess));
+        assert_eq!(RoleAction::Promote.apply(UserRole::WithAccess), Some(UserRole::Admin));
+        assert_eq!(RoleAction::Promote.apply(UserRole::Admin), None);
+        assert_eq!(RoleAction::Demote.apply(UserRole::Admin), Some(UserRole::WithAccess));
+        assert_eq!(RoleAction::Demote.apply(UserRole::Default), None);
+        assert_eq!(RoleAction::Ban.apply(UserRole::Admin), Some(UserRole::Banned));
+        assert_eq!(RoleAction::Ban.apply(UserRole::Banned), None);
+        assert_eq!(RoleAction::Promote.apply(UserRole::Banned), None);
+        assert_eq!(RoleAction::Unban.apply(UserRole::Banned), Some(UserRole::Default));
+        assert_eq!(RoleAction::Unban.apply(UserRole::Default), None);
+    }
+}
@@ -0,0 +1,59 @@
+use db_pg::{AnswerStyle, MessageStatus, UserRole};
+use localization::{t, Lang};
+use logging::log_error;
+use teloxide::types::User;
+
+use crate::TelegramBot;
+
+impl TelegramBot {
+    /// Язык интерфейса: выбранный в боте, затем `language_code` из Telegram, иначе язык по умолчанию
+    pub async fn lang(&self, user: &User) -> Lang {
+        if let Some(lang) = self.stored_lang(user.id.0 as i64).await {
+            return lang;
+        }
+        user.language_code.as_deref().and_then(Lang::from_code).unwrap_or_default()
+    }
+
+    /// Язык, сохранённый пользователем в `users.language`
+    pub async fn stored_lang(&self, telegram_id: i64) -> Option<Lang> {
+        match self.db.get_user_language(telegram_id).await {
+            Ok(code) => code.as_deref().and_then(Lang::from_code),
+            Err(e) => {
+                log_error!("Не удалось получить язык пользователя {}: {}", telegram_id, e);
+                None
+            }
+        }
+    }
+}
+
+pub fn status_label(lang: Lang, status: MessageStatus) -> String {
+    let key = match status {
+        MessageStatus::Pending => "status.pending",
+        MessageStatus::Accepted => "status.accepted",
+        MessageStatus::Answered => "status.answered",
+        MessageStatus::Rejected => "status.rejected",
+    };
+    t(lang, key)
+}
+
+pub fn role_label(lang: Lang, role: UserRole) -> String {
+    let key = match role {
+        UserRole::Default => "role.default",
+        UserRole::WithAccess => "role.with_access",
+        UserRole::Admin => "role.admin",
+        UserRole::Banned => "role.banned",
+    };
+    t(lang, key)
+}
+
+/// Название языка для настроек, `None` — язык из Telegram
+pub fn language_label(lang: Lang, language: Option<Lang>) -> String {
+    match language {
+        Some(language) => t(lang, &format!("settings.language.{}", language.code())),
+        None => t(lang, "settings.language.auto"),
+    }
+}
+
+pub fn style_label(lang: Lang, style: AnswerStyle) -> String {
+    t(lang, &format!("settings.style.{}", style.code()))
+}
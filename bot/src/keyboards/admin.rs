@@ -0,0 +1,42 @@
+use db_pg::{Message, MessageStatus};
+use localization::{t, Lang};
+use teloxide::types::InlineKeyboardMarkup;
+
+use crate::{callback_data::CallbackData, keyboards::requests::{messages_page, page_slice, short_text, total_pages}};
+
+/// Список обращений для модерации
+pub fn admin_requests(messages: &[Message], current_page: usize) -> InlineKeyboardMarkup {
+    let (page_messages, current_page) = page_slice(messages, current_page);
+    messages_page(page_messages, current_page, total_pages(messages.len()), CallbackData::AdminMessage, CallbackData::AdminPage, status_label)
+}
+
+fn status_label(msg: &Message) -> String {
+    let icon = match msg.status {
+        MessageStatus::Pending => "🕓",
+        MessageStatus::Accepted => "🛠",
+        MessageStatus::Answered => "✅",
+        MessageStatus::Rejected => "⛔",
+    };
+    format!("{} {}", icon, short_text(msg))
+}
+
+/// Действия над одним обращением
+pub fn admin_request_actions(lang: Lang, message: &Message, back_page: usize) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+
+    if message.status == MessageStatus::Pending {
+        keyboard = keyboard.append_row(vec![CallbackData::AdminAccept(message.id).button(t(lang, "admin.accept_button"))]);
+    }
+    let answer = CallbackData::AdminAnswer(message.id).button(t(lang, "admin.answer_button"));
+    let reject = CallbackData::AdminReject(message.id).button(t(lang, "admin.reject_button"));
+    let back = CallbackData::AdminPage(back_page).button(t(lang, "admin.back_button"));
+
+    keyboard.append_row(vec![answer, reject]).append_row(vec![back])
+}
+
+/// Отмена ввода ответа, возвращает к карточке обращения
+pub fn admin_answer_cancel(lang: Lang, message: &Message) -> InlineKeyboardMarkup {
+    let cancel = CallbackData::AdminMessage(message.id).button(t(lang, "admin.cancel_button"));
+
+    InlineKeyboardMarkup::default().append_row(vec![cancel])
+}
@@ -1,25 +1,45 @@
-use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use localization::{t, Lang};
+use teloxide::types::InlineKeyboardMarkup;
+use uuid::Uuid;
 
-pub fn faq() -> InlineKeyboardMarkup {
-    let q1 = InlineKeyboardButton::callback("🤔 Чем полезен этот бот?", "profits");
+use crate::callback_data::CallbackData;
+
+pub fn faq(lang: Lang) -> InlineKeyboardMarkup {
+    let q1 = CallbackData::Profits.button(t(lang, "faq.profits_button"));
     
     // Необходимо если вызывается из Inline menu
-    let back_to_faq = InlineKeyboardButton::callback("⬅️", "back_to_menu");
+    let back_to_faq = CallbackData::BackToMenu.button("⬅️");
     
     InlineKeyboardMarkup::default().append_row(vec![q1]).append_row(vec![back_to_faq])
 }
 
-pub fn feedback_ai() -> InlineKeyboardMarkup {
-    let yes = InlineKeyboardButton::callback("✅", "fb_yes");
-    let no  = InlineKeyboardButton::callback("⛔", "fb_no");
-    let back_to_faq = InlineKeyboardButton::callback("⬅️", "back_to_faq");
+pub fn feedback_ai(answer_id: Uuid) -> InlineKeyboardMarkup {
+    let yes = CallbackData::FeedbackYes(answer_id).button("✅");
+    let no  = CallbackData::FeedbackNo(answer_id).button("⛔");
+    let back_to_faq = CallbackData::BackToFaq.button("⬅️");
 
     InlineKeyboardMarkup::default().append_row(vec![yes, no]).append_row(vec![back_to_faq])
 }
 
+/// Заменяет клавиатуру оценки после ответа пользователя
+pub fn feedback_thanks(lang: Lang) -> InlineKeyboardMarkup {
+    let thanks = CallbackData::FeedbackThanks.button(t(lang, "feedback.thanks_button"));
+    let back_to_faq = CallbackData::BackToFaq.button("⬅️");
+
+    InlineKeyboardMarkup::default().append_row(vec![thanks]).append_row(vec![back_to_faq])
+}
+
+/// После отрицательной оценки предлагаем передать вопрос администратору
+pub fn feedback_escalate(lang: Lang, answer_id: Uuid) -> InlineKeyboardMarkup {
+    let escalate = CallbackData::FeedbackEscalate(answer_id).button(t(lang, "feedback.escalate_button"));
+    let back_to_faq = CallbackData::BackToFaq.button("⬅️");
+
+    InlineKeyboardMarkup::default().append_row(vec![escalate]).append_row(vec![back_to_faq])
+}
+
 pub fn profits() -> InlineKeyboardMarkup {
-    let back_to_faq = InlineKeyboardButton::callback("⬅️", "back_to_faq");
+    let back_to_faq = CallbackData::BackToFaq.button("⬅️");
     // Ещё вопросы если надо
 
     InlineKeyboardMarkup::default().append_row(vec![back_to_faq])
-}
\ No newline at end of file
+}
@@ -0,0 +1,46 @@
+use db_pg::StoredFile;
+use localization::{t, Lang};
+use teloxide::types::InlineKeyboardMarkup;
+use uuid::Uuid;
+
+use crate::{callback_data::CallbackData, keyboards::requests::create_navigation_row};
+
+/// Длина имени файла на кнопке
+const LABEL_CHARS: usize = 30;
+
+/// Страница файлов пользователя, `files` уже содержит только эту страницу
+pub fn files(files: &[StoredFile], current_page: usize, total_pages: usize) -> InlineKeyboardMarkup {
+    let mut rows = files
+        .iter()
+        .map(|file| vec![CallbackData::File(file.id).button(format!("📄 {}", short_name(&file.original_name)))])
+        .collect::<Vec<_>>();
+
+    if total_pages > 1 {
+        rows.push(create_navigation_row(current_page, total_pages, CallbackData::FilesPage));
+    }
+
+    InlineKeyboardMarkup::new(rows).append_row(vec![CallbackData::BackToMenu.button("⬅️")])
+}
+
+pub fn file_actions(lang: Lang, file_id: Uuid) -> InlineKeyboardMarkup {
+    let share = CallbackData::FileShare(file_id).button(t(lang, "files.share_button"));
+    let delete = CallbackData::FileDelete(file_id).button(t(lang, "files.delete_button"));
+    let back_to_files = CallbackData::FileSharing.button("⬅️");
+
+    InlineKeyboardMarkup::default().append_row(vec![share, delete]).append_row(vec![back_to_files])
+}
+
+/// Под сообщением о загруженном файле
+pub fn open_files(lang: Lang) -> InlineKeyboardMarkup {
+    let files = CallbackData::FileSharing.button(t(lang, "files.list_button"));
+
+    InlineKeyboardMarkup::default().append_row(vec![files])
+}
+
+fn short_name(name: &str) -> String {
+    if name.chars().count() > LABEL_CHARS {
+        format!("{}...", name.chars().take(LABEL_CHARS).collect::<String>())
+    } else {
+        name.to_string()
+    }
+}
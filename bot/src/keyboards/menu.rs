@@ -1,10 +1,14 @@
-use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use localization::{t, Lang};
+use teloxide::types::InlineKeyboardMarkup;
 
-pub fn menu() -> InlineKeyboardMarkup {
-    let file_sharing = InlineKeyboardButton::callback("Обменник 🔁", "file_sharing");
-    let my_requests = InlineKeyboardButton::callback("Мои обращения 📖", "my_requests");
-    let settings = InlineKeyboardButton::callback("⚙️", "settings");
-    let faq = InlineKeyboardButton::callback("FAQ ℹ️", "faq");
+use crate::callback_data::CallbackData;
 
-    InlineKeyboardMarkup::default().append_row(vec![file_sharing, my_requests]).append_row(vec![settings, faq])
+pub fn menu(lang: Lang) -> InlineKeyboardMarkup {
+    let file_sharing = CallbackData::FileSharing.button(t(lang, "menu.file_sharing"));
+    let my_requests = CallbackData::MyRequests.button(t(lang, "menu.my_requests"));
+    let wallet = CallbackData::Wallet.button(t(lang, "menu.wallet"));
+    let settings = CallbackData::Settings.button("⚙️");
+    let faq = CallbackData::Faq.button(t(lang, "menu.faq"));
+
+    InlineKeyboardMarkup::default().append_row(vec![file_sharing, my_requests]).append_row(vec![wallet]).append_row(vec![settings, faq])
 }
\ No newline at end of file
@@ -4,4 +4,5 @@ pub mod menu;
 pub mod wallet;
 pub mod settings;
 pub mod file_sharing;
-pub mod requests;
\ No newline at end of file
+pub mod requests;
+pub mod admin;
\ No newline at end of file
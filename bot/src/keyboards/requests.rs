@@ -1,72 +1,92 @@
 use db_pg::Message;
+use localization::{t, Lang};
 use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use uuid::Uuid;
 
-pub fn history() -> InlineKeyboardMarkup {
-    let all = InlineKeyboardButton::callback("Все собщения", "all_requests");
-    let answered = InlineKeyboardButton::callback("С ответом", "answered_requests");
-    let accepted = InlineKeyboardButton::callback("Принятые", "accepted_requests");
-    let back_to_menu = InlineKeyboardButton::callback("⬅️", "back_to_menu");
+use crate::callback_data::CallbackData;
 
-    InlineKeyboardMarkup::default().append_row(vec![all]).append_row(vec![answered, accepted])
+pub fn history(lang: Lang) -> InlineKeyboardMarkup {
+    let all = CallbackData::AllRequests.button(t(lang, "requests.all_button"));
+    let answered = CallbackData::AnsweredRequests.button(t(lang, "requests.answered_button"));
+    let accepted = CallbackData::AcceptedRequests.button(t(lang, "requests.accepted_button"));
+    let back_to_menu = CallbackData::BackToMenu.button("⬅️");
+
+    InlineKeyboardMarkup::default().append_row(vec![all]).append_row(vec![answered, accepted]).append_row(vec![back_to_menu])
 }
 
 pub const ITEMS_PER_PAGE: usize = 10;
 
-pub fn all_messages(messages: Vec<Message>, current_page: usize) -> InlineKeyboardMarkup {
-    let total_pages = (messages.len().max(1) + ITEMS_PER_PAGE - 1) / ITEMS_PER_PAGE;
-    let current_page = current_page.min(total_pages.saturating_sub(1));
+/// Страница обращений пользователя, `messages` уже содержит только эту страницу
+pub fn all_messages(messages: &[Message], current_page: usize, total_pages: usize) -> InlineKeyboardMarkup {
+    let back_to_history = CallbackData::MyRequests.button("⬅️");
 
-    let start_idx = current_page * ITEMS_PER_PAGE;
-    let end_idx = (start_idx + ITEMS_PER_PAGE).min(messages.len());
+    messages_page(messages, current_page, total_pages, CallbackData::Message, CallbackData::Page, short_text).append_row(vec![back_to_history])
+}
 
-    let mut rows = messages[start_idx..end_idx]
+/// Страница списка обращений: кнопка на каждое сообщение и панель навигации.
+/// `item` и `page` задают callback кнопок сообщений и страниц
+pub fn messages_page(
+    page_messages: &[Message],
+    current_page: usize,
+    total_pages: usize,
+    item: fn(Uuid) -> CallbackData,
+    page: fn(usize) -> CallbackData,
+    label: fn(&Message) -> String,
+) -> InlineKeyboardMarkup {
+    let mut rows = page_messages
         .iter()
         .map(|msg| {
-            let text = msg.text.chars().take(10).collect::<String>();
-            let button_text = if msg.text.chars().count() > 10 {
-                format!("{}...", text)
-            } else {
-                text
-            };
-            vec![InlineKeyboardButton::callback(
-                button_text,
-                format!("msg_{}", msg.id)
-            )]
+            vec![item(msg.id).button(label(msg))]
         })
         .collect::<Vec<_>>();
 
     // Добавляем панель навигации, если нужно
     if total_pages > 1 {
-        let navigation_row = create_navigation_row(current_page, total_pages);
+        let navigation_row = create_navigation_row(current_page, total_pages, page);
         rows.push(navigation_row);
     }
 
     InlineKeyboardMarkup::new(rows)
 }
 
-pub fn create_navigation_row(current_page: usize, total_pages: usize) -> Vec<InlineKeyboardButton> {
+/// Срез одной страницы из полного списка, номер страницы ограничивается последней
+pub fn page_slice(messages: &[Message], current_page: usize) -> (&[Message], usize) {
+    let current_page = current_page.min(total_pages(messages.len()) - 1);
+    let start_idx = current_page * ITEMS_PER_PAGE;
+    let end_idx = (start_idx + ITEMS_PER_PAGE).min(messages.len());
+
+    (&messages[start_idx..end_idx], current_page)
+}
+
+/// Количество страниц, пустой список занимает одну страницу
+pub fn total_pages(items: usize) -> usize {
+    items.max(1).div_ceil(ITEMS_PER_PAGE)
+}
+
+/// Первые 10 символов текста обращения
+pub fn short_text(msg: &Message) -> String {
+    let text = msg.text.chars().take(10).collect::<String>();
+    if msg.text.chars().count() > 10 {
+        format!("{}...", text)
+    } else {
+        text
+    }
+}
+
+pub fn create_navigation_row(current_page: usize, total_pages: usize, page: fn(usize) -> CallbackData) -> Vec<InlineKeyboardButton> {
     let mut buttons = Vec::new();
     
     // Кнопка "Назад"
     if current_page > 0 {
-        buttons.push(InlineKeyboardButton::callback(
-            "⬅️",
-            format!("page_{}", current_page.saturating_sub(1))
-        ));
+        buttons.push(page(current_page.saturating_sub(1)).button("⬅️"));
     }
     
     // Кнопка с текущей страницей
-    buttons.push(InlineKeyboardButton::callback(
-        format!("{}/{}", current_page + 1, total_pages),
-        "current_page".to_string()
-    ));
+    buttons.push(CallbackData::CurrentPage.button(format!("{}/{}", current_page + 1, total_pages)));
     
     // Кнопка "Вперед"
     if current_page < total_pages - 1 {
-        buttons.push(InlineKeyboardButton::callback(
-            "➡️",
-            format!("page_{}", current_page + 1)
-        ));
+        buttons.push(page(current_page + 1).button("➡️"));
     }
     
     buttons
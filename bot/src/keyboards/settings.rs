@@ -0,0 +1,43 @@
+use db_pg::AnswerStyle;
+use localization::{t, Lang};
+use teloxide::types::InlineKeyboardMarkup;
+
+use crate::{callback_data::CallbackData, i18n::{language_label, style_label}};
+
+pub fn settings(lang: Lang, notifications: bool) -> InlineKeyboardMarkup {
+    let language = CallbackData::SettingsLanguage.button(t(lang, "settings.language_button"));
+    let style = CallbackData::SettingsStyle.button(t(lang, "settings.style_button"));
+    let notifications_key = if notifications { "settings.notifications_on_button" } else { "settings.notifications_off_button" };
+    let notifications = CallbackData::ToggleNotifications.button(t(lang, notifications_key));
+    let back_to_menu = CallbackData::BackToMenu.button("⬅️");
+
+    InlineKeyboardMarkup::default()
+        .append_row(vec![language])
+        .append_row(vec![style])
+        .append_row(vec![notifications])
+        .append_row(vec![back_to_menu])
+}
+
+/// Выбор языка, текущий отмечен галочкой
+pub fn settings_language(lang: Lang, current: Option<Lang>) -> InlineKeyboardMarkup {
+    let choices = std::iter::once(None).chain(Lang::ALL.into_iter().map(Some));
+    let rows = choices
+        .map(|choice| vec![CallbackData::SetLanguage(choice).button(checked(language_label(lang, choice), choice == current))])
+        .collect::<Vec<_>>();
+
+    InlineKeyboardMarkup::new(rows).append_row(vec![CallbackData::Settings.button("⬅️")])
+}
+
+/// Выбор стиля ответов AI, текущий отмечен галочкой
+pub fn settings_style(lang: Lang, current: AnswerStyle) -> InlineKeyboardMarkup {
+    let rows = AnswerStyle::ALL
+        .into_iter()
+        .map(|style| vec![CallbackData::SetStyle(style).button(checked(style_label(lang, style), style == current))])
+        .collect::<Vec<_>>();
+
+    InlineKeyboardMarkup::new(rows).append_row(vec![CallbackData::Settings.button("⬅️")])
+}
+
+fn checked(label: String, is_current: bool) -> String {
+    if is_current { format!("✅ {}", label) } else { label }
+}
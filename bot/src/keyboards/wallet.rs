@@ -0,0 +1,17 @@
+use localization::{t, Lang};
+use teloxide::types::InlineKeyboardMarkup;
+
+use crate::callback_data::CallbackData;
+
+pub fn wallet() -> InlineKeyboardMarkup {
+    let back_to_menu = CallbackData::BackToMenu.button("⬅️");
+
+    InlineKeyboardMarkup::default().append_row(vec![back_to_menu])
+}
+
+/// Под сообщением о нехватке кредитов
+pub fn open_wallet(lang: Lang) -> InlineKeyboardMarkup {
+    let wallet = CallbackData::Wallet.button(t(lang, "menu.wallet"));
+
+    InlineKeyboardMarkup::default().append_row(vec![wallet])
+}
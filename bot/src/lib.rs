@@ -1,61 +1,125 @@
-use db_pg::UserRepository;
-use handlers::{commands::{command_handler, Commander}, messages};
+use config::{BotConfig, DialogueStorageKind, FileStorageKind};
+use conversation::Conversation;
+use db::Database;
+use db_pg::{PgDialogueStorage, Store, UserRepository};
+use handlers::{commands::{command_handler, Commander}, file_manager::FileManager, messages};
 use logging::{log_error, log_info, logger::setup_logger};
 use dotenvy::dotenv;
 use state::State;
-use teloxide::{adaptors::{throttle::Limits}, dispatching::dialogue::InMemStorage, prelude::*};
-use types::MyBot;
-use std::{env, sync::Arc};
+use teloxide::{adaptors::{throttle::Limits}, dispatching::{dialogue::{InMemStorage, Storage}, UpdateHandler}, error_handlers::IgnoringErrorHandler, prelude::*, RequestError};
+use tokio::sync::OnceCell;
+use permissions::RoleCache;
+use types::{MyBot, MyStorage};
+use std::{env, error::Error, path::Path, sync::Arc};
+use recorder::UpdateRecorder;
+use storage::{FileStorage, LocalStorage, S3Storage};
 
-use crate::handlers::callback::{callback_handler, CallbackHandlerFactory};
+use crate::handlers::callback::{callback_handler, CallbackRegistry};
 
 pub mod keyboards;
+pub mod callback_data;
 mod handlers;
 pub mod state;
 pub mod types;
-mod error;
+pub mod config;
+pub mod conversation;
+pub mod notifications;
+pub mod i18n;
+pub mod storage;
+pub mod download_server;
+pub mod permissions;
+pub mod error;
+pub mod recorder;
+pub mod replay;
 
 pub struct TelegramBot {
     pub bot: MyBot,
-    pub storage: Arc<InMemStorage<State>>,
-    pub db: Arc<UserRepository>,
-    pub callback_handlers: Arc<CallbackHandlerFactory>,
+    pub storage: Arc<MyStorage>,
+    pub db: Arc<dyn Store>,
+    pub callback_handlers: Arc<CallbackRegistry>,
+    pub conversation: Conversation,
+    pub files: FileManager,
+    pub roles: RoleCache,
+    pub config: BotConfig,
+    /// Username бота для deep link, запрашивается у Telegram один раз
+    username: OnceCell<String>,
 }
 
 impl TelegramBot {
     /// Create Bot Copy
-    pub async fn new(bot_token: String, db: Arc<UserRepository>) -> Arc<Self> {
+    pub async fn new(bot_token: String, db: Arc<UserRepository>, history: Option<Arc<Database>>, config: &BotConfig) -> Arc<Self> {
         let bot = Bot::new(bot_token).throttle(Limits::default());
-        let storage = InMemStorage::<State>::new();
-        let callback_handlers = Arc::new(CallbackHandlerFactory::new());
-        Arc::new(TelegramBot { bot, storage, db, callback_handlers })
+        let storage: Arc<MyStorage> = match config.dialogue_storage {
+            DialogueStorageKind::InMemory => InMemStorage::<State>::new().erase(),
+            DialogueStorageKind::Postgres => PgDialogueStorage::<State>::new(db.pool.clone()).erase(),
+        };
+        log_info!("Хранилище диалогов: {:?}", config.dialogue_storage);
+        let file_storage: Arc<dyn FileStorage> = match config.file_storage {
+            FileStorageKind::Local => Arc::new(
+                LocalStorage::new(&config.file_storage_path).await.expect("Не удалось создать хранилище файлов")
+            ),
+            FileStorageKind::S3 => Arc::new(S3Storage::new(
+                config.s3.clone().expect("Для FILE_STORAGE=s3 нужны S3_ENDPOINT, S3_BUCKET, S3_ACCESS_KEY и S3_SECRET_KEY")
+            )),
+        };
+        log_info!("Хранилище файлов: {:?}", config.file_storage);
+        Self::with_parts(bot, db, storage, file_storage, history, config)
+    }
+
+    /// Бот из готовых частей, в тестах база подменяется на `MemoryStore`
+    pub fn with_parts(
+        bot: MyBot,
+        db: Arc<dyn Store>,
+        storage: Arc<MyStorage>,
+        file_storage: Arc<dyn FileStorage>,
+        history: Option<Arc<Database>>,
+        config: &BotConfig,
+    ) -> Arc<Self> {
+        let callback_handlers = Arc::new(CallbackRegistry::new());
+        let conversation = Conversation::new(history, config.history_turns);
+        let files = FileManager::new(file_storage, db.clone(), config);
+        let roles = RoleCache::new(config.role_cache_ttl);
+        Arc::new(TelegramBot { bot, storage, db, callback_handlers, conversation, files, roles, config: config.clone(), username: OnceCell::new() })
+    }
+
+    /// Username бота без @
+    pub async fn username(&self) -> Result<&str, RequestError> {
+        let username = self.username
+            .get_or_try_init(|| async { Ok::<_, RequestError>(self.bot.get_me().await?.username().to_string()) })
+            .await?;
+        Ok(username)
+    }
+
+    /// Запись апдейтов, если задан UPDATE_RECORD_PATH
+    async fn recorder(&self) -> Option<Arc<UpdateRecorder>> {
+        let path = self.config.update_record_path.as_ref()?;
+        match UpdateRecorder::open(path, self.config.update_record_key.as_deref()).await {
+            Ok(recorder) => Some(Arc::new(recorder)),
+            Err(e) => {
+                log_error!("Не удалось открыть {} для записи апдейтов: {}", path.display(), e);
+                None
+            }
+        }
     }
 
     /// Bot Start
     pub async fn run(self: Arc<Self>) {
-        let handler = dptree::entry()
-            .branch(
-                Update::filter_message()
-                    .branch(
-                    dptree::entry().filter_command::<Commander>().enter_dialogue::<Message, InMemStorage<State>, State>().endpoint(
-                        |bot: Arc<TelegramBot>, dialogue, msg, cmd: Commander| async move {
-                            command_handler(bot, dialogue, msg, cmd).await
-                        }
-                    ))
-                    .branch(
-                        dptree::entry().enter_dialogue::<Message, InMemStorage<State>, State>().endpoint(
-                            |bot: Arc<TelegramBot>, dialogue, msg| async move {
-                                messages::default_messages(bot, dialogue, msg).await
-                            }
-                        )
-                    )
-            ).branch(
-                Update::filter_callback_query().enter_dialogue::<CallbackQuery, InMemStorage<State>, State>().endpoint(
-                    |bot: Arc<TelegramBot>, dialogue, q| async move {
-                        callback_handler(bot, dialogue, q).await
-                    }
-                )
-            );
+        tokio::spawn(notifications::run_status_notifier(self.clone()));
+        tokio::spawn(handlers::file_manager::run_link_cleanup(self.clone()));
+        tokio::spawn(permissions::run_role_invalidation(self.clone()));
+        if let Some(addr) = self.config.download_server_addr {
+            tokio::spawn(download_server::run_download_server(self.clone(), addr));
+        }
+
+        let handler = match self.recorder().await {
+            Some(recorder) => dptree::entry()
+                .inspect_async(move |update: Update| {
+                    let recorder = recorder.clone();
+                    async move { recorder.record(&update).await }
+                })
+                .chain(schema()),
+            None => schema(),
+        };
 
         // Dispatch builder and starter
         Dispatcher::builder(self.bot.clone(), handler)
@@ -63,6 +127,8 @@ impl TelegramBot {
                 self.clone(),
                 self.storage.clone()
             ])
+            // Ошибки обработчиков уже залогированы в error::report
+            .error_handler(IgnoringErrorHandler::new())
             .enable_ctrlc_handler()
             .build()
             .dispatch()
@@ -70,6 +136,95 @@ impl TelegramBot {
     }
 }
 
+/// Дерево обработчиков апдейтов. Его же использует `replay` для записанных апдейтов
+pub fn schema() -> UpdateHandler<Box<dyn Error + Send + Sync>> {
+    dptree::entry()
+        .branch(
+            Update::filter_message().filter_map_async(permissions::sender_role)
+                .branch(
+                    // Команда без нужной роли получает отказ и не уходит в обычные сообщения
+                    dptree::entry().filter_command::<Commander>()
+                        .branch(
+                            dptree::filter(permissions::command_allowed).enter_dialogue::<Message, MyStorage, State>().endpoint(
+                                |bot: Arc<TelegramBot>, dialogue, msg: Message, cmd: Commander| async move {
+                                    let (chat_id, user) = (msg.chat.id, msg.from.clone());
+                                    let result = command_handler(bot.clone(), dialogue, msg, cmd).await;
+                                    error::report(&bot, chat_id, user.as_ref(), result).await
+                                }
+                            )
+                        )
+                        .endpoint(permissions::deny_message)
+                )
+                .branch(
+                    dptree::filter(permissions::message_allowed).enter_dialogue::<Message, MyStorage, State>().endpoint(
+                        |bot: Arc<TelegramBot>, dialogue, msg: Message| async move {
+                            let (chat_id, user) = (msg.chat.id, msg.from.clone());
+                            let result = messages::default_messages(bot.clone(), dialogue, msg).await;
+                            error::report(&bot, chat_id, user.as_ref(), result).await
+                        }
+                    )
+                )
+                .endpoint(permissions::deny_message)
+        ).branch(
+            Update::filter_callback_query().filter_map_async(permissions::sender_role)
+                .branch(
+                    dptree::filter(permissions::callback_allowed).enter_dialogue::<CallbackQuery, MyStorage, State>().endpoint(
+                        |bot: Arc<TelegramBot>, dialogue, q: CallbackQuery| async move {
+                            let user = q.from.clone();
+                            let result = callback_handler(bot.clone(), dialogue, q).await;
+                            error::report(&bot, user.id.into(), Some(&user), result).await
+                        }
+                    )
+                )
+                .endpoint(permissions::deny_callback)
+        )
+}
+
+/// Применяет миграции базы данных и завершается
+pub async fn migrate() {
+    dotenv().ok();
+    setup_logger().expect("Не удалось настроить логгер");
+
+    let url = env::var("DB_URL").expect("Ошибка при получение DB_URL из .env");
+    let repo = UserRepository::new(&url).await.unwrap();
+    if let Err(e) = repo.migrate().await {
+        log_error!("Ошибка миграции базы данных: {}", e);
+        std::process::exit(1);
+    }
+    match repo.schema_version().await {
+        Ok(version) => log_info!("Миграции применены, версия схемы: {}", version.unwrap_or_default()),
+        Err(e) => log_error!("Не удалось получить версию схемы: {}", e),
+    }
+}
+
+/// Воспроизводит запись апдейтов и завершается с кодом 1, если какой-то апдейт не обработан
+pub async fn replay(path: &Path) {
+    dotenv().ok();
+    setup_logger().expect("Не удалось настроить логгер");
+
+    let recording = match tokio::fs::read_to_string(path).await {
+        Ok(recording) => recording,
+        Err(e) => {
+            log_error!("Не удалось прочитать запись {}: {}", path.display(), e);
+            std::process::exit(2);
+        }
+    };
+    let report = match replay::replay(&recording, &BotConfig::default()).await {
+        Ok(report) => report,
+        Err(e) => {
+            log_error!("Не удалось запустить воспроизведение: {}", e);
+            std::process::exit(2);
+        }
+    };
+    for failure in &report.failures {
+        log_error!("{}", failure);
+    }
+    log_info!("Воспроизведено апдейтов: {}, с ошибками: {}", report.updates, report.failures.len());
+    if !report.failures.is_empty() {
+        std::process::exit(1);
+    }
+}
+
 pub async fn start() {
     // Donenv, logger, load
     dotenv().ok();
@@ -77,16 +232,38 @@ pub async fn start() {
 
     let token = env::var("TOKEN").expect("Ошибка при получение токена из .env");
     let url = env::var("DB_URL").unwrap();
+    let config = BotConfig::default();
 
 
     log_info!("Бот запущен...");
 
     let repo = UserRepository::new(&url).await.unwrap();
-    if let Err(e) = repo.init_table().await {
-        log_error!("Ошибка иницализации таблицы: {}", e);
+    // DB_AUTO_MIGRATE=false, если миграции применяются отдельно командой `migrate`
+    let auto_migrate = env::var("DB_AUTO_MIGRATE").map(|v| v != "false" && v != "0").unwrap_or(true);
+    if auto_migrate && let Err(e) = repo.migrate().await {
+        log_error!("Ошибка миграции базы данных, бот не запущен: {}", e);
+        return;
     }
 
+    // История диалогов с AI хранится в MongoDB (UserHistory)
+    let history = match env::var("MONGODB_URI") {
+        Ok(uri) => {
+            let db_name = env::var("MONGODB_DB").unwrap_or("qortex".to_string());
+            match Database::new(&uri, &db_name).await {
+                Ok(db) => Some(db),
+                Err(e) => {
+                    log_error!("Ошибка подключения к MongoDB, ответы AI будут без контекста: {}", e);
+                    None
+                }
+            }
+        }
+        Err(_) => {
+            log_info!("MONGODB_URI не задан, ответы AI будут без контекста");
+            None
+        }
+    };
+
     // Bot init
-    let bot = TelegramBot::new(token, repo.into()).await;
+    let bot = TelegramBot::new(token, repo.into(), history, &config).await;
     let _urn = bot.run().await;
 }
\ No newline at end of file
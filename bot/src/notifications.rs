@@ -0,0 +1,126 @@
+use std::{sync::Arc, time::Duration};
+
+use db_pg::{DbError, Message, MessageStatus, MessageStatusEvent, MessageStatusListener, Store, UserRoleListener};
+use localization::{t_args, Lang};
+use logging::{log_error, log_info};
+use teloxide::{payloads::SendMessageSetters, prelude::Requester, types::{ChatId, ParseMode}, utils::markdown::escape};
+use tokio::time::sleep;
+
+use crate::{i18n::status_label, types::HandlerResult, TelegramBot};
+
+/// Pause before subscribing again after the listener failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Subscription to a Postgres channel
+pub(crate) trait Listener: Sized + Send {
+    type Event;
+
+    fn subscribe(db: &dyn Store) -> impl Future<Output = Result<Self, DbError>> + Send + '_;
+
+    fn recv(&mut self) -> impl Future<Output = Result<Self::Event, DbError>> + Send;
+}
+
+impl Listener for MessageStatusListener {
+    type Event = MessageStatusEvent;
+
+    fn subscribe(db: &dyn Store) -> impl Future<Output = Result<Self, DbError>> + Send + '_ {
+        db.listen_message_status()
+    }
+
+    fn recv(&mut self) -> impl Future<Output = Result<MessageStatusEvent, DbError>> + Send {
+        MessageStatusListener::recv(self)
+    }
+}
+
+impl Listener for UserRoleListener {
+    type Event = i64;
+
+    fn subscribe(db: &dyn Store) -> impl Future<Output = Result<Self, DbError>> + Send + '_ {
+        db.listen_user_role()
+    }
+
+    fn recv(&mut self) -> impl Future<Output = Result<i64, DbError>> + Send {
+        UserRoleListener::recv(self)
+    }
+}
+
+/// Keeps a subscription alive and passes its events to `handle`.
+/// After an error subscribes again, `on_subscribed` runs after every subscription
+pub(crate) async fn listen<L: Listener, F: Future<Output = ()>>(
+    bots: &TelegramBot,
+    what: &str,
+    mut on_subscribed: impl FnMut(),
+    mut handle: impl FnMut(L::Event) -> F,
+) {
+    loop {
+        let mut listener = match L::subscribe(bots.db.as_ref()).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                log_error!("Не удалось подписаться на {}: {}", what, e);
+                sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        on_subscribed();
+
+        loop {
+            match listener.recv().await {
+                Ok(event) => handle(event).await,
+                Err(e) => {
+                    log_error!("Подписка на {} прервалась: {}", what, e);
+                    sleep(RECONNECT_DELAY).await;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Listens for request status changes in Postgres and notifies authors.
+/// Works for changes made by the bot, gRPC service or plain SQL
+pub async fn run_status_notifier(bots: Arc<TelegramBot>) {
+    let bots = bots.as_ref();
+    listen::<MessageStatusListener, _>(
+        bots,
+        "изменения обращений",
+        || log_info!("Уведомления об обращениях запущены"),
+        |event| async move {
+            if let Err(e) = notify_author(bots, event.id).await {
+                log_error!("Ошибка уведомления {} об обращении {}: {}", event.telegram_id, event.id, e);
+            }
+        },
+    )
+    .await
+}
+
+async fn notify_author(bots: &TelegramBot, message_id: uuid::Uuid) -> HandlerResult {
+    // Статус перечитываем, событие могло устареть пока шло
+    let Some(message) = bots.db.get_message_by_id(message_id).await? else {
+        return Ok(());
+    };
+    if message.status == MessageStatus::Pending {
+        return Ok(());
+    }
+    if !bots.db.get_user_settings(message.telegram_id).await?.status_notifications {
+        return Ok(());
+    }
+
+    // language_code из Telegram здесь недоступен, только сохранённый выбор
+    let lang = bots.stored_lang(message.telegram_id).await.unwrap_or_default();
+    bots.bot.send_message(ChatId(message.telegram_id), status_text(lang, &message))
+        .parse_mode(ParseMode::MarkdownV2)
+        .await?;
+
+    Ok(())
+}
+
+fn status_text(lang: Lang, message: &Message) -> String {
+    let mut text = t_args(lang, "notifications.status", &[
+        ("uid", &escape(&message.id.to_string())),
+        ("status", &escape(&status_label(lang, message.status))),
+    ]);
+    if let Some(answer) = &message.answer {
+        text.push_str(&t_args(lang, "requests.answer", &[("answer", &escape(answer))]));
+    }
+    text
+}
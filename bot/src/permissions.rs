@@ -0,0 +1,174 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use db_pg::{UserRole, UserRoleListener};
+use localization::t;
+use logging::{log_error, log_info};
+use teloxide::{payloads::AnswerCallbackQuerySetters, prelude::Requester, types::{CallbackQuery, Message, Update}};
+
+use crate::{callback_data::CallbackData, handlers::commands::Commander, notifications::listen, types::HandlerResult, TelegramBot};
+
+/// Роли, которым доступна команда или кнопка
+pub type Roles = &'static [UserRole];
+
+pub const EVERYONE: Roles = &[UserRole::Default, UserRole::WithAccess, UserRole::Admin];
+pub const ADMINS: Roles = &[UserRole::Admin];
+
+pub fn allows(roles: Roles, role: UserRole) -> bool {
+    roles.contains(&role)
+}
+
+/// Роли пользователей, чтобы не ходить в базу на каждый апдейт.
+/// Записи сбрасываются по событию из Postgres, TTL страхует от потерянных событий
+pub struct RoleCache {
+    ttl: Duration,
+    roles: Mutex<HashMap<i64, (UserRole, Instant)>>,
+}
+
+impl RoleCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, roles: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn get(&self, telegram_id: i64) -> Option<UserRole> {
+        let roles = self.roles.lock().unwrap();
+        roles
+            .get(&telegram_id)
+            .filter(|(_, cached_at)| cached_at.elapsed() < self.ttl)
+            .map(|(role, _)| *role)
+    }
+
+    /// Роль из кэша без учёта TTL, если её не сбросило событие
+    pub fn last(&self, telegram_id: i64) -> Option<UserRole> {
+        self.roles.lock().unwrap().get(&telegram_id).map(|(role, _)| *role)
+    }
+
+    pub fn insert(&self, telegram_id: i64, role: UserRole) {
+        self.roles.lock().unwrap().insert(telegram_id, (role, Instant::now()));
+    }
+
+    pub fn invalidate(&self, telegram_id: i64) {
+        self.roles.lock().unwrap().remove(&telegram_id);
+    }
+
+    pub fn clear(&self) {
+        self.roles.lock().unwrap().clear();
+    }
+}
+
+impl TelegramBot {
+    /// Роль пользователя, из кэша или из базы
+    pub async fn role(&self, telegram_id: i64) -> HandlerResult<UserRole> {
+        if let Some(role) = self.roles.get(telegram_id) {
+            return Ok(role);
+        }
+        let role = self.db.get_role(telegram_id).await?;
+        self.roles.insert(telegram_id, role);
+        Ok(role)
+    }
+}
+
+/// Роль отправителя для проверок доступа. Если база недоступна, берётся последняя
+/// известная роль, а без неё апдейт не обрабатывается
+pub async fn sender_role(bots: Arc<TelegramBot>, update: Update) -> Option<UserRole> {
+    let Some(user) = update.from() else {
+        return Some(UserRole::Default);
+    };
+    let telegram_id = user.id.0 as i64;
+    match bots.role(telegram_id).await {
+        Ok(role) => Some(role),
+        Err(e) => {
+            let last = bots.roles.last(telegram_id);
+            log_error!("Не удалось получить роль пользователя {}, последняя известная {:?}: {}", user.id.0, last, e);
+            last
+        }
+    }
+}
+
+pub fn command_allowed(cmd: Commander, role: UserRole) -> bool {
+    allows(cmd.required_roles(), role)
+}
+
+/// Кнопки с неразобранными данными пропускаются, на них ответит `callback_handler`
+pub fn callback_allowed(bots: Arc<TelegramBot>, q: CallbackQuery, role: UserRole) -> bool {
+    q.data
+        .as_deref()
+        .and_then(|raw| raw.parse::<CallbackData>().ok())
+        .is_none_or(|data| allows(bots.callback_handlers.required_roles(&data), role))
+}
+
+/// Заблокированные пользователи могут только получать уведомления
+pub fn message_allowed(role: UserRole) -> bool {
+    role != UserRole::Banned
+}
+
+fn denial_key(role: UserRole) -> &'static str {
+    match role {
+        UserRole::Banned => "access.banned",
+        _ => "access.denied",
+    }
+}
+
+pub async fn deny_message(bots: Arc<TelegramBot>, msg: Message, role: UserRole) -> HandlerResult {
+    let Some(user) = &msg.from else {
+        return Ok(());
+    };
+    log_info!("Пользователь {} с ролью {:?} без доступа к сообщению {:?}", user.id.0, role, msg.text());
+    let lang = bots.lang(user).await;
+    bots.bot.send_message(msg.chat.id, t(lang, denial_key(role))).await?;
+    Ok(())
+}
+
+pub async fn deny_callback(bots: Arc<TelegramBot>, q: CallbackQuery, role: UserRole) -> HandlerResult {
+    log_info!("Пользователь {} с ролью {:?} без доступа к кнопке {:?}", q.from.id.0, role, q.data);
+    let lang = bots.lang(&q.from).await;
+    bots.bot.answer_callback_query(q.id.clone())
+        .text(t(lang, denial_key(role)))
+        .show_alert(true)
+        .await?;
+    Ok(())
+}
+
+/// Сбрасывает роли в кэше по изменениям в Postgres, откуда бы они ни пришли
+pub async fn run_role_invalidation(bots: Arc<TelegramBot>) {
+    let bots = bots.as_ref();
+    listen::<UserRoleListener, _>(
+        bots,
+        "изменения ролей",
+        // Пока подписки не было, события могли потеряться
+        || bots.roles.clear(),
+        |telegram_id| async move { bots.roles.invalidate(telegram_id) },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_roles_expire_and_invalidate() {
+        let cache = RoleCache::new(Duration::from_secs(60));
+        assert_eq!(cache.get(1), None);
+        cache.insert(1, UserRole::Admin);
+        assert_eq!(cache.get(1), Some(UserRole::Admin));
+        cache.invalidate(1);
+        assert_eq!(cache.get(1), None);
+
+        let expired = RoleCache::new(Duration::ZERO);
+        expired.insert(1, UserRole::Admin);
+        assert_eq!(expired.get(1), None);
+        assert_eq!(expired.last(1), Some(UserRole::Admin));
+        expired.invalidate(1);
+        assert_eq!(expired.last(1), None);
+    }
+
+    #[test]
+    fn admin_commands_need_admin_role() {
+        assert!(command_allowed(Commander::Admin, UserRole::Admin));
+        assert!(!command_allowed(Commander::Admin, UserRole::WithAccess));
+        assert!(!command_allowed(Commander::Grant(String::new()), UserRole::Default));
+        assert!(command_allowed(Commander::Faq, UserRole::Default));
+        assert!(!command_allowed(Commander::Start(String::new()), UserRole::Banned));
+        assert!(!message_allowed(UserRole::Banned));
+    }
+}
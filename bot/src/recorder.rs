@@ -0,0 +1,187 @@
+//! Запись входящих апдейтов в JSON Lines для воспроизведения командой `replay`.
+//!
+//! Идентификаторы и имена пользователей заменяются псевдонимами через HMAC,
+//! поэтому один и тот же пользователь в пределах записи остаётся одним и тем же,
+//! а настоящие id в файл не попадают. Текст сообщений записывается как есть
+
+use std::{io, path::Path};
+
+use hmac::{Hmac, Mac};
+use logging::{log_error, log_info};
+use serde_json::Value;
+use sha2::Sha256;
+use teloxide::types::Update;
+use tokio::{fs::{File, OpenOptions}, io::AsyncWriteExt, sync::Mutex};
+
+/// Наибольший id пользователя, который teloxide ещё считает пользовательским
+const MAX_USER_ID: u64 = (1 << 40) - 1;
+/// Id групп лежат в `-999_999_999_999..=-1`
+const MAX_GROUP_ID: u64 = 999_999_999_999;
+/// Id супергрупп и каналов лежат в `-1_997_852_516_352..=-1_000_000_000_000`
+const CHANNEL_ID_BASE: u64 = 1_000_000_000_000;
+const CHANNEL_ID_RANGE: u64 = 997_852_516_352;
+
+/// Заменяет идентификаторы пользователей и чатов псевдонимами
+pub struct Pseudonymizer {
+    key: Vec<u8>,
+}
+
+impl Pseudonymizer {
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+
+    fn hash(&self, kind: &str, value: &str) -> u64 {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(kind.as_bytes());
+        mac.update(value.as_bytes());
+        let bytes = mac.finalize().into_bytes();
+        u64::from_be_bytes(bytes[..8].try_into().unwrap())
+    }
+
+    /// Псевдоним id из того же диапазона, что и исходный: пользователь, группа или канал
+    pub fn id(&self, id: i64) -> i64 {
+        let hash = self.hash("id", &id.to_string());
+        let pseudonym = if id > 0 {
+            1 + hash % MAX_USER_ID
+        } else if id.unsigned_abs() <= MAX_GROUP_ID {
+            1 + hash % MAX_GROUP_ID
+        } else {
+            CHANNEL_ID_BASE + hash % CHANNEL_ID_RANGE
+        };
+        if id > 0 { pseudonym as i64 } else { -(pseudonym as i64) }
+    }
+
+    fn name(&self, kind: &str, prefix: &str, value: &str) -> String {
+        format!("{}{:08x}", prefix, self.hash(kind, value) as u32)
+    }
+
+    /// Заменяет в JSON апдейта id, имена, username и телефоны пользователей и чатов.
+    /// Данные ботов остаются, по ним в апдейтах узнаётся сам бот
+    pub fn apply(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                let is_bot = object.get("is_bot").and_then(Value::as_bool);
+                let is_user = is_bot == Some(false);
+                let is_chat = object.get("type").and_then(Value::as_str)
+                    .is_some_and(|kind| matches!(kind, "private" | "group" | "supergroup" | "channel"));
+
+                for (field, item) in object.iter_mut() {
+                    let replacement = match (field.as_str(), &*item) {
+                        ("id", Value::Number(id)) if is_user || is_chat => id.as_i64().map(|id| self.id(id).into()),
+                        ("user_id", Value::Number(id)) => id.as_i64().map(|id| self.id(id).into()),
+                        ("username", Value::String(s)) if is_bot != Some(true) => {
+                            Some(self.name("username", "user_", s).into())
+                        }
+                        ("first_name", Value::String(s)) if is_bot != Some(true) => {
+                            Some(self.name("first_name", "User ", s).into())
+                        }
+                        ("last_name", Value::String(s)) if is_bot != Some(true) => {
+                            Some(self.name("last_name", "", s).into())
+                        }
+                        ("phone_number", Value::String(s)) => {
+                            Some((self.hash("phone", s) % 10_000_000_000).to_string().into())
+                        }
+                        _ => None,
+                    };
+                    match replacement {
+                        Some(replacement) => *item = replacement,
+                        None => self.apply(item),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.apply(item)),
+            _ => {}
+        }
+    }
+}
+
+/// Дописывает апдейты в файл, по одному JSON на строку
+pub struct UpdateRecorder {
+    file: Mutex<File>,
+    pseudonymizer: Pseudonymizer,
+}
+
+impl UpdateRecorder {
+    /// Открывает файл на дозапись. Без ключа псевдонимы разных запусков не совпадут
+    pub async fn open(path: &Path, key: Option<&str>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path).await?;
+        let key = match key {
+            Some(key) => key.as_bytes().to_vec(),
+            None => {
+                log_info!("UPDATE_RECORD_KEY не задан, псевдонимы действуют только в этом запуске");
+                uuid::Uuid::new_v4().as_bytes().to_vec()
+            }
+        };
+        log_info!("Апдейты записываются в {}", path.display());
+        Ok(Self { file: Mutex::new(file), pseudonymizer: Pseudonymizer::new(&key) })
+    }
+
+    /// Строка записи для апдейта
+    pub fn line(&self, update: &Update) -> serde_json::Result<String> {
+        let mut value = serde_json::to_value(update)?;
+        self.pseudonymizer.apply(&mut value);
+        let mut line = serde_json::to_string(&value)?;
+        line.push('\n');
+        Ok(line)
+    }
+
+    /// Записывает апдейт. Ошибки записи только логируются, обработка апдейта продолжается
+    pub async fn record(&self, update: &Update) {
+        let line = match self.line(update) {
+            Ok(line) => line,
+            Err(e) => {
+                log_error!("Не удалось сериализовать апдейт {}: {}", update.id.0, e);
+                return;
+            }
+        };
+        let mut file = self.file.lock().await;
+        if let Err(e) = file.write_all(line.as_bytes()).await {
+            log_error!("Не удалось записать апдейт {}: {}", update.id.0, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn pseudonyms_are_stable_and_keep_id_ranges() {
+        let pseudonymizer = Pseudonymizer::new(b"key");
+        let mut update = json!({
+            "update_id": 7,
+            "message": {
+                "message_id": 3,
+                "date": 1_700_000_000,
+                "chat": { "id": 501, "type": "private", "first_name": "Alice", "username": "alice" },
+                "from": { "id": 501, "is_bot": false, "first_name": "Alice", "username": "alice", "language_code": "en" },
+                "text": "/start",
+            }
+        });
+        pseudonymizer.apply(&mut update);
+
+        let message = &update["message"];
+        let user_id = message["from"]["id"].as_i64().unwrap();
+        assert_ne!(user_id, 501);
+        assert!(user_id > 0);
+        assert_eq!(message["chat"]["id"].as_i64(), Some(user_id));
+        assert_eq!(message["chat"]["username"], message["from"]["username"]);
+        assert_ne!(message["from"]["first_name"], "Alice");
+        assert_eq!(message["from"]["language_code"], "en");
+        assert_eq!(message["text"], "/start");
+        assert_eq!(update["update_id"], 7);
+
+        // Запись по-прежнему разбирается как апдейт
+        let update: Update = serde_json::from_str(&update.to_string()).unwrap();
+        assert_eq!(update.chat().map(|chat| chat.id.0), Some(user_id));
+        let line = serde_json::to_string(&update).unwrap();
+        assert_eq!(serde_json::from_str::<Update>(&line).unwrap(), update);
+
+        assert!(pseudonymizer.id(-42) < 0 && pseudonymizer.id(-42) >= -(MAX_GROUP_ID as i64));
+        assert!(pseudonymizer.id(-1_001_234_567_890) <= -(CHANNEL_ID_BASE as i64));
+        assert_ne!(Pseudonymizer::new(b"other").id(501), user_id);
+    }
+}
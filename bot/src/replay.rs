@@ -0,0 +1,250 @@
+//! Воспроизведение записанных апдейтов (см. `recorder`) для поиска регрессий.
+//!
+//! Апдейты по одному проходят через то же дерево обработчиков, что и в `run`,
+//! но с базой `MemoryStore`, диалогами в памяти и заглушкой Telegram Bot API,
+//! которая на всё отвечает успехом. Ошибки обработчиков и паники собираются в отчёт.
+//! Состояние базы на момент записи не восстанавливается, все пользователи новые
+
+use std::{
+    any::Any,
+    fmt, io,
+    ops::ControlFlow,
+    path::PathBuf,
+    sync::{atomic::{AtomicI32, Ordering}, Arc},
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use db_pg::MemoryStore;
+use serde_json::{json, Value};
+use teloxide::{
+    adaptors::throttle::Limits,
+    dispatching::dialogue::{InMemStorage, Storage},
+    prelude::*,
+    types::{Me, UpdateKind},
+};
+use tokio::net::TcpListener;
+
+use crate::{config::BotConfig, schema, state::State as DialogueState, storage::LocalStorage, types::MyStorage, TelegramBot};
+
+const STUB_BOT_ID: i64 = 1;
+const STUB_BOT_USERNAME: &str = "qortex_replay_bot";
+
+/// Что пошло не так с апдейтом
+#[derive(Debug)]
+pub enum FailureKind {
+    /// Строку записи не удалось разобрать как апдейт
+    Parse(String),
+    /// Обработчик вернул ошибку
+    Error(String),
+    /// Обработчик запаниковал
+    Panic(String),
+}
+
+/// Апдейт из записи, на котором обработка не удалась
+#[derive(Debug)]
+pub struct ReplayFailure {
+    /// Номер строки в записи, с единицы
+    pub line: usize,
+    pub update_id: Option<u32>,
+    pub kind: FailureKind,
+}
+
+impl fmt::Display for ReplayFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "строка {}", self.line)?;
+        if let Some(update_id) = self.update_id {
+            write!(f, " (апдейт {})", update_id)?;
+        }
+        match &self.kind {
+            FailureKind::Parse(e) => write!(f, ": запись не разобрана: {}", e),
+            FailureKind::Error(e) => write!(f, ": ошибка обработчика: {}", e),
+            FailureKind::Panic(e) => write!(f, ": паника: {}", e),
+        }
+    }
+}
+
+/// Итог воспроизведения
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// Сколько апдейтов прочитано из записи
+    pub updates: usize,
+    pub failures: Vec<ReplayFailure>,
+}
+
+/// Прогоняет запись, по апдейту на строку, через обработчики бота
+pub async fn replay(recording: &str, config: &BotConfig) -> io::Result<ReplayReport> {
+    let api_url = start_stub_api().await?;
+    let bot = Bot::new("0:replay").set_api_url(api_url.parse().expect("адрес заглушки корректен")).throttle(Limits {
+        messages_per_sec_chat: 1000,
+        messages_per_min_chat: 10_000,
+        messages_per_min_channel_or_supergroup: 10_000,
+        messages_per_sec_overall: 1000,
+    });
+    let me: Me = bot.get_me().await.map_err(io::Error::other)?;
+
+    let files_dir = std::env::temp_dir().join(format!("qortex-replay-{}", uuid::Uuid::new_v4().simple()));
+    let file_storage = Arc::new(LocalStorage::new(&files_dir).await?);
+    let dialogues: Arc<MyStorage> = InMemStorage::<DialogueState>::new().erase();
+    let config = BotConfig { download_server_addr: None, update_record_path: None, ..config.clone() };
+    let bots = TelegramBot::with_parts(bot.clone(), Arc::new(MemoryStore::new()), dialogues, file_storage, None, &config);
+
+    let handler = schema();
+    let mut report = ReplayReport::default();
+    for (index, line) in recording.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        report.updates += 1;
+        let line_number = index + 1;
+
+        // Неизвестный апдейт teloxide не отвергает, а кладёт в UpdateKind::Error
+        let update = match serde_json::from_str::<Update>(line) {
+            Ok(update) if !matches!(update.kind, UpdateKind::Error(_)) => update,
+            parsed => {
+                let (update_id, error) = match parsed {
+                    Ok(update) => (Some(update.id.0), "неизвестный вид апдейта".to_string()),
+                    Err(e) => {
+                        let update_id = serde_json::from_str::<Value>(line)
+                            .ok()
+                            .and_then(|value| value["update_id"].as_u64())
+                            .map(|id| id as u32);
+                        (update_id, e.to_string())
+                    }
+                };
+                report.failures.push(ReplayFailure { line: line_number, update_id, kind: FailureKind::Parse(error) });
+                continue;
+            }
+        };
+        let update_id = update.id.0;
+        let deps = dptree::deps![bots.clone(), bots.storage.clone(), bot.clone(), me.clone(), update];
+
+        // Отдельная задача, чтобы паника обработчика не прервала воспроизведение
+        let handler = handler.clone();
+        let kind = match tokio::spawn(async move { handler.dispatch(deps).await }).await {
+            Ok(ControlFlow::Break(Err(e))) => Some(FailureKind::Error(e.to_string())),
+            Ok(_) => None,
+            Err(e) if e.is_panic() => Some(FailureKind::Panic(panic_message(e.into_panic()))),
+            Err(e) => Some(FailureKind::Panic(e.to_string())),
+        };
+        if let Some(kind) = kind {
+            report.failures.push(ReplayFailure { line: line_number, update_id: Some(update_id), kind });
+        }
+    }
+
+    let _ = tokio::fs::remove_dir_all(&files_dir).await;
+    Ok(report)
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map(|s| s.to_string()).unwrap_or_else(|| "без сообщения".to_string()),
+    }
+}
+
+#[derive(Default)]
+struct StubApi {
+    last_message_id: AtomicI32,
+}
+
+impl StubApi {
+    fn message(&self, message_id: i32, body: &Value) -> Value {
+        json!({
+            "message_id": message_id,
+            "date": 0,
+            "chat": { "id": body["chat_id"], "type": "private", "first_name": "Replay" },
+            "from": { "id": STUB_BOT_ID, "is_bot": true, "first_name": "Qortex", "username": STUB_BOT_USERNAME },
+            "text": body["text"].as_str().unwrap_or_default(),
+        })
+    }
+}
+
+async fn handle_method(State(api): State<Arc<StubApi>>, Path((_token, method)): Path<(String, String)>, body: Bytes) -> impl IntoResponse {
+    // Файлы приходят multipart, их содержимое не нужно
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let result = match method.to_lowercase().as_str() {
+        "getme" => json!({
+            "id": STUB_BOT_ID,
+            "is_bot": true,
+            "first_name": "Qortex",
+            "username": STUB_BOT_USERNAME,
+            "can_join_groups": false,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": false,
+        }),
+        "sendmessage" | "senddocument" | "sendphoto" => {
+            let message_id = api.last_message_id.fetch_add(1, Ordering::SeqCst) + 1;
+            api.message(message_id, &body)
+        }
+        "editmessagetext" | "editmessagereplymarkup" => {
+            api.message(body["message_id"].as_i64().unwrap_or_default() as i32, &body)
+        }
+        "getfile" => json!({
+            "file_id": body["file_id"],
+            "file_unique_id": "replay",
+            "file_size": 0,
+            "file_path": "replay",
+        }),
+        _ => json!(true),
+    };
+    ([(header::CONTENT_TYPE, "application/json")], json!({ "ok": true, "result": result }).to_string())
+}
+
+/// Скачивание файла отдаёт пустое содержимое
+async fn handle_file(Path((_token, _path)): Path<(String, PathBuf)>) -> impl IntoResponse {
+    Bytes::new()
+}
+
+/// Заглушка Bot API на локальном порту, возвращает её адрес
+async fn start_stub_api() -> io::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let router = Router::new()
+        .route("/{token}/{method}", post(handle_method))
+        .route("/file/{token}/{*path}", get(handle_file))
+        .with_state(Arc::new(StubApi::default()));
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replays_recording_and_reports_broken_lines() {
+        let user = json!({ "id": 501, "is_bot": false, "first_name": "Alice", "language_code": "en" });
+        let chat = json!({ "id": 501, "type": "private", "first_name": "Alice" });
+        let start = json!({
+            "update_id": 1,
+            "message": { "message_id": 1, "date": 1_700_000_000, "chat": chat, "from": user, "text": "/start" },
+        });
+        // Возврат к списку после перезапуска, состояния диалога нет
+        let back = json!({
+            "update_id": 2,
+            "callback_query": {
+                "id": "q2",
+                "from": user,
+                "chat_instance": "replay",
+                "data": "back_to_page:0",
+                "message": { "message_id": 5, "date": 1_700_000_000, "chat": chat, "text": "..." },
+            },
+        });
+        let recording = format!("{}\n{}\n{{\"update_id\": 3, \"message\": 42}}\n", start, back);
+
+        let config = BotConfig { welcome_credits: 0, ..Default::default() };
+        let report = replay(&recording, &config).await.unwrap();
+        assert_eq!(report.updates, 3);
+        assert_eq!(report.failures.len(), 1, "{:?}", report.failures);
+        let failure = &report.failures[0];
+        assert_eq!((failure.line, failure.update_id), (3, Some(3)));
+        assert!(matches!(failure.kind, FailureKind::Parse(_)));
+    }
+}
@@ -1,17 +1,33 @@
-use db_pg::Message;
+use db_pg::{MessageCursor, MessageStatus};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub enum State {
     #[default]
     OnWaiting,
     Send,
     WaitQuestion,
+    /// `filter` is None for all requests of the user.
+    /// `first` and `last` are keyset cursors of the shown page, requests are fetched from DB per page
     ViewingMessages {
-        messages: Vec<Message>,
         current_page: usize,
+        filter: Option<MessageStatus>,
+        first: Option<MessageCursor>,
+        last: Option<MessageCursor>,
     },
     ViewingSingleMessage {
-        message: Message,
+        message_id: Uuid,
         back_page: usize,
+        filter: Option<MessageStatus>,
+        /// First request of the page to return to
+        page_start: Option<MessageCursor>,
     },
-}
\ No newline at end of file
+    AdminViewingRequests {
+        current_page: usize,
+    },
+    AdminAnswering {
+        message_id: Uuid,
+        back_page: usize,
+    },
+}
@@ -0,0 +1,76 @@
+use std::{io::ErrorKind, path::{Path, PathBuf}};
+
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use tokio::{fs, io::{AsyncReadExt, AsyncSeekExt, SeekFrom}};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::storage::{validate_key, ByteStream, FileStorage, StorageError, StorageResult};
+
+/// Файлы в локальной папке, подходит для одного экземпляра бота
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub async fn new(root: impl AsRef<Path>) -> std::io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).await?;
+        Ok(Self { root })
+    }
+
+    fn path(&self, key: &str) -> StorageResult<PathBuf> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl FileStorage for LocalStorage {
+    async fn put(&self, key: &str, content: Vec<u8>, _content_type: Option<&str>) -> StorageResult<()> {
+        let path = self.path(key)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        // Пишем во временный файл, чтобы читатели не увидели файл наполовину
+        let tmp = path.with_extension(format!("{}.tmp", Uuid::new_v4().simple()));
+        fs::write(&tmp, content).await?;
+        if let Err(e) = fs::rename(&tmp, &path).await {
+            let _ = fs::remove_file(&tmp).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> StorageResult<Option<Vec<u8>>> {
+        match fs::read(self.path(key)?).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn stream(&self, key: &str, range: Option<(u64, u64)>) -> StorageResult<Option<ByteStream>> {
+        let mut file = match fs::File::open(self.path(key)?).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let stream: ByteStream = match range {
+            Some((start, end)) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                Box::pin(ReaderStream::new(file.take(end - start + 1)).map_err(StorageError::from))
+            }
+            None => Box::pin(ReaderStream::new(file).map_err(StorageError::from)),
+        };
+        Ok(Some(stream))
+    }
+
+    async fn delete(&self, key: &str) -> StorageResult<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
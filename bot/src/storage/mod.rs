@@ -0,0 +1,75 @@
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::Stream;
+use thiserror::Error;
+
+pub mod local;
+pub mod s3;
+
+pub use local::LocalStorage;
+pub use s3::{S3Config, S3Storage};
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("storage io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("storage http error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("storage responded with {status}: {body}")]
+    Status { status: u16, body: String },
+    #[error("invalid storage key `{0}`")]
+    InvalidKey(String),
+    #[error("invalid storage endpoint `{0}`")]
+    InvalidEndpoint(String),
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/// Содержимое файла частями, для отдачи без загрузки в память
+pub type ByteStream = Pin<Box<dyn Stream<Item = StorageResult<Bytes>> + Send>>;
+
+/// Хранилище содержимого файлов обменника. Ключи вида `<владелец>/<id файла>`
+/// выдаёт FileManager, имена от пользователей в ключи не попадают
+#[async_trait]
+pub trait FileStorage: Send + Sync {
+    /// Записывает файл, существующий с тем же ключом заменяется
+    async fn put(&self, key: &str, content: Vec<u8>, content_type: Option<&str>) -> StorageResult<()>;
+
+    /// Содержимое файла, None если его нет
+    async fn get(&self, key: &str) -> StorageResult<Option<Vec<u8>>>;
+
+    /// Потоковое чтение файла или байт `start..=end`, None если файла нет
+    async fn stream(&self, key: &str, range: Option<(u64, u64)>) -> StorageResult<Option<ByteStream>>;
+
+    /// Удаляет файл, отсутствие файла не ошибка
+    async fn delete(&self, key: &str) -> StorageResult<()>;
+}
+
+/// Ключ из сегментов без `.` и `..`, чтобы он не мог выйти за пределы хранилища
+pub fn validate_key(key: &str) -> StorageResult<()> {
+    let valid = !key.is_empty()
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        });
+    if valid { Ok(()) } else { Err(StorageError::InvalidKey(key.to_string())) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_key;
+
+    #[test]
+    fn storage_keys() {
+        assert!(validate_key("42/0f8fad5bd9cb469fa16570867728950e").is_ok());
+        assert!(validate_key("").is_err());
+        assert!(validate_key("/etc/passwd").is_err());
+        assert!(validate_key("42/../43/file").is_err());
+        assert!(validate_key("42//file").is_err());
+        assert!(validate_key("42/file name").is_err());
+    }
+}
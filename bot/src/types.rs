@@ -1,12 +1,15 @@
-use teloxide::{adaptors::Throttle, dispatching::dialogue::InMemStorage, prelude::Dialogue, Bot};
+use teloxide::{adaptors::Throttle, dispatching::dialogue::ErasedStorage, prelude::Dialogue, Bot};
 
 use crate::state::State;
 
 /// MyDialogue type need for using FSM Context managment
-pub type MyDialogue = Dialogue<State, InMemStorage<State>>;
+pub type MyDialogue = Dialogue<State, ErasedStorage<State>>;
+
+/// Storage behind MyDialogue, in-memory or Postgres depending on BotConfig
+pub type MyStorage = ErasedStorage<State>;
 
 /// Simple Result<> type for functions
-pub type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+pub type HandlerResult<T = ()> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
 /// Using Trottle describer for rate limit setting
-pub type MyBot = Throttle<Bot>;
\ No newline at end of file
+pub type MyBot = Throttle<Bot>;
@@ -0,0 +1,174 @@
+mod support;
+
+use std::pin::Pin;
+
+use db_pg::{MessageStatus, MessageStore, UserStore};
+use futures_util::{Stream, StreamExt};
+use grpc_service::server::proto::{
+    ai_generation_service_server::{AiGenerationService, AiGenerationServiceServer},
+    TextGenerationChunk, TextGenerationRequest, TextGenerationResponse,
+};
+use localization::{t, t_args, Lang};
+use support::{TestBot, TestUser};
+use teloxide::utils::markdown::escape;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+const ALICE: TestUser = TestUser { id: 501, first_name: "Alice" };
+
+fn faq_text(user: TestUser) -> String {
+    t_args(Lang::En, "faq.text", &[("name", &format!("{} ", user.first_name))])
+}
+
+#[tokio::test]
+async fn faq_flow() {
+    let test = TestBot::start().await;
+    let api = &test.api;
+
+    api.send_text(ALICE, "/faq");
+    let faq = api.expect("sendMessage").await;
+    assert_eq!(faq.chat_id(), ALICE.id);
+    assert_eq!(faq.text(), faq_text(ALICE));
+
+    api.press(ALICE, &faq, &faq.button(&t(Lang::En, "faq.profits_button")));
+    api.expect("answerCallbackQuery").await;
+    assert_eq!(api.expect("deleteMessage").await.message_id, faq.message_id);
+    let profits = api.expect("sendMessage").await;
+    assert_eq!(profits.text(), t(Lang::En, "faq.profits"));
+
+    // Назад к вопросам
+    api.press(ALICE, &profits, &profits.button("⬅️"));
+    api.expect("answerCallbackQuery").await;
+    assert_eq!(api.expect("deleteMessage").await.message_id, profits.message_id);
+    let faq = api.expect("sendMessage").await;
+    assert_eq!(faq.text(), faq_text(ALICE));
+
+    // И в главное меню
+    api.press(ALICE, &faq, &faq.button("⬅️"));
+    api.expect("answerCallbackQuery").await;
+    api.expect("deleteMessage").await;
+    let menu = api.expect("sendMessage").await;
+    assert_eq!(menu.text(), t_args(Lang::En, "start.greeting", &[("name", ALICE.first_name)]));
+}
+
+#[tokio::test]
+async fn request_history_flow() {
+    let test = TestBot::start().await;
+    let api = &test.api;
+
+    api.send_text(ALICE, "/start");
+    let menu = api.expect("sendMessage").await;
+    assert_eq!(menu.text(), t_args(Lang::En, "start.greeting", &[("name", ALICE.first_name)]));
+    assert!(test.store.get_user_by_telegram_id(ALICE.id).await.unwrap().is_some());
+
+    api.send_text(ALICE, "/send Where is my order?");
+    let sent = api.expect("sendMessage").await;
+    let request = test.store.get_user_messages(ALICE.id).await.unwrap().remove(0);
+    assert_eq!(request.text, "Where is my order?");
+    assert_eq!(sent.text(), t_args(Lang::En, "send.sent", &[("uid", &escape(&request.id.to_string()))]));
+
+    // Меню -> Мои обращения -> Все
+    api.press(ALICE, &menu, &menu.button(&t(Lang::En, "menu.my_requests")));
+    api.expect("answerCallbackQuery").await;
+    assert_eq!(api.expect("deleteMessage").await.message_id, menu.message_id);
+    let history = api.expect("sendMessage").await;
+    assert_eq!(history.text(), t(Lang::En, "requests.choose"));
+
+    api.press(ALICE, &history, &history.button(&t(Lang::En, "requests.all_button")));
+    api.expect("answerCallbackQuery").await;
+    let list = api.expect("editMessageText").await;
+    assert_eq!(list.message_id, history.message_id);
+    assert_eq!(list.text(), t(Lang::En, "requests.title.all"));
+
+    // Карточка обращения и возврат к списку
+    api.press(ALICE, &list, &list.button("Where is m..."));
+    api.expect("answerCallbackQuery").await;
+    let card = api.expect("editMessageText").await;
+    assert!(card.text().contains(&escape(&request.text)));
+    assert!(card.text().contains(&escape(&t(Lang::En, "status.pending"))));
+
+    api.press(ALICE, &card, &card.button(&t(Lang::En, "requests.back_button")));
+    api.expect("answerCallbackQuery").await;
+    assert_eq!(api.expect("editMessageText").await.text(), t(Lang::En, "requests.title.all"));
+
+    // Ответ администратора приходит уведомлением, сохранённого языка нет
+    test.store.update_message_status(request.id, MessageStatus::Answered, Some("Tomorrow")).await.unwrap();
+    let notice = api.expect("sendMessage").await;
+    assert_eq!(notice.chat_id(), ALICE.id);
+    assert!(notice.text().starts_with(&t_args(Lang::Ru, "notifications.status", &[
+        ("uid", &escape(&request.id.to_string())),
+        ("status", &escape(&t(Lang::Ru, "status.answered"))),
+    ])));
+
+    api.press(ALICE, &history, &history.button(&t(Lang::En, "requests.answered_button")));
+    api.expect("answerCallbackQuery").await;
+    let answered = api.expect("editMessageText").await;
+    assert_eq!(answered.text(), t(Lang::En, "requests.title.answered"));
+    assert_eq!(answered.buttons()[0].0, "Where is m...");
+}
+
+const CHUNKS: [&str; 3] = ["It will ", "be sunny ", "tomorrow"];
+
+/// AI сервис, отдающий заранее заготовленный ответ
+struct CannedAi;
+
+#[tonic::async_trait]
+impl AiGenerationService for CannedAi {
+    type GenerateTextStreamStream =
+        Pin<Box<dyn Stream<Item = Result<TextGenerationChunk, Status>> + Send + 'static>>;
+
+    async fn generate_text(
+        &self,
+        _request: Request<TextGenerationRequest>,
+    ) -> Result<Response<TextGenerationResponse>, Status> {
+        Ok(Response::new(TextGenerationResponse { generated_text: CHUNKS.concat() }))
+    }
+
+    async fn generate_text_stream(
+        &self,
+        _request: Request<TextGenerationRequest>,
+    ) -> Result<Response<Self::GenerateTextStreamStream>, Status> {
+        let chunks = futures_util::stream::iter(CHUNKS.map(|text| TextGenerationChunk { text: text.to_string() }))
+            .map(Ok);
+        Ok(Response::new(Box::pin(chunks)))
+    }
+}
+
+#[tokio::test]
+async fn question_flow() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ai_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        Server::builder()
+            .add_service(AiGenerationServiceServer::new(CannedAi))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let config = bot::config::BotConfig { ai_service_url: ai_url, welcome_credits: 3, ai_answer_price: 1, ..Default::default() };
+    let test = TestBot::start_with(config).await;
+    let api = &test.api;
+
+    // FAQ переводит чат в режим вопросов к Qortex AI
+    api.send_text(ALICE, "/faq");
+    api.expect("sendMessage").await;
+    api.send_text(ALICE, "What about the weather?");
+    let thinking = api.expect("sendMessage").await;
+    assert_eq!(thinking.text(), t(Lang::En, "ai.thinking"));
+
+    // Промежуточные правки зависят от таймингов, ждём итоговую
+    let expected = t_args(Lang::En, "ai.answer", &[("answer", &escape(&CHUNKS.concat())), ("note", "")]);
+    let answer = loop {
+        let edit = api.expect("editMessageText").await;
+        assert_eq!(edit.message_id, thinking.message_id);
+        if edit.text() == expected {
+            break edit;
+        }
+    };
+    assert_eq!(test.store.get_balance(ALICE.id).await.unwrap(), 2);
+
+    api.press(ALICE, &answer, &answer.button("✅"));
+    api.expect("answerCallbackQuery").await;
+    let rated = api.expect("editMessageReplyMarkup").await;
+    assert_eq!(rated.message_id, thinking.message_id);
+    assert_eq!(rated.buttons()[0].0, t(Lang::En, "feedback.thanks_button"));
+}
@@ -0,0 +1,44 @@
+use bot::storage::{FileStorage, LocalStorage, S3Config, S3Storage};
+use futures_util::TryStreamExt;
+use uuid::Uuid;
+
+/// put/get/stream/delete round trip shared by every storage
+async fn check_round_trip(storage: &dyn FileStorage) {
+    let key = format!("test/{}", Uuid::new_v4().simple());
+
+    assert_eq!(storage.get(&key).await.unwrap(), None);
+
+    storage.put(&key, b"first".to_vec(), Some("text/plain")).await.unwrap();
+    storage.put(&key, b"second".to_vec(), Some("text/plain")).await.unwrap();
+    assert_eq!(storage.get(&key).await.unwrap(), Some(b"second".to_vec()));
+
+    let range = storage.stream(&key, Some((1, 3))).await.unwrap().unwrap();
+    let chunks: Vec<_> = range.try_collect().await.unwrap();
+    assert_eq!(chunks.concat(), b"eco");
+
+    storage.delete(&key).await.unwrap();
+    assert_eq!(storage.get(&key).await.unwrap(), None);
+    assert!(storage.stream(&key, None).await.unwrap().is_none());
+    // Deleting a missing file is not an error
+    storage.delete(&key).await.unwrap();
+
+    assert!(storage.put("../escape", Vec::new(), None).await.is_err());
+}
+
+#[tokio::test]
+async fn local_storage() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = LocalStorage::new(dir.path()).await.unwrap();
+
+    check_round_trip(&storage).await;
+}
+
+/// Needs an S3 compatible server configured with `S3_*` variables, for example MinIO:
+/// `docker run -p 9000:9000 minio/minio server /data` with the bucket created beforehand,
+/// then `cargo test -p bot --test storage -- --ignored`
+#[tokio::test]
+#[ignore = "needs S3_* variables pointing to MinIO or S3"]
+async fn s3_storage() {
+    let config = S3Config::from_env().expect("S3_ENDPOINT, S3_BUCKET, S3_ACCESS_KEY and S3_SECRET_KEY must be set");
+    check_round_trip(&S3Storage::new(config)).await;
+}
@@ -0,0 +1,303 @@
+//! Фейковый Telegram Bot API для сквозных тестов бота.
+//!
+//! Сервер отдаёт заранее заданные апдейты через `getUpdates`, а исходящие вызовы
+//! бота (`sendMessage`, `editMessageText`, `deleteMessage`, ...) записывает,
+//! чтобы тест проверил их по порядку. Бот запускается целиком, с диспетчером и
+//! фоновыми задачами, только база заменена на `MemoryStore`
+
+use std::{collections::VecDeque, sync::{atomic::{AtomicI32, Ordering}, Arc, Mutex}, time::Duration};
+
+use axum::{body::Bytes, extract::{Path, State}, http::header, response::IntoResponse, routing::post, Router};
+use bot::{config::BotConfig, storage::LocalStorage, types::{MyBot, MyStorage}, TelegramBot};
+use db_pg::MemoryStore;
+use serde_json::{json, Value};
+use teloxide::{adaptors::throttle::Limits, dispatching::dialogue::{InMemStorage, Storage}, prelude::*};
+use tokio::{net::TcpListener, sync::Notify, time::timeout};
+
+const TOKEN: &str = "123456:TEST";
+const BOT_ID: i64 = 1000;
+pub const BOT_USERNAME: &str = "qortex_test_bot";
+
+/// Сколько ждать очередного вызова бота
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Пользователь, от имени которого приходят апдейты
+#[derive(Debug, Clone, Copy)]
+pub struct TestUser {
+    pub id: i64,
+    pub first_name: &'static str,
+}
+
+impl TestUser {
+    fn json(&self) -> Value {
+        json!({ "id": self.id, "is_bot": false, "first_name": self.first_name, "language_code": "en" })
+    }
+
+    fn chat(&self) -> Value {
+        json!({ "id": self.id, "type": "private", "first_name": self.first_name })
+    }
+}
+
+/// Вызов Bot API, сделанный ботом
+#[derive(Debug, Clone)]
+pub struct Call {
+    /// Имя метода как в документации Telegram, например `sendMessage`
+    pub method: String,
+    pub body: Value,
+    /// Сообщение, которое создал или изменил вызов
+    pub message_id: i32,
+}
+
+impl Call {
+    pub fn text(&self) -> &str {
+        self.body["text"].as_str().unwrap_or_default()
+    }
+
+    pub fn chat_id(&self) -> i64 {
+        self.body["chat_id"].as_i64().unwrap_or_default()
+    }
+
+    /// Inline-кнопки как пары (текст, callback_data)
+    pub fn buttons(&self) -> Vec<(String, String)> {
+        self.body["reply_markup"]["inline_keyboard"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|row| row.as_array().cloned().unwrap_or_default())
+            .map(|button| (
+                button["text"].as_str().unwrap_or_default().to_string(),
+                button["callback_data"].as_str().unwrap_or_default().to_string(),
+            ))
+            .collect()
+    }
+
+    /// callback_data кнопки с текстом `label`
+    pub fn button(&self, label: &str) -> String {
+        let buttons = self.buttons();
+        match buttons.iter().find(|(text, _)| text == label) {
+            Some((_, data)) => data.clone(),
+            None => panic!("Нет кнопки {:?} в {}: {:?}", label, self.method, buttons),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Api {
+    updates: Mutex<Vec<Value>>,
+    new_update: Notify,
+    calls: Mutex<VecDeque<Call>>,
+    new_call: Notify,
+    last_message_id: AtomicI32,
+}
+
+impl Api {
+    fn next_message_id(&self) -> i32 {
+        self.last_message_id.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn record(&self, call: Call) {
+        self.calls.lock().unwrap().push_back(call);
+        self.new_call.notify_waiters();
+    }
+
+    /// Long polling: ждёт апдейтов с id не меньше offset, но не дольше timeout
+    async fn get_updates(&self, body: &Value) -> Value {
+        let offset = body["offset"].as_i64().unwrap_or(0);
+        let wait = Duration::from_secs(body["timeout"].as_u64().unwrap_or(0));
+        loop {
+            let notified = self.new_update.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let pending: Vec<Value> = self.updates
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|update| update["update_id"].as_i64().unwrap_or_default() >= offset)
+                .cloned()
+                .collect();
+            if !pending.is_empty() || wait.is_zero() {
+                return Value::Array(pending);
+            }
+            if timeout(wait, notified).await.is_err() {
+                return json!([]);
+            }
+        }
+    }
+
+    fn message(&self, message_id: i32, body: &Value) -> Value {
+        json!({
+            "message_id": message_id,
+            "date": 1_700_000_000,
+            "chat": { "id": body["chat_id"], "type": "private", "first_name": "Test" },
+            "from": { "id": BOT_ID, "is_bot": true, "first_name": "Qortex", "username": BOT_USERNAME },
+            "text": body["text"].as_str().unwrap_or_default(),
+        })
+    }
+}
+
+async fn handle(State(api): State<Arc<Api>>, Path((_token, method)): Path<(String, String)>, body: Bytes) -> impl IntoResponse {
+    // Файлы приходят multipart, их тело тестам не нужно
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let result = match method.to_lowercase().as_str() {
+        "getme" => json!({
+            "id": BOT_ID,
+            "is_bot": true,
+            "first_name": "Qortex",
+            "username": BOT_USERNAME,
+            "can_join_groups": false,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": false,
+        }),
+        "getwebhookinfo" => json!({ "url": "", "has_custom_certificate": false, "pending_update_count": 0 }),
+        "deletewebhook" => json!(true),
+        "getupdates" => api.get_updates(&body).await,
+        "sendmessage" | "senddocument" | "sendphoto" => {
+            let message_id = api.next_message_id();
+            let message = api.message(message_id, &body);
+            api.record(Call { method: canonical(&method), body, message_id });
+            message
+        }
+        "editmessagetext" | "editmessagereplymarkup" => {
+            let message_id = body["message_id"].as_i64().unwrap_or_default() as i32;
+            let message = api.message(message_id, &body);
+            api.record(Call { method: canonical(&method), body, message_id });
+            message
+        }
+        _ => {
+            let message_id = body["message_id"].as_i64().unwrap_or_default() as i32;
+            api.record(Call { method: canonical(&method), body, message_id });
+            json!(true)
+        }
+    };
+    ([(header::CONTENT_TYPE, "application/json")], json!({ "ok": true, "result": result }).to_string())
+}
+
+/// teloxide пишет методы с заглавной буквы (`SendMessage`), в тестах они как в документации
+fn canonical(method: &str) -> String {
+    let mut chars = method.chars();
+    chars.next().map(|first| first.to_ascii_lowercase().to_string() + chars.as_str()).unwrap_or_default()
+}
+
+/// Локальный сервер, притворяющийся api.telegram.org
+pub struct FakeTelegram {
+    api: Arc<Api>,
+    url: String,
+    last_update_id: AtomicI32,
+}
+
+impl FakeTelegram {
+    pub async fn start() -> Self {
+        let api = Arc::new(Api::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let router = Router::new().route("/{token}/{method}", post(handle)).with_state(api.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        Self { api, url, last_update_id: AtomicI32::new(0) }
+    }
+
+    /// Бот, отправляющий запросы на этот сервер. Лимиты Telegram в тестах только замедляют
+    pub fn bot(&self) -> MyBot {
+        Bot::new(TOKEN)
+            .set_api_url(self.url.parse().unwrap())
+            .throttle(Limits {
+                messages_per_sec_chat: 1000,
+                messages_per_min_chat: 10_000,
+                messages_per_min_channel_or_supergroup: 10_000,
+                messages_per_sec_overall: 1000,
+            })
+    }
+
+    fn push(&self, mut update: Value) {
+        let update_id = self.last_update_id.fetch_add(1, Ordering::SeqCst) + 1;
+        update["update_id"] = json!(update_id);
+        self.api.updates.lock().unwrap().push(update);
+        self.api.new_update.notify_waiters();
+    }
+
+    /// Текстовое сообщение или команда от пользователя
+    pub fn send_text(&self, user: TestUser, text: &str) {
+        let message_id = self.api.next_message_id();
+        self.push(json!({
+            "message": {
+                "message_id": message_id,
+                "date": 1_700_000_000,
+                "chat": user.chat(),
+                "from": user.json(),
+                "text": text,
+            }
+        }));
+    }
+
+    /// Нажатие inline-кнопки под сообщением, которое бот отправил или изменил вызовом `message`
+    pub fn press(&self, user: TestUser, message: &Call, data: &str) {
+        let id = self.last_update_id.load(Ordering::SeqCst) + 1;
+        self.push(json!({
+            "callback_query": {
+                "id": format!("query{}", id),
+                "from": user.json(),
+                "chat_instance": "test",
+                "data": data,
+                "message": {
+                    "message_id": message.message_id,
+                    "date": 1_700_000_000,
+                    "chat": user.chat(),
+                    "from": { "id": BOT_ID, "is_bot": true, "first_name": "Qortex", "username": BOT_USERNAME },
+                    "text": message.text(),
+                },
+            }
+        }));
+    }
+
+    /// Следующий вызов бота, он должен быть вызовом `method`
+    pub async fn expect(&self, method: &str) -> Call {
+        let call = self.next_call().await;
+        assert_eq!(call.method, method, "Ожидался {}, бот вызвал {:?}", method, call);
+        call
+    }
+
+    pub async fn next_call(&self) -> Call {
+        let wait = async {
+            loop {
+                let notified = self.api.new_call.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                if let Some(call) = self.api.calls.lock().unwrap().pop_front() {
+                    return call;
+                }
+                notified.await;
+            }
+        };
+        match timeout(CALL_TIMEOUT, wait).await {
+            Ok(call) => call,
+            Err(_) => panic!("Бот ничего не вызвал за {:?}", CALL_TIMEOUT),
+        }
+    }
+}
+
+/// Запущенный бот на фейковом API и базе в памяти
+pub struct TestBot {
+    pub api: FakeTelegram,
+    pub store: Arc<MemoryStore>,
+    _files: tempfile::TempDir,
+}
+
+impl TestBot {
+    pub async fn start() -> Self {
+        Self::start_with(BotConfig::default()).await
+    }
+
+    pub async fn start_with(config: BotConfig) -> Self {
+        let api = FakeTelegram::start().await;
+        let store = Arc::new(MemoryStore::new());
+        let files = tempfile::tempdir().unwrap();
+        let file_storage = Arc::new(LocalStorage::new(files.path()).await.unwrap());
+        let dialogues: Arc<MyStorage> = InMemStorage::new().erase();
+        let config = BotConfig { download_server_addr: None, ..config };
+
+        let bots = TelegramBot::with_parts(api.bot(), store.clone(), dialogues, file_storage, None, &config);
+        tokio::spawn(bots.clone().run());
+        Self { api, store, _files: files }
+    }
+}
@@ -1,7 +1,9 @@
 use mongodb::bson::{oid::ObjectId, Bson, DateTime};
 use serde::{Deserialize, Serialize};
 
-
+pub const ROLE_SYSTEM: &str = "system";
+pub const ROLE_USER: &str = "user";
+pub const ROLE_ASSISTANT: &str = "assistant";
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct HistoryMessage {
@@ -10,6 +12,26 @@ pub struct HistoryMessage {
     pub timestamp: DateTime,
 }
 
+impl HistoryMessage {
+    // Question from user
+    pub fn user(content: String) -> Self {
+        Self {
+            role: ROLE_USER.to_string(),
+            content,
+            timestamp: DateTime::now(),
+        }
+    }
+
+    // Answer from AI
+    pub fn assistant(content: String) -> Self {
+        Self {
+            role: ROLE_ASSISTANT.to_string(),
+            content,
+            timestamp: DateTime::now(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UserHistory {
     #[serde(rename = "_id")]
@@ -29,7 +51,7 @@ impl UserHistory {
             id: Bson::ObjectId(ObjectId::new()),
             telegram_id,
             messages: vec![HistoryMessage {
-                role: "system".to_string(),
+                role: ROLE_SYSTEM.to_string(),
                 content: system_prompt,
                 timestamp: DateTime::now(),
             }]
@@ -45,7 +67,7 @@ impl UserHistory {
             id: Bson::ObjectId(ObjectId::new()),
             telegram_id,
             messages: vec![HistoryMessage {
-                role: "user".to_string(),
+                role: ROLE_USER.to_string(),
                 content: prompt,
                 timestamp: DateTime::now(),
             }]
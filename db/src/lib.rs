@@ -2,7 +2,7 @@ use std::{env, sync::{Arc, Mutex}};
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use collections::{answer::AnswerRequest, history::UserHistory, user::User};
+use collections::{answer::AnswerRequest, history::{HistoryMessage, UserHistory}, user::User};
 use mongodb::{bson::{doc, oid::ObjectId, to_bson, Document}, options::ClientOptions, Client, Collection};
 use serde::{Deserialize, Serialize};
 
@@ -80,8 +80,62 @@ impl Database {
             return Ok(StatusCode::ObjectIdError);
         }
     }
+
+    // Append messages to user history, history is limited to HISTORY_LIMIT last messages
+    pub async fn append_history(
+        &self,
+        telegram_id: i64,
+        messages: Vec<HistoryMessage>,
+    ) -> Result<()> {
+        self.history_collection
+            .update_one(
+                doc! { "telegram_id": telegram_id },
+                doc! {
+                    "$push": {
+                        "messages": {
+                            "$each": to_bson(&messages)?,
+                            "$slice": -HISTORY_LIMIT,
+                        }
+                    }
+                },
+            )
+            .upsert(true)
+            .await?;
+
+        Ok(())
+    }
+
+    // Last `limit` messages of user history, oldest first
+    pub async fn last_history(
+        &self,
+        telegram_id: i64,
+        limit: usize,
+    ) -> Result<Vec<HistoryMessage>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let history = self.history_collection
+            .find_one(doc! { "telegram_id": telegram_id })
+            .projection(doc! { "messages": { "$slice": -(limit as i64) } })
+            .await?;
+
+        Ok(history.map(|h| h.messages).unwrap_or_default())
+    }
+
+    // Clear user history, next question starts a fresh context
+    pub async fn clear_history(&self, telegram_id: i64) -> Result<()> {
+        self.history_collection
+            .delete_one(doc! { "telegram_id": telegram_id })
+            .await?;
+
+        Ok(())
+    }
 }
 
+// Max messages kept in one UserHistory document
+pub const HISTORY_LIMIT: i64 = 100;
+
 pub async fn db_test() -> mongodb::error::Result<()> {
     let uri = env::var("MONGODB_URI").expect("Incorrect database_string");
     println!("uri: {}", uri);
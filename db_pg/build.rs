@@ -0,0 +1,4 @@
+// sqlx::migrate! встраивает миграции при сборке, новые файлы должны пересобирать крейт
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,185 @@
+-- Схема, которую раньше создавал UserRepository::init_table.
+-- Все операторы идемпотентны, чтобы базы, созданные init_table, перешли на миграции без изменений
+
+CREATE TABLE IF NOT EXISTS users (
+    telegram_id BIGINT PRIMARY KEY,
+    username TEXT NOT NULL,
+    uuid UUID NOT NULL UNIQUE,
+    role TEXT NOT NULL DEFAULT 'default'
+);
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS language TEXT;
+
+-- Смена роли уходит в канал user_role, чтобы бот сбросил закэшированную роль
+CREATE OR REPLACE FUNCTION notify_user_role() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM pg_notify('user_role', OLD.telegram_id::text);
+    ELSIF TG_OP = 'INSERT' OR NEW.role IS DISTINCT FROM OLD.role THEN
+        PERFORM pg_notify('user_role', NEW.telegram_id::text);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS users_role_notify ON users;
+
+CREATE TRIGGER users_role_notify
+AFTER INSERT OR UPDATE OR DELETE ON users
+FOR EACH ROW EXECUTE FUNCTION notify_user_role();
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'message_status') THEN
+        CREATE TYPE message_status AS ENUM ('pending', 'accepted', 'answered', 'rejected');
+    END IF;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS messages (
+    id UUID PRIMARY KEY,
+    telegram_id BIGINT NOT NULL REFERENCES users(telegram_id) ON DELETE CASCADE,
+    text TEXT NOT NULL,
+    status message_status NOT NULL DEFAULT 'pending',
+    answer TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Любое изменение статуса или ответа, откуда бы оно ни пришло, уходит в канал message_status
+CREATE OR REPLACE FUNCTION notify_message_status() RETURNS trigger AS $$
+BEGIN
+    IF NEW.status IS DISTINCT FROM OLD.status OR NEW.answer IS DISTINCT FROM OLD.answer THEN
+        PERFORM pg_notify(
+            'message_status',
+            json_build_object('id', NEW.id, 'telegram_id', NEW.telegram_id, 'status', NEW.status)::text
+        );
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS messages_status_notify ON messages;
+
+CREATE TRIGGER messages_status_notify
+AFTER UPDATE ON messages
+FOR EACH ROW EXECUTE FUNCTION notify_message_status();
+
+CREATE TABLE IF NOT EXISTS dialogues (
+    chat_id BIGINT PRIMARY KEY,
+    state JSONB NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS ai_feedback (
+    id UUID PRIMARY KEY,
+    telegram_id BIGINT NOT NULL,
+    question TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    helpful BOOLEAN,
+    escalated_message_id UUID REFERENCES messages(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    rated_at TIMESTAMPTZ
+);
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'answer_style') THEN
+        CREATE TYPE answer_style AS ENUM ('precise', 'balanced', 'creative');
+    END IF;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS user_settings (
+    telegram_id BIGINT PRIMARY KEY,
+    answer_style answer_style NOT NULL DEFAULT 'balanced',
+    status_notifications BOOLEAN NOT NULL DEFAULT TRUE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'ledger_kind') THEN
+        CREATE TYPE ledger_kind AS ENUM ('welcome', 'grant', 'charge', 'refund');
+    END IF;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS wallet_ledger (
+    id BIGSERIAL PRIMARY KEY,
+    telegram_id BIGINT NOT NULL,
+    amount BIGINT NOT NULL,
+    kind ledger_kind NOT NULL,
+    reference UUID,
+    comment TEXT,
+    created_by BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS wallet_ledger_user_idx ON wallet_ledger (telegram_id, created_at DESC);
+
+-- Приветственные кредиты начисляются один раз
+CREATE UNIQUE INDEX IF NOT EXISTS wallet_ledger_welcome_idx ON wallet_ledger (telegram_id) WHERE kind = 'welcome';
+
+-- Записи журнала не меняются и не удаляются, исправления делаются новыми записями
+CREATE OR REPLACE FUNCTION wallet_ledger_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'wallet_ledger is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS wallet_ledger_append_only ON wallet_ledger;
+
+CREATE TRIGGER wallet_ledger_append_only
+BEFORE UPDATE OR DELETE ON wallet_ledger
+FOR EACH ROW EXECUTE FUNCTION wallet_ledger_append_only();
+
+CREATE TABLE IF NOT EXISTS role_audit (
+    id BIGSERIAL PRIMARY KEY,
+    telegram_id BIGINT NOT NULL,
+    old_role TEXT NOT NULL,
+    new_role TEXT NOT NULL,
+    changed_by BIGINT NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS role_audit_user_idx ON role_audit (telegram_id, created_at DESC);
+
+-- Журнал ролей, как и журнал кошелька, только дополняется
+CREATE OR REPLACE FUNCTION role_audit_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'role_audit is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS role_audit_append_only ON role_audit;
+
+CREATE TRIGGER role_audit_append_only
+BEFORE UPDATE OR DELETE ON role_audit
+FOR EACH ROW EXECUTE FUNCTION role_audit_append_only();
+
+CREATE TABLE IF NOT EXISTS files (
+    id UUID PRIMARY KEY,
+    owner_id BIGINT NOT NULL,
+    original_name TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    mime_type TEXT,
+    checksum TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (owner_id, original_name)
+);
+
+CREATE TABLE IF NOT EXISTS share_links (
+    token TEXT PRIMARY KEY,
+    file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    created_by BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ,
+    max_downloads INTEGER,
+    downloads INTEGER NOT NULL DEFAULT 0,
+    expired BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+-- Фоновая задача проверяет только действующие ссылки
+CREATE INDEX IF NOT EXISTS share_links_active_idx ON share_links (expires_at) WHERE NOT expired;
@@ -0,0 +1,23 @@
+-- UserRole в коде объявлен как Postgres enum user_role, а роль хранилась в TEXT
+CREATE TYPE user_role AS ENUM ('default', 'admin', 'withaccess', 'banned');
+
+-- Роли, заданные вручную, могли быть в другом регистре или с подчёркиванием.
+-- Неизвестные значения становятся 'default'
+CREATE FUNCTION pg_temp.to_user_role(role TEXT) RETURNS user_role AS $$
+    SELECT CASE replace(lower(trim(role)), '_', '')
+        WHEN 'admin' THEN 'admin'::user_role
+        WHEN 'withaccess' THEN 'withaccess'::user_role
+        WHEN 'banned' THEN 'banned'::user_role
+        ELSE 'default'::user_role
+    END
+$$ LANGUAGE sql;
+
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
+ALTER TABLE users ALTER COLUMN role TYPE user_role USING pg_temp.to_user_role(role);
+ALTER TABLE users ALTER COLUMN role SET DEFAULT 'default';
+
+ALTER TABLE role_audit
+    ALTER COLUMN old_role TYPE user_role USING pg_temp.to_user_role(old_role),
+    ALTER COLUMN new_role TYPE user_role USING pg_temp.to_user_role(new_role);
+
+DROP FUNCTION pg_temp.to_user_role(TEXT);
@@ -0,0 +1,5 @@
+-- Обращения пользователя по времени: списки и постраничный вывод в боте
+CREATE INDEX IF NOT EXISTS messages_user_created_idx ON messages (telegram_id, created_at);
+
+-- Очередь модерации выбирает обращения по статусу
+CREATE INDEX IF NOT EXISTS messages_status_idx ON messages (status);
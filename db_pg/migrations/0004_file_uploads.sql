@@ -0,0 +1,11 @@
+-- Загрузка файла с тем же именем идёт только одна: бот занимает имя, пишет содержимое
+-- и метаданные и освобождает его. Брошенная загрузка перехватывается по started_at.
+-- Размер незавершённых загрузок учитывается в квоте владельца наравне с его файлами
+CREATE TABLE IF NOT EXISTS file_uploads (
+    owner_id BIGINT NOT NULL,
+    original_name TEXT NOT NULL,
+    token UUID NOT NULL,
+    size BIGINT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (owner_id, original_name)
+);
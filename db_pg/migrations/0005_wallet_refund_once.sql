@@ -0,0 +1,15 @@
+-- Ответ возвращается не больше одного раза, даже если возврат повторили.
+-- Прежний refund мог записать повторные возвраты, из них остаётся самый ранний.
+-- Журнал только дополняется, поэтому защита от удаления на время снимается
+ALTER TABLE wallet_ledger DISABLE TRIGGER wallet_ledger_append_only;
+
+DELETE FROM wallet_ledger duplicate
+USING wallet_ledger kept
+WHERE duplicate.kind = 'refund'
+  AND kept.kind = 'refund'
+  AND kept.reference = duplicate.reference
+  AND kept.id < duplicate.id;
+
+ALTER TABLE wallet_ledger ENABLE TRIGGER wallet_ledger_append_only;
+
+CREATE UNIQUE INDEX IF NOT EXISTS wallet_ledger_refund_idx ON wallet_ledger (reference) WHERE kind = 'refund';
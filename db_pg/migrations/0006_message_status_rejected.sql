@@ -0,0 +1,5 @@
+-- no-transaction
+-- В базах, созданных до миграций, у message_status нет 'rejected'.
+-- Новое значение enum нельзя использовать в той же транзакции, а до PostgreSQL 12
+-- ADD VALUE в транзакции не выполняется вовсе, поэтому миграция идёт без неё
+ALTER TYPE message_status ADD VALUE IF NOT EXISTS 'rejected';
@@ -0,0 +1,105 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::postgres::PgPool;
+use teloxide::dispatching::dialogue::Storage;
+use teloxide::types::ChatId;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DialogueStorageError {
+    #[error("dialogue serialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("postgres error: {0}")]
+    Postgres(#[from] sqlx::Error),
+    #[error("dialogue for chat {0} not found")]
+    DialogueNotFound(i64),
+}
+
+/// FSM storage for teloxide dialogues, state is kept as JSONB in `dialogues` table
+pub struct PgDialogueStorage<D> {
+    pool: PgPool,
+    _state: PhantomData<fn() -> D>,
+}
+
+impl<D> PgDialogueStorage<D> {
+    /// Table `dialogues` must exist, see `UserRepository::migrate`
+    pub fn new(pool: PgPool) -> Arc<Self> {
+        Arc::new(Self { pool, _state: PhantomData })
+    }
+}
+
+impl<D> Storage<D> for PgDialogueStorage<D>
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = DialogueStorageError;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let deleted = sqlx::query("DELETE FROM dialogues WHERE chat_id = $1")
+                .bind(chat_id)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+
+            if deleted == 0 {
+                return Err(DialogueStorageError::DialogueNotFound(chat_id));
+            }
+
+            Ok(())
+        })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let state = serde_json::to_value(&dialogue)?;
+            sqlx::query(
+                r#"
+                INSERT INTO dialogues (chat_id, state, updated_at)
+                VALUES ($1, $2, NOW())
+                ON CONFLICT (chat_id) DO UPDATE
+                SET state = EXCLUDED.state, updated_at = NOW()
+                "#,
+            )
+            .bind(chat_id)
+            .bind(state)
+            .execute(&self.pool)
+            .await?;
+
+            Ok(())
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+    ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            let state: Option<serde_json::Value> =
+                sqlx::query_scalar("SELECT state FROM dialogues WHERE chat_id = $1")
+                    .bind(chat_id)
+                    .fetch_optional(&self.pool)
+                    .await?;
+
+            // Состояние от старой версии бота не должно навсегда блокировать чат,
+            // поэтому нечитаемое значение считаем отсутствующим
+            Ok(state.and_then(|s| serde_json::from_value(s).ok()))
+        })
+    }
+}
@@ -0,0 +1,145 @@
+use std::{error, future::Future, time::Duration};
+
+use sqlx::migrate::MigrateError;
+use thiserror::Error;
+
+/// How many times a connection is requested before giving up
+const RETRY_ATTEMPTS: u32 = 3;
+/// Pause before the first retry, doubled for every next one
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Error of a `UserRepository` method
+#[derive(Debug, Error)]
+pub enum DbError {
+    /// A row the method relies on does not exist
+    #[error("record not found")]
+    NotFound,
+    /// Unique, foreign key, check or not-null constraint violated
+    #[error("constraint `{constraint}` violated: {message}")]
+    Constraint { constraint: String, message: String },
+    /// Postgres is unreachable or dropped the connection, worth retrying later
+    #[error("database connection error: {0}")]
+    Connection(#[source] sqlx::Error),
+    /// No free connection in the pool or the statement was cancelled by timeout
+    #[error("database timeout: {0}")]
+    Timeout(#[source] sqlx::Error),
+    /// A row or a notification doesn't match the expected type
+    #[error("failed to decode database value: {0}")]
+    Decode(#[source] Box<dyn error::Error + Send + Sync>),
+    #[error("database migration failed: {0}")]
+    Migrate(#[from] MigrateError),
+    #[error("database error: {0}")]
+    Query(#[source] sqlx::Error),
+}
+
+impl DbError {
+    /// Connection and timeout errors, the same request may succeed a bit later
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Connection(_) | Self::Timeout(_))
+    }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => Self::NotFound,
+            sqlx::Error::PoolTimedOut => Self::Timeout(e),
+            sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::PoolClosed | sqlx::Error::WorkerCrashed => {
+                Self::Connection(e)
+            }
+            sqlx::Error::Decode(_)
+            | sqlx::Error::ColumnDecode { .. }
+            | sqlx::Error::ColumnNotFound(_)
+            | sqlx::Error::ColumnIndexOutOfBounds { .. }
+            | sqlx::Error::TypeNotFound { .. } => Self::Decode(Box::new(e)),
+            sqlx::Error::Database(ref db_error) => {
+                let code = db_error.code().unwrap_or_default();
+                match &code[..code.len().min(2)] {
+                    // integrity_constraint_violation
+                    "23" => Self::Constraint {
+                        constraint: db_error.constraint().unwrap_or_default().to_string(),
+                        message: db_error.message().to_string(),
+                    },
+                    // connection_exception
+                    "08" => Self::Connection(e),
+                    // query_canceled by statement_timeout
+                    _ if code == "57014" => Self::Timeout(e),
+                    // admin_shutdown, crash_shutdown, cannot_connect_now
+                    _ if matches!(code.as_ref(), "57P01" | "57P02" | "57P03") => Self::Connection(e),
+                    _ => Self::Query(e),
+                }
+            }
+            _ => Self::Query(e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for DbError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Decode(Box::new(e))
+    }
+}
+
+impl From<std::num::ParseIntError> for DbError {
+    fn from(e: std::num::ParseIntError) -> Self {
+        Self::Decode(Box::new(e))
+    }
+}
+
+/// Repeats `operation` while it fails with a transient error.
+/// Used only where nothing was sent to Postgres yet, so a retry never repeats a write
+pub(crate) async fn retry<T, F, Fut>(mut operation: F) -> Result<T, DbError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, DbError>>,
+{
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if e.is_transient() && attempt < RETRY_ATTEMPTS => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn classifies_sqlx_errors() {
+        assert!(matches!(DbError::from(sqlx::Error::RowNotFound), DbError::NotFound));
+        assert!(DbError::from(sqlx::Error::PoolTimedOut).is_transient());
+        let io = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        assert!(DbError::from(sqlx::Error::Io(io)).is_transient());
+        assert!(!DbError::from(sqlx::Error::ColumnNotFound("role".to_string())).is_transient());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_only_transient_errors() {
+        let calls = AtomicU32::new(0);
+        let result: Result<(), DbError> = retry(|| async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(DbError::from(sqlx::Error::PoolTimedOut))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), RETRY_ATTEMPTS);
+
+        let calls = AtomicU32::new(0);
+        let result: Result<(), DbError> = retry(|| async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(DbError::NotFound)
+        })
+        .await;
+        assert!(matches!(result, Err(DbError::NotFound)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,380 @@
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::{Result, UserRepository};
+
+/// File uploaded to the file sharing menu, the content itself is kept by the bot under `id`
+#[derive(Debug, Clone, FromRow)]
+pub struct StoredFile {
+    pub id: Uuid,
+    pub owner_id: i64,
+    /// Name from Telegram, unique per owner. Used only for display and downloads
+    pub original_name: String,
+    /// Size in bytes
+    pub size: i64,
+    pub mime_type: Option<String>,
+    /// SHA-256 of the content, hex
+    pub checksum: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Metadata of a file being saved
+#[derive(Debug, Clone)]
+pub struct NewFile<'a> {
+    /// Id for a new row, a replaced file keeps its old id
+    pub id: Uuid,
+    pub owner_id: i64,
+    pub original_name: &'a str,
+    pub size: i64,
+    pub mime_type: Option<&'a str>,
+    pub checksum: &'a str,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct ShareLink {
+    pub token: String,
+    pub file_id: Uuid,
+    pub created_by: i64,
+    pub created_at: DateTime<Utc>,
+    /// None for links without a lifetime
+    pub expires_at: Option<DateTime<Utc>>,
+    /// None for unlimited downloads
+    pub max_downloads: Option<i32>,
+    pub downloads: i32,
+    /// Set by `expire_share_links` once the lifetime or the limit is over
+    pub expired: bool,
+}
+
+impl ShareLink {
+    /// Why the link can't be downloaded any more, None while it is open.
+    /// `expired` is also set for exhausted links, so the limit is checked first
+    pub fn closed(&self, now: DateTime<Utc>) -> Option<ShareRedeem> {
+        if self.max_downloads.is_some_and(|max| self.downloads >= max) {
+            return Some(ShareRedeem::LimitReached);
+        }
+        if self.expired || self.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Some(ShareRedeem::Expired);
+        }
+        None
+    }
+}
+
+/// Files and bytes stored by a user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromRow)]
+pub struct FileUsage {
+    pub files: i64,
+    pub bytes: i64,
+}
+
+/// Result of opening a share link
+#[derive(Debug, Clone)]
+pub enum ShareRedeem {
+    /// Download counted, the file can be sent
+    Redeemed(StoredFile),
+    NotFound,
+    Expired,
+    LimitReached,
+}
+
+const FILE_COLUMNS: &str = "id, owner_id, original_name, size, mime_type, checksum, created_at";
+
+/// Claim of an upload that was not released in time is taken over by the next upload
+pub const UPLOAD_CLAIM_TTL_SECS: i64 = 600;
+
+/// Upload that holds its file name until it is released
+#[derive(Debug, Clone, Copy)]
+pub struct FileClaim {
+    pub token: Uuid,
+    /// Files of the owner together with the other uploads in progress,
+    /// the quota is checked against it
+    pub usage: FileUsage,
+}
+
+impl UserRepository {
+    /// Saves metadata of an uploaded file, a file with the same name is replaced
+    /// and keeps its id, so its share links stay valid
+    pub async fn upsert_file(&self, file: NewFile<'_>) -> Result<StoredFile> {
+        let stored = sqlx::query_as::<_, StoredFile>(&format!(
+            r#"
+            INSERT INTO files (id, owner_id, original_name, size, mime_type, checksum)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (owner_id, original_name) DO UPDATE
+            SET size = EXCLUDED.size,
+                mime_type = EXCLUDED.mime_type,
+                checksum = EXCLUDED.checksum,
+                created_at = NOW()
+            RETURNING {}
+            "#,
+            FILE_COLUMNS
+        ))
+        .bind(file.id)
+        .bind(file.owner_id)
+        .bind(file.original_name)
+        .bind(file.size)
+        .bind(file.mime_type)
+        .bind(file.checksum)
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+
+        Ok(stored)
+    }
+
+    /// Claims the file name of the owner for an upload of `size` bytes, None while another
+    /// upload of the same name holds it. Claims of one owner are serialized with an advisory
+    /// lock, so every claim sees the uploads claimed before it in `FileClaim::usage`
+    pub async fn claim_file_upload(&self, owner_id: i64, original_name: &str, size: i64) -> Result<Option<FileClaim>> {
+        let mut tx = self.begin().await?;
+
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(owner_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let token: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            INSERT INTO file_uploads (owner_id, original_name, token, size)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (owner_id, original_name) DO UPDATE
+            SET token = EXCLUDED.token,
+                size = EXCLUDED.size,
+                started_at = NOW()
+            WHERE file_uploads.started_at < NOW() - make_interval(secs => $5)
+            RETURNING token
+            "#,
+        )
+        .bind(owner_id)
+        .bind(original_name)
+        .bind(Uuid::new_v4())
+        .bind(size)
+        .bind(UPLOAD_CLAIM_TTL_SECS as f64)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(token) = token else {
+            tx.rollback().await?;
+            return Ok(None);
+        };
+
+        // A new name adds a file, a replaced one only the bytes it grows by
+        let usage = sqlx::query_as::<_, FileUsage>(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM files WHERE owner_id = $1)
+                    + COUNT(u.token) FILTER (WHERE f.id IS NULL) AS files,
+                (SELECT COALESCE(SUM(size), 0) FROM files WHERE owner_id = $1)::BIGINT
+                    + COALESCE(SUM(GREATEST(u.size - COALESCE(f.size, 0), 0)), 0)::BIGINT AS bytes
+            FROM file_uploads u
+            LEFT JOIN files f ON f.owner_id = u.owner_id AND f.original_name = u.original_name
+            WHERE u.owner_id = $1
+              AND u.original_name <> $2
+              AND u.started_at >= NOW() - make_interval(secs => $3)
+            "#,
+        )
+        .bind(owner_id)
+        .bind(original_name)
+        .bind(UPLOAD_CLAIM_TTL_SECS as f64)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(FileClaim { token, usage }))
+    }
+
+    /// Releases the claim, a claim already taken over by another upload stays
+    pub async fn release_file_upload(&self, owner_id: i64, original_name: &str, token: Uuid) -> Result<()> {
+        sqlx::query("DELETE FROM file_uploads WHERE owner_id = $1 AND original_name = $2 AND token = $3")
+            .bind(owner_id)
+            .bind(original_name)
+            .bind(token)
+            .execute(&mut *self.conn().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_file(&self, id: Uuid) -> Result<Option<StoredFile>> {
+        let file = sqlx::query_as::<_, StoredFile>(&format!("SELECT {} FROM files WHERE id = $1", FILE_COLUMNS))
+            .bind(id)
+            .fetch_optional(&mut *self.conn().await?)
+            .await?;
+
+        Ok(file)
+    }
+
+    pub async fn get_file_by_name(&self, owner_id: i64, original_name: &str) -> Result<Option<StoredFile>> {
+        let file = sqlx::query_as::<_, StoredFile>(&format!(
+            "SELECT {} FROM files WHERE owner_id = $1 AND original_name = $2",
+            FILE_COLUMNS
+        ))
+        .bind(owner_id)
+        .bind(original_name)
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+
+        Ok(file)
+    }
+
+    pub async fn get_file_usage(&self, owner_id: i64) -> Result<FileUsage> {
+        let usage = sqlx::query_as::<_, FileUsage>(
+            "SELECT COUNT(*) AS files, COALESCE(SUM(size), 0)::BIGINT AS bytes FROM files WHERE owner_id = $1"
+        )
+        .bind(owner_id)
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+
+        Ok(usage)
+    }
+
+    /// Deletes the file of the owner together with its share links, returns the deleted row
+    pub async fn delete_file(&self, owner_id: i64, id: Uuid) -> Result<Option<StoredFile>> {
+        let file = sqlx::query_as::<_, StoredFile>(&format!(
+            "DELETE FROM files WHERE id = $1 AND owner_id = $2 RETURNING {}",
+            FILE_COLUMNS
+        ))
+        .bind(id)
+        .bind(owner_id)
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+
+        Ok(file)
+    }
+
+    /// Files of the user by name
+    pub async fn get_user_files(&self, owner_id: i64, offset: i64, limit: i64) -> Result<Vec<StoredFile>> {
+        let files = sqlx::query_as::<_, StoredFile>(&format!(
+            r#"
+            SELECT {}
+            FROM files
+            WHERE owner_id = $1
+            ORDER BY original_name, id
+            OFFSET $2
+            LIMIT $3
+            "#,
+            FILE_COLUMNS
+        ))
+        .bind(owner_id)
+        .bind(offset)
+        .bind(limit)
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+
+        Ok(files)
+    }
+
+    pub async fn create_share_link(
+        &self,
+        token: &str,
+        file_id: Uuid,
+        created_by: i64,
+        expires_at: Option<DateTime<Utc>>,
+        max_downloads: Option<i32>,
+    ) -> Result<ShareLink> {
+        let link = sqlx::query_as::<_, ShareLink>(
+            r#"
+            INSERT INTO share_links (token, file_id, created_by, expires_at, max_downloads)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING token, file_id, created_by, created_at, expires_at, max_downloads, downloads, expired
+            "#,
+        )
+        .bind(token)
+        .bind(file_id)
+        .bind(created_by)
+        .bind(expires_at)
+        .bind(max_downloads)
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+
+        Ok(link)
+    }
+
+    /// Checks the link and counts a download. The row is locked, so concurrent
+    /// downloads never go over `max_downloads`
+    pub async fn redeem_share_link(&self, token: &str) -> Result<ShareRedeem> {
+        let mut tx = self.begin().await?;
+
+        let link = sqlx::query_as::<_, ShareLink>(
+            r#"
+            SELECT token, file_id, created_by, created_at, expires_at, max_downloads, downloads, expired
+            FROM share_links
+            WHERE token = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(token)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(link) = link else {
+            tx.rollback().await?;
+            return Ok(ShareRedeem::NotFound);
+        };
+        if let Some(closed) = link.closed(Utc::now()) {
+            tx.rollback().await?;
+            return Ok(closed);
+        }
+
+        sqlx::query("UPDATE share_links SET downloads = downloads + 1 WHERE token = $1")
+            .bind(token)
+            .execute(&mut *tx)
+            .await?;
+
+        let file = sqlx::query_as::<_, StoredFile>(&format!("SELECT {} FROM files WHERE id = $1", FILE_COLUMNS))
+            .bind(link.file_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(ShareRedeem::Redeemed(file))
+    }
+
+    /// Checks the link without counting a download. With `counted` the caller ties the
+    /// request to a download already counted by `redeem_share_link`, so only the lifetime
+    /// is checked, otherwise the rules are the same as for `redeem_share_link`
+    pub async fn peek_share_link(&self, token: &str, counted: bool) -> Result<ShareRedeem> {
+        let link = sqlx::query_as::<_, ShareLink>(
+            r#"
+            SELECT token, file_id, created_by, created_at, expires_at, max_downloads, downloads, expired
+            FROM share_links
+            WHERE token = $1
+            "#,
+        )
+        .bind(token)
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+
+        let Some(link) = link else {
+            return Ok(ShareRedeem::NotFound);
+        };
+        let now = Utc::now();
+        if counted && link.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Ok(ShareRedeem::Expired);
+        }
+        if !counted && let Some(closed) = link.closed(now) {
+            return Ok(closed);
+        }
+
+        Ok(match self.get_file(link.file_id).await? {
+            Some(file) => ShareRedeem::Redeemed(file),
+            None => ShareRedeem::NotFound,
+        })
+    }
+
+    /// Marks links with an expired lifetime or exhausted downloads, returns how many were marked
+    pub async fn expire_share_links(&self) -> Result<u64> {
+        let expired = sqlx::query(
+            r#"
+            UPDATE share_links
+            SET expired = TRUE
+            WHERE NOT expired
+              AND ((expires_at IS NOT NULL AND expires_at <= NOW())
+                OR (max_downloads IS NOT NULL AND downloads >= max_downloads))
+            "#,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?
+        .rows_affected();
+
+        Ok(expired)
+    }
+}
@@ -1,46 +1,80 @@
-use std::{error, fmt};
+use std::fmt;
 use std::time::Duration;
 
-use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use serde::{Deserialize, Serialize};
+use sqlx::migrate::Migrator;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::{PgPool, PgPoolOptions};
 use sqlx::types::chrono::{DateTime, Utc};
-use sqlx::{FromRow, Row};
+use sqlx::{FromRow, Postgres, Transaction};
 use uuid::Uuid;
 
+pub mod dialogue;
+pub mod error;
+pub mod files;
+pub mod memory;
+pub mod notifications;
+pub mod paging;
+pub mod roles;
+pub mod settings;
+pub mod store;
+pub mod wallet;
+
+pub use dialogue::{DialogueStorageError, PgDialogueStorage};
+pub use error::DbError;
+pub use files::{FileClaim, FileUsage, NewFile, ShareLink, ShareRedeem, StoredFile};
+pub use memory::MemoryStore;
+pub use paging::{MessageCursor, MessagePage, PageCursor};
+pub use roles::RoleAuditEntry;
+pub use notifications::{MessageStatusEvent, MessageStatusListener, UserRoleListener, MESSAGE_STATUS_CHANNEL, USER_ROLE_CHANNEL};
+pub use settings::{AnswerStyle, UserSettings};
+pub use store::{FileStore, MessageStore, Store, UserStore};
+pub use wallet::{LedgerEntry, LedgerKind};
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
 #[sqlx(type_name= "user_role", rename_all="lowercase")]
 pub enum UserRole {
     Default,
     Admin,
     WithAccess,
+    /// Only receives notifications, messages and buttons are answered with a refusal
+    Banned,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
 #[sqlx(type_name = "message_status", rename_all = "lowercase")]
 pub enum MessageStatus {
     Pending,    // Ожидает рассмотрения
     Accepted,   // Принято в работу
     Answered,   // Ответ дан
+    Rejected,   // Отклонено администратором
 }
 
+/// Name of the status as stored in Postgres, for logs. Users see `status.*` texts
+/// from the localization catalogs
 impl fmt::Display for MessageStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Pending => write!(f, "Ожидает рассмотрения"),
-            Self::Accepted => write!(f, "Принято в работу"),
-            Self::Answered => write!(f, "Ответ дан"),
+            Self::Pending => write!(f, "pending"),
+            Self::Accepted => write!(f, "accepted"),
+            Self::Answered => write!(f, "answered"),
+            Self::Rejected => write!(f, "rejected"),
         }
     }
 }
 
-#[derive(Debug, FromRow)]
+#[derive(Debug, Clone, FromRow)]
 pub struct User {
     pub telegram_id: i64,
     pub username: Option<String>,
     pub uuid: Uuid,
     pub role: UserRole,
+    /// Язык, выбранный пользователем, перекрывает `language_code` из Telegram
+    pub language: Option<String>,
 }
 
-#[derive(Debug, Clone, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Message {
     pub id: Uuid,
     pub telegram_id: i64,
@@ -51,90 +85,85 @@ pub struct Message {
     pub updated_at: DateTime<Utc>,
 }
 
+/// Answer of Qortex AI with user rating, `helpful` is None until rated
+#[derive(Debug, Clone, FromRow)]
+pub struct AiFeedback {
+    pub id: Uuid,
+    pub telegram_id: i64,
+    pub question: String,
+    pub answer: String,
+    pub helpful: Option<bool>,
+    pub escalated_message_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub rated_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct UserRepository {
     pub pool: PgPool,
 }
 
-type Result<T> = std::result::Result<T, Box<dyn error::Error + Send + Sync>>;
+/// Migrations from `db_pg/migrations`, embedded at build time. Applied versions
+/// are kept in `_sqlx_migrations`, so every migration runs once
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+pub(crate) type Result<T> = std::result::Result<T, DbError>;
 
 impl UserRepository {
     pub async fn new(url: &str) -> Result<Self> {
-        let pool = PgPoolOptions::new()
-            .max_connections(5)
-            .acquire_timeout(Duration::from_secs(3))
-            .connect(url)
-            .await
-            .expect("Failed to create db pool");
+        let pool = error::retry(|| async {
+            Ok(PgPoolOptions::new()
+                .max_connections(5)
+                .acquire_timeout(Duration::from_secs(3))
+                .connect(url)
+                .await?)
+        })
+        .await?;
         Ok(Self { pool })
     }
 
+    /// Connection from the pool. Acquiring is retried on transient errors,
+    /// queries are not, so a write is never sent twice
+    pub(crate) async fn conn(&self) -> Result<PoolConnection<Postgres>> {
+        error::retry(|| async { Ok(self.pool.acquire().await?) }).await
+    }
 
-    pub async fn init_table(&self) -> Result<()> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS users (
-                telegram_id BIGINT PRIMARY KEY,
-                username TEXT NOT NULL,
-                uuid UUID NOT NULL UNIQUE,
-                role TEXT NOT NULL DEFAULT 'default'
-            );
-            "#
-        )
-        .execute(&self.pool)
-        .await
-        .expect("Failed to create users table");
-
-        sqlx::query(
-            r#"
-            DO $$
-            BEGIN
-                IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'message_status') THEN
-                    CREATE TYPE message_status AS ENUM ('pending', 'accepted', 'answered');
-                END IF;
-            END
-            $$;
-            "#
-        )
-        .execute(&self.pool)
-        .await
-    .expect("Failed to create message_status enum type");
+    /// Transaction on a connection acquired like in `conn`
+    pub(crate) async fn begin(&self) -> Result<Transaction<'static, Postgres>> {
+        error::retry(|| async { Ok(self.pool.begin().await?) }).await
+    }
 
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS messages (
-                id UUID PRIMARY KEY,
-                telegram_id BIGINT NOT NULL REFERENCES users(telegram_id) ON DELETE CASCADE,
-                text TEXT NOT NULL,
-                status message_status NOT NULL DEFAULT 'pending',
-                answer TEXT,
-                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
-                updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
-            );
-            "#,
-        )
-        .execute(&self.pool)
-        .await
-        .expect("Failed to create messages table");
 
+    /// Applies embedded migrations that are not applied yet, see `MIGRATOR`
+    pub async fn migrate(&self) -> Result<()> {
+        MIGRATOR.run(&self.pool).await?;
         Ok(())
     }
 
+    /// Version of the last applied migration, None for an empty database
+    pub async fn schema_version(&self) -> Result<Option<i64>> {
+        let version = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success")
+            .fetch_one(&mut *self.conn().await?)
+            .await?;
+
+        Ok(version)
+    }
+
     pub async fn add_user(&self, user: &User) -> Result<()> {
         sqlx::query(
             r#"
-            INSERT INTO users (telegram_id, username, uuid, role)
-            VALUES ($1, $2, $3, $4)
+            INSERT INTO users (telegram_id, username, uuid, role, language)
+            VALUES ($1, $2, $3, $4, $5)
             ON CONFLICT (telegram_id) DO NOTHING
             "#
         )
         .bind(user.telegram_id)
-        .bind(&user.username.clone().unwrap_or("None".to_string()))
+        .bind(user.username.clone().unwrap_or("None".to_string()))
         .bind(user.uuid)
         .bind(user.role)
-        .execute(&self.pool)
-        .await
-        .expect("Failed to insert user");
+        .bind(&user.language)
+        .execute(&mut *self.conn().await?)
+        .await?;
         
         Ok(())
     }
@@ -144,50 +173,89 @@ impl UserRepository {
             "DELETE FROM users WHERE telegram_id = $1"
         )
         .bind(telegram_id)
-        .execute(&self.pool)
-        .await
-        .expect("Failed to delete user");
+        .execute(&mut *self.conn().await?)
+        .await?;
         
         Ok(())
     }
 
-    pub async fn check_role(&self, telegram_id: i64, required_role: UserRole) -> Result<bool> {
+    /// Role of the user, `Default` for unknown users
+    pub async fn get_role(&self, telegram_id: i64) -> Result<UserRole> {
         let role: UserRole = sqlx::query_scalar(
             "SELECT role FROM users WHERE telegram_id = $1"
         )
         .bind(telegram_id)
-        .fetch_optional(&self.pool)
-        .await
-        .expect("Failed to fetch user role")
+        .fetch_optional(&mut *self.conn().await?)
+        .await?
         .unwrap_or(UserRole::Default);
-        
-        Ok(role == required_role)
+
+        Ok(role)
+    }
+
+    /// Has the user any of `allowed` roles
+    pub async fn check_role(&self, telegram_id: i64, allowed: &[UserRole]) -> Result<bool> {
+        let role = self.get_role(telegram_id).await?;
+
+        Ok(allowed.contains(&role))
     }
 
     pub async fn find_by_username(&self, username: &str) -> Result<Option<User>> {
         let user = sqlx::query_as::<_, User>(
-            "SELECT telegram_id, username, uuid, role FROM users WHERE username = $1"
+            "SELECT telegram_id, username, uuid, role, language FROM users WHERE username = $1"
         )
         .bind(username)
-        .fetch_optional(&self.pool)
-        .await
-        .expect("Failed to find user by username");
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
         
         Ok(user)
     }
 
     pub async fn get_user(&self, user_uuid: Uuid) -> Result<Option<User>> {
         let user = sqlx::query_as::<_, User>(
-            "SELECT telegram_id, username, uuid, role FROM users WHERE uuid = $1"
+            "SELECT telegram_id, username, uuid, role, language FROM users WHERE uuid = $1"
         )
         .bind(user_uuid)
-        .fetch_optional(&self.pool)
-        .await
-        .expect("Failed to get user");
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
         
         Ok(user)
     }
 
+    pub async fn get_user_by_telegram_id(&self, telegram_id: i64) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            "SELECT telegram_id, username, uuid, role, language FROM users WHERE telegram_id = $1"
+        )
+        .bind(telegram_id)
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+
+        Ok(user)
+    }
+
+    pub async fn get_user_language(&self, telegram_id: i64) -> Result<Option<String>> {
+        let language: Option<Option<String>> = sqlx::query_scalar(
+            "SELECT language FROM users WHERE telegram_id = $1"
+        )
+        .bind(telegram_id)
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+
+        Ok(language.flatten())
+    }
+
+    /// `None` сбрасывает выбор, язык снова берётся из Telegram
+    pub async fn set_user_language(&self, telegram_id: i64, language: Option<&str>) -> Result<()> {
+        sqlx::query(
+            "UPDATE users SET language = $1 WHERE telegram_id = $2"
+        )
+        .bind(language)
+        .bind(telegram_id)
+        .execute(&mut *self.conn().await?)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn add_message(&self, telegram_id: i64, text: &str) -> Result<Uuid> {
         let message_id = Uuid::new_v4();
         
@@ -200,9 +268,8 @@ impl UserRepository {
         .bind(message_id)
         .bind(telegram_id)
         .bind(text)
-        .execute(&self.pool)
-        .await
-        .expect("Failed to insert message");
+        .execute(&mut *self.conn().await?)
+        .await?;
 
         Ok(message_id)
     }
@@ -226,9 +293,19 @@ impl UserRepository {
         .bind(new_status)
         .bind(answer)
         .bind(message_id)
-        .execute(&self.pool)
-        .await
-        .expect("Failed to update message status");
+        .execute(&mut *self.conn().await?)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Changes only the status, the answer stays as it is
+    pub async fn set_message_status(&self, message_id: Uuid, new_status: MessageStatus) -> Result<()> {
+        sqlx::query("UPDATE messages SET status = $1, updated_at = NOW() WHERE id = $2")
+            .bind(new_status)
+            .bind(message_id)
+            .execute(&mut *self.conn().await?)
+            .await?;
 
         Ok(())
     }
@@ -243,9 +320,25 @@ impl UserRepository {
             "#,
         )
         .bind(telegram_id)
-        .fetch_all(&self.pool)
-        .await
-        .expect("Failed to fetch messages");
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+
+        Ok(messages)
+    }
+
+    pub async fn get_user_messages_by_status(&self, telegram_id: i64, status: MessageStatus) -> Result<Vec<Message>> {
+        let messages = sqlx::query_as::<_, Message>(
+            r#"
+            SELECT id, telegram_id, text, status, answer, created_at, updated_at
+            FROM messages
+            WHERE telegram_id = $1 AND status = $2
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(telegram_id)
+        .bind(status)
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
 
         Ok(messages)
     }
@@ -259,9 +352,8 @@ impl UserRepository {
             "#,
         )
         .bind(message_id)
-        .fetch_optional(&self.pool)
-        .await
-        .expect("Failed to fetch message by ID");
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
 
         Ok(message)
     }
@@ -276,12 +368,131 @@ impl UserRepository {
             "#,
         )
         .bind(status)
-        .fetch_all(&self.pool)
-        .await
-        .expect("Failed to fetch messages by status");
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
 
         Ok(messages)
     }
+
+    pub async fn get_messages_by_statuses(&self, statuses: &[MessageStatus]) -> Result<Vec<Message>> {
+        let messages = sqlx::query_as::<_, Message>(
+            r#"
+            SELECT id, telegram_id, text, status, answer, created_at, updated_at
+            FROM messages
+            WHERE status = ANY($1)
+            ORDER BY created_at ASC
+            "#,
+        )
+        .bind(statuses)
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+
+        Ok(messages)
+    }
+
+    pub async fn add_ai_answer(
+        &self,
+        answer_id: Uuid,
+        telegram_id: i64,
+        question: &str,
+        answer: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO ai_feedback (id, telegram_id, question, answer)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(answer_id)
+        .bind(telegram_id)
+        .bind(question)
+        .bind(answer)
+        .execute(&mut *self.conn().await?)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Rates answer of the user, None if there is no such answer
+    pub async fn rate_ai_answer(
+        &self,
+        answer_id: Uuid,
+        telegram_id: i64,
+        helpful: bool,
+    ) -> Result<Option<AiFeedback>> {
+        let feedback = sqlx::query_as::<_, AiFeedback>(
+            r#"
+            UPDATE ai_feedback
+            SET helpful = $1, rated_at = NOW()
+            WHERE id = $2 AND telegram_id = $3
+            RETURNING id, telegram_id, question, answer, helpful, escalated_message_id, created_at, rated_at
+            "#,
+        )
+        .bind(helpful)
+        .bind(answer_id)
+        .bind(telegram_id)
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+
+        Ok(feedback)
+    }
+
+    pub async fn get_ai_feedback(&self, answer_id: Uuid) -> Result<Option<AiFeedback>> {
+        let feedback = sqlx::query_as::<_, AiFeedback>(
+            r#"
+            SELECT id, telegram_id, question, answer, helpful, escalated_message_id, created_at, rated_at
+            FROM ai_feedback
+            WHERE id = $1
+            "#,
+        )
+        .bind(answer_id)
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+
+        Ok(feedback)
+    }
+
+    /// Creates an admin request from the question of the user's answer and links them in one
+    /// transaction. The feedback row is locked, so repeated taps create a single request.
+    /// Returns the request id, None if the answer is not the user's or was already escalated
+    pub async fn escalate_ai_feedback(&self, answer_id: Uuid, telegram_id: i64) -> Result<Option<Uuid>> {
+        let mut tx = self.begin().await?;
+
+        let question: Option<String> = sqlx::query_scalar(
+            r#"
+            SELECT question
+            FROM ai_feedback
+            WHERE id = $1 AND telegram_id = $2 AND escalated_message_id IS NULL
+            FOR UPDATE
+            "#,
+        )
+        .bind(answer_id)
+        .bind(telegram_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(question) = question else {
+            tx.rollback().await?;
+            return Ok(None);
+        };
+
+        let message_id = Uuid::new_v4();
+        sqlx::query("INSERT INTO messages (id, telegram_id, text) VALUES ($1, $2, $3)")
+            .bind(message_id)
+            .bind(telegram_id)
+            .bind(&question)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("UPDATE ai_feedback SET escalated_message_id = $1 WHERE id = $2")
+            .bind(message_id)
+            .bind(answer_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(Some(message_id))
+    }
 }
 
 pub fn add(left: u64, right: u64) -> u64 {
@@ -297,4 +508,14 @@ mod tests {
         let result = add(2, 2);
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn migrations_are_sequential() {
+        let versions: Vec<i64> = MIGRATOR.iter().map(|migration| migration.version).collect();
+        let expected: Vec<i64> = (1..=versions.len() as i64).collect();
+        assert_eq!(versions, expected);
+        assert!(MIGRATOR.iter().all(|migration| !migration.sql.trim().is_empty()));
+        // ALTER TYPE ... ADD VALUE can't run inside a transaction before PostgreSQL 12
+        assert!(MIGRATOR.iter().filter(|migration| migration.sql.contains("ADD VALUE")).all(|migration| migration.no_tx));
+    }
 }
@@ -0,0 +1,794 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use futures::{stream, Stream};
+use sqlx::types::chrono::{DateTime, Utc};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    files::UPLOAD_CLAIM_TTL_SECS, AiFeedback, AnswerStyle, DbError, FileClaim, FileStore, FileUsage, LedgerEntry, LedgerKind, Message, MessagePage,
+    MessageStatus, MessageStatusEvent, MessageStatusListener, MessageStore, NewFile, PageCursor, Result,
+    RoleAuditEntry, ShareLink, ShareRedeem, StoredFile, User, UserRole, UserRoleListener, UserSettings, UserStore,
+};
+
+/// Events kept for a slow listener, older ones are lost like during a reconnect of PgListener
+const EVENT_CAPACITY: usize = 64;
+
+/// `Store` kept in memory, for tests of the bot without Postgres.
+/// Follows the queries of `UserRepository`: the same defaults, ordering,
+/// constraints and notifications of the triggers
+pub struct MemoryStore {
+    tables: Mutex<Tables>,
+    user_role: broadcast::Sender<i64>,
+    message_status: broadcast::Sender<MessageStatusEvent>,
+}
+
+#[derive(Default)]
+struct Tables {
+    users: Vec<User>,
+    messages: Vec<Message>,
+    ai_feedback: Vec<AiFeedback>,
+    settings: HashMap<i64, UserSettings>,
+    ledger: Vec<LedgerEntry>,
+    role_audit: Vec<RoleAuditEntry>,
+    files: Vec<StoredFile>,
+    share_links: Vec<ShareLink>,
+    /// Claims of `file_uploads` by owner and name: token, size and start
+    file_uploads: HashMap<(i64, String), (Uuid, i64, DateTime<Utc>)>,
+    /// Last value of the BIGSERIAL columns
+    last_id: i64,
+}
+
+impl Tables {
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn user_mut(&mut self, telegram_id: i64) -> Option<&mut User> {
+        self.users.iter_mut().find(|user| user.telegram_id == telegram_id)
+    }
+
+    fn balance(&self, telegram_id: i64) -> i64 {
+        self.ledger.iter().filter(|entry| entry.telegram_id == telegram_id).map(|entry| entry.amount).sum()
+    }
+
+    fn add_entry(
+        &mut self,
+        telegram_id: i64,
+        amount: i64,
+        kind: LedgerKind,
+        reference: Option<Uuid>,
+        comment: Option<&str>,
+        created_by: Option<i64>,
+    ) {
+        let id = self.next_id();
+        self.ledger.push(LedgerEntry {
+            id,
+            telegram_id,
+            amount,
+            kind,
+            reference,
+            comment: comment.map(str::to_string),
+            created_by,
+            created_at: Utc::now(),
+        });
+    }
+
+    fn file(&self, id: Uuid) -> Option<&StoredFile> {
+        self.files.iter().find(|file| file.id == id)
+    }
+}
+
+fn violation(constraint: &str) -> DbError {
+    DbError::Constraint {
+        constraint: constraint.to_string(),
+        message: format!("violates constraint \"{}\"", constraint),
+    }
+}
+
+/// Events sent after the subscription, like LISTEN
+fn subscribe<T: Clone + Send + 'static>(sender: &broadcast::Sender<T>) -> impl Stream<Item = Result<T>> + Send + 'static {
+    stream::unfold(sender.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((Ok(event), receiver)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+fn newest_first(messages: &mut [Message]) {
+    messages.sort_by_key(|message| Reverse((message.created_at, message.id)));
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            tables: Mutex::new(Tables::default()),
+            user_role: broadcast::channel(EVENT_CAPACITY).0,
+            message_status: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+
+    fn notify_role(&self, telegram_id: i64) {
+        // Nobody listening is not an error, as with NOTIFY
+        let _ = self.user_role.send(telegram_id);
+    }
+
+    fn notify_status(&self, message: &Message) {
+        let _ = self.message_status.send(MessageStatusEvent {
+            id: message.id,
+            telegram_id: message.telegram_id,
+            status: message.status,
+        });
+    }
+
+    fn messages_where(&self, filter: impl Fn(&Message) -> bool) -> Vec<Message> {
+        self.tables().messages.iter().filter(|message| filter(message)).cloned().collect()
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn add_user(&self, user: &User) -> Result<()> {
+        {
+            let mut tables = self.tables();
+            if tables.users.iter().any(|u| u.telegram_id == user.telegram_id) {
+                return Ok(());
+            }
+            if tables.users.iter().any(|u| u.uuid == user.uuid) {
+                return Err(violation("users_uuid_key"));
+            }
+            tables.users.push(User {
+                username: Some(user.username.clone().unwrap_or("None".to_string())),
+                ..user.clone()
+            });
+        }
+        self.notify_role(user.telegram_id);
+        Ok(())
+    }
+
+    async fn delete_user(&self, telegram_id: i64) -> Result<()> {
+        {
+            let mut tables = self.tables();
+            let before = tables.users.len();
+            tables.users.retain(|user| user.telegram_id != telegram_id);
+            if tables.users.len() == before {
+                return Ok(());
+            }
+            // messages reference users with ON DELETE CASCADE, answers with ON DELETE SET NULL
+            let deleted: Vec<Uuid> = tables.messages.iter().filter(|m| m.telegram_id == telegram_id).map(|m| m.id).collect();
+            tables.messages.retain(|message| message.telegram_id != telegram_id);
+            for feedback in &mut tables.ai_feedback {
+                if feedback.escalated_message_id.is_some_and(|id| deleted.contains(&id)) {
+                    feedback.escalated_message_id = None;
+                }
+            }
+        }
+        self.notify_role(telegram_id);
+        Ok(())
+    }
+
+    async fn get_role(&self, telegram_id: i64) -> Result<UserRole> {
+        Ok(self.tables().user_mut(telegram_id).map(|user| user.role).unwrap_or(UserRole::Default))
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<User>> {
+        Ok(self.tables().users.iter().find(|user| user.username.as_deref() == Some(username)).cloned())
+    }
+
+    async fn get_user(&self, user_uuid: Uuid) -> Result<Option<User>> {
+        Ok(self.tables().users.iter().find(|user| user.uuid == user_uuid).cloned())
+    }
+
+    async fn get_user_by_telegram_id(&self, telegram_id: i64) -> Result<Option<User>> {
+        Ok(self.tables().user_mut(telegram_id).cloned())
+    }
+
+    async fn get_user_language(&self, telegram_id: i64) -> Result<Option<String>> {
+        Ok(self.tables().user_mut(telegram_id).and_then(|user| user.language.clone()))
+    }
+
+    async fn set_user_language(&self, telegram_id: i64, language: Option<&str>) -> Result<()> {
+        if let Some(user) = self.tables().user_mut(telegram_id) {
+            user.language = language.map(str::to_string);
+        }
+        Ok(())
+    }
+
+    async fn set_role(&self, telegram_id: i64, expected: UserRole, role: UserRole, changed_by: i64, reason: Option<&str>) -> Result<Option<UserRole>> {
+        let old_role = {
+            let mut tables = self.tables();
+            let Some(user) = tables.user_mut(telegram_id) else {
+                return Ok(None);
+            };
+            let old_role = user.role;
+            if old_role != expected || old_role == role {
+                return Ok(Some(old_role));
+            }
+            user.role = role;
+            let id = tables.next_id();
+            tables.role_audit.push(RoleAuditEntry {
+                id,
+                telegram_id,
+                old_role,
+                new_role: role,
+                changed_by,
+                reason: reason.map(str::to_string),
+                created_at: Utc::now(),
+            });
+            old_role
+        };
+        self.notify_role(telegram_id);
+        Ok(Some(old_role))
+    }
+
+    async fn get_role_audit(&self, telegram_id: i64, limit: i64) -> Result<Vec<RoleAuditEntry>> {
+        let mut entries: Vec<RoleAuditEntry> = self.tables().role_audit
+            .iter()
+            .filter(|entry| entry.telegram_id == telegram_id)
+            .cloned()
+            .collect();
+        entries.sort_by_key(|entry| Reverse((entry.created_at, entry.id)));
+        entries.truncate(limit.max(0) as usize);
+        Ok(entries)
+    }
+
+    async fn listen_user_role(&self) -> Result<UserRoleListener> {
+        Ok(UserRoleListener::new(subscribe(&self.user_role)))
+    }
+
+    async fn get_user_settings(&self, telegram_id: i64) -> Result<UserSettings> {
+        let settings = self.tables().settings.get(&telegram_id).cloned();
+        Ok(settings.unwrap_or_else(|| UserSettings::default_for(telegram_id)))
+    }
+
+    async fn set_answer_style(&self, telegram_id: i64, style: AnswerStyle) -> Result<()> {
+        let mut tables = self.tables();
+        let settings = tables.settings.entry(telegram_id).or_insert_with(|| UserSettings::default_for(telegram_id));
+        settings.answer_style = style;
+        settings.updated_at = Utc::now();
+        Ok(())
+    }
+
+    async fn toggle_status_notifications(&self, telegram_id: i64) -> Result<bool> {
+        let mut tables = self.tables();
+        let settings = tables.settings.entry(telegram_id).or_insert_with(|| UserSettings::default_for(telegram_id));
+        settings.status_notifications = !settings.status_notifications;
+        settings.updated_at = Utc::now();
+        Ok(settings.status_notifications)
+    }
+
+    async fn get_balance(&self, telegram_id: i64) -> Result<i64> {
+        Ok(self.tables().balance(telegram_id))
+    }
+
+    async fn get_ledger(&self, telegram_id: i64, limit: i64) -> Result<Vec<LedgerEntry>> {
+        let mut entries: Vec<LedgerEntry> = self.tables().ledger
+            .iter()
+            .filter(|entry| entry.telegram_id == telegram_id)
+            .cloned()
+            .collect();
+        entries.sort_by_key(|entry| Reverse((entry.created_at, entry.id)));
+        entries.truncate(limit.max(0) as usize);
+        Ok(entries)
+    }
+
+    async fn add_welcome_credits(&self, telegram_id: i64, amount: i64) -> Result<bool> {
+        let mut tables = self.tables();
+        if tables.ledger.iter().any(|entry| entry.telegram_id == telegram_id && entry.kind == LedgerKind::Welcome) {
+            return Ok(false);
+        }
+        tables.add_entry(telegram_id, amount, LedgerKind::Welcome, None, None, None);
+        Ok(true)
+    }
+
+    async fn grant_credits(&self, telegram_id: i64, amount: i64, admin_id: i64, comment: Option<&str>) -> Result<i64> {
+        let mut tables = self.tables();
+        if !tables.users.iter().any(|user| user.telegram_id == telegram_id) {
+            return Err(DbError::NotFound);
+        }
+        tables.add_entry(telegram_id, amount, LedgerKind::Grant, None, comment, Some(admin_id));
+        Ok(tables.balance(telegram_id))
+    }
+
+    async fn charge(&self, telegram_id: i64, amount: i64, reference: Uuid) -> Result<Option<i64>> {
+        let mut tables = self.tables();
+        let balance = tables.balance(telegram_id);
+        if balance < amount {
+            return Ok(None);
+        }
+        tables.add_entry(telegram_id, -amount, LedgerKind::Charge, Some(reference), None, None);
+        Ok(Some(balance - amount))
+    }
+
+    async fn refund(&self, telegram_id: i64, reference: Uuid) -> Result<()> {
+        let mut tables = self.tables();
+        if tables.ledger.iter().any(|entry| entry.reference == Some(reference) && entry.kind == LedgerKind::Refund) {
+            return Ok(());
+        }
+        let charges: Vec<i64> = tables.ledger
+            .iter()
+            .filter(|entry| entry.telegram_id == telegram_id && entry.reference == Some(reference) && entry.kind == LedgerKind::Charge)
+            .map(|entry| entry.amount)
+            .collect();
+        for amount in charges {
+            tables.add_entry(telegram_id, -amount, LedgerKind::Refund, Some(reference), None, None);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MessageStore for MemoryStore {
+    async fn add_message(&self, telegram_id: i64, text: &str) -> Result<Uuid> {
+        let mut tables = self.tables();
+        if tables.user_mut(telegram_id).is_none() {
+            return Err(violation("messages_telegram_id_fkey"));
+        }
+        let now = Utc::now();
+        let message_id = Uuid::new_v4();
+        tables.messages.push(Message {
+            id: message_id,
+            telegram_id,
+            text: text.to_string(),
+            status: MessageStatus::Pending,
+            answer: None,
+            created_at: now,
+            updated_at: now,
+        });
+        Ok(message_id)
+    }
+
+    async fn update_message_status(&self, message_id: Uuid, new_status: MessageStatus, answer: Option<&str>) -> Result<()> {
+        let changed = {
+            let mut tables = self.tables();
+            let Some(message) = tables.messages.iter_mut().find(|message| message.id == message_id) else {
+                return Ok(());
+            };
+            let changed = message.status != new_status || message.answer.as_deref() != answer;
+            message.status = new_status;
+            message.answer = answer.map(str::to_string);
+            message.updated_at = Utc::now();
+            changed.then(|| message.clone())
+        };
+        if let Some(message) = changed {
+            self.notify_status(&message);
+        }
+        Ok(())
+    }
+
+    async fn set_message_status(&self, message_id: Uuid, new_status: MessageStatus) -> Result<()> {
+        let changed = {
+            let mut tables = self.tables();
+            let Some(message) = tables.messages.iter_mut().find(|message| message.id == message_id) else {
+                return Ok(());
+            };
+            let changed = message.status != new_status;
+            message.status = new_status;
+            message.updated_at = Utc::now();
+            changed.then(|| message.clone())
+        };
+        if let Some(message) = changed {
+            self.notify_status(&message);
+        }
+        Ok(())
+    }
+
+    async fn get_user_messages(&self, telegram_id: i64) -> Result<Vec<Message>> {
+        let mut messages = self.messages_where(|message| message.telegram_id == telegram_id);
+        messages.sort_by_key(|message| Reverse(message.created_at));
+        Ok(messages)
+    }
+
+    async fn get_user_messages_by_status(&self, telegram_id: i64, status: MessageStatus) -> Result<Vec<Message>> {
+        let mut messages = self.messages_where(|message| message.telegram_id == telegram_id && message.status == status);
+        messages.sort_by_key(|message| Reverse(message.created_at));
+        Ok(messages)
+    }
+
+    async fn get_user_messages_page(
+        &self,
+        telegram_id: i64,
+        status: Option<MessageStatus>,
+        cursor: PageCursor,
+        limit: i64,
+    ) -> Result<MessagePage> {
+        let mut messages = self.messages_where(|message| {
+            message.telegram_id == telegram_id && status.is_none_or(|status| message.status == status)
+        });
+        let total = messages.len() as i64;
+        newest_first(&mut messages);
+
+        let limit = limit.max(0) as usize;
+        let key = |message: &Message| (message.created_at, message.id);
+        let messages = match cursor {
+            PageCursor::First => messages.into_iter().take(limit).collect(),
+            PageCursor::From(c) => messages.into_iter().filter(|m| key(m) <= (c.created_at, c.id)).take(limit).collect(),
+            PageCursor::After(c) => messages.into_iter().filter(|m| key(m) < (c.created_at, c.id)).take(limit).collect(),
+            PageCursor::Before(c) => {
+                // The nearest newer requests are the last ones in this order
+                let newer: Vec<Message> = messages.into_iter().filter(|m| key(m) > (c.created_at, c.id)).collect();
+                let skip = newer.len().saturating_sub(limit);
+                newer.into_iter().skip(skip).collect()
+            }
+        };
+
+        Ok(MessagePage { messages, total })
+    }
+
+    async fn get_message_by_id(&self, message_id: Uuid) -> Result<Option<Message>> {
+        Ok(self.messages_where(|message| message.id == message_id).pop())
+    }
+
+    async fn get_messages_by_status(&self, status: MessageStatus) -> Result<Vec<Message>> {
+        let mut messages = self.messages_where(|message| message.status == status);
+        messages.sort_by_key(|message| message.created_at);
+        Ok(messages)
+    }
+
+    async fn get_messages_by_statuses(&self, statuses: &[MessageStatus]) -> Result<Vec<Message>> {
+        let mut messages = self.messages_where(|message| statuses.contains(&message.status));
+        messages.sort_by_key(|message| message.created_at);
+        Ok(messages)
+    }
+
+    async fn listen_message_status(&self) -> Result<MessageStatusListener> {
+        Ok(MessageStatusListener::new(subscribe(&self.message_status)))
+    }
+
+    async fn add_ai_answer(&self, answer_id: Uuid, telegram_id: i64, question: &str, answer: &str) -> Result<()> {
+        let mut tables = self.tables();
+        if tables.ai_feedback.iter().any(|feedback| feedback.id == answer_id) {
+            return Err(violation("ai_feedback_pkey"));
+        }
+        tables.ai_feedback.push(AiFeedback {
+            id: answer_id,
+            telegram_id,
+            question: question.to_string(),
+            answer: answer.to_string(),
+            helpful: None,
+            escalated_message_id: None,
+            created_at: Utc::now(),
+            rated_at: None,
+        });
+        Ok(())
+    }
+
+    async fn rate_ai_answer(&self, answer_id: Uuid, telegram_id: i64, helpful: bool) -> Result<Option<AiFeedback>> {
+        let mut tables = self.tables();
+        let Some(feedback) = tables.ai_feedback.iter_mut().find(|f| f.id == answer_id && f.telegram_id == telegram_id) else {
+            return Ok(None);
+        };
+        feedback.helpful = Some(helpful);
+        feedback.rated_at = Some(Utc::now());
+        Ok(Some(feedback.clone()))
+    }
+
+    async fn get_ai_feedback(&self, answer_id: Uuid) -> Result<Option<AiFeedback>> {
+        Ok(self.tables().ai_feedback.iter().find(|feedback| feedback.id == answer_id).cloned())
+    }
+
+    async fn escalate_ai_feedback(&self, answer_id: Uuid, telegram_id: i64) -> Result<Option<Uuid>> {
+        let mut tables = self.tables();
+        let Some(index) = tables.ai_feedback.iter().position(|feedback| {
+            feedback.id == answer_id && feedback.telegram_id == telegram_id && feedback.escalated_message_id.is_none()
+        }) else {
+            return Ok(None);
+        };
+        if tables.user_mut(telegram_id).is_none() {
+            return Err(violation("messages_telegram_id_fkey"));
+        }
+        let now = Utc::now();
+        let message_id = Uuid::new_v4();
+        let text = tables.ai_feedback[index].question.clone();
+        tables.messages.push(Message {
+            id: message_id,
+            telegram_id,
+            text,
+            status: MessageStatus::Pending,
+            answer: None,
+            created_at: now,
+            updated_at: now,
+        });
+        tables.ai_feedback[index].escalated_message_id = Some(message_id);
+        Ok(Some(message_id))
+    }
+}
+
+#[async_trait]
+impl FileStore for MemoryStore {
+    async fn upsert_file(&self, file: NewFile<'_>) -> Result<StoredFile> {
+        let mut tables = self.tables();
+        let existing = tables.files
+            .iter_mut()
+            .find(|stored| stored.owner_id == file.owner_id && stored.original_name == file.original_name);
+        if let Some(stored) = existing {
+            stored.size = file.size;
+            stored.mime_type = file.mime_type.map(str::to_string);
+            stored.checksum = file.checksum.to_string();
+            stored.created_at = Utc::now();
+            return Ok(stored.clone());
+        }
+        if tables.file(file.id).is_some() {
+            return Err(violation("files_pkey"));
+        }
+        let stored = StoredFile {
+            id: file.id,
+            owner_id: file.owner_id,
+            original_name: file.original_name.to_string(),
+            size: file.size,
+            mime_type: file.mime_type.map(str::to_string),
+            checksum: file.checksum.to_string(),
+            created_at: Utc::now(),
+        };
+        tables.files.push(stored.clone());
+        Ok(stored)
+    }
+
+    async fn claim_file_upload(&self, owner_id: i64, original_name: &str, size: i64) -> Result<Option<FileClaim>> {
+        let now = Utc::now();
+        let active_since = now - chrono::Duration::seconds(UPLOAD_CLAIM_TTL_SECS);
+        let mut tables = self.tables();
+        let key = (owner_id, original_name.to_string());
+        if let Some((_, _, started_at)) = tables.file_uploads.get(&key)
+            && *started_at >= active_since
+        {
+            return Ok(None);
+        }
+        let token = Uuid::new_v4();
+        tables.file_uploads.insert(key, (token, size, now));
+
+        let owned = tables.files.iter().filter(|file| file.owner_id == owner_id);
+        let mut usage = FileUsage { files: owned.clone().count() as i64, bytes: owned.map(|file| file.size).sum() };
+        let pending = tables.file_uploads
+            .iter()
+            .filter(|((owner, name), (_, _, started_at))| *owner == owner_id && name != original_name && *started_at >= active_since);
+        for ((_, name), (_, size, _)) in pending {
+            match tables.files.iter().find(|file| file.owner_id == owner_id && file.original_name == *name) {
+                Some(file) => usage.bytes += (size - file.size).max(0),
+                None => {
+                    usage.files += 1;
+                    usage.bytes += size;
+                }
+            }
+        }
+        Ok(Some(FileClaim { token, usage }))
+    }
+
+    async fn release_file_upload(&self, owner_id: i64, original_name: &str, token: Uuid) -> Result<()> {
+        let mut tables = self.tables();
+        let key = (owner_id, original_name.to_string());
+        if tables.file_uploads.get(&key).is_some_and(|(claimed, _, _)| *claimed == token) {
+            tables.file_uploads.remove(&key);
+        }
+        Ok(())
+    }
+
+    async fn get_file(&self, id: Uuid) -> Result<Option<StoredFile>> {
+        Ok(self.tables().file(id).cloned())
+    }
+
+    async fn get_file_by_name(&self, owner_id: i64, original_name: &str) -> Result<Option<StoredFile>> {
+        Ok(self.tables().files.iter().find(|file| file.owner_id == owner_id && file.original_name == original_name).cloned())
+    }
+
+    async fn get_file_usage(&self, owner_id: i64) -> Result<FileUsage> {
+        let tables = self.tables();
+        let owned = tables.files.iter().filter(|file| file.owner_id == owner_id);
+        Ok(FileUsage {
+            files: owned.clone().count() as i64,
+            bytes: owned.map(|file| file.size).sum(),
+        })
+    }
+
+    async fn delete_file(&self, owner_id: i64, id: Uuid) -> Result<Option<StoredFile>> {
+        let mut tables = self.tables();
+        let Some(index) = tables.files.iter().position(|file| file.id == id && file.owner_id == owner_id) else {
+            return Ok(None);
+        };
+        let file = tables.files.remove(index);
+        tables.share_links.retain(|link| link.file_id != id);
+        Ok(Some(file))
+    }
+
+    async fn get_user_files(&self, owner_id: i64, offset: i64, limit: i64) -> Result<Vec<StoredFile>> {
+        let mut files: Vec<StoredFile> = self.tables().files.iter().filter(|file| file.owner_id == owner_id).cloned().collect();
+        files.sort_by(|a, b| (&a.original_name, a.id).cmp(&(&b.original_name, b.id)));
+        Ok(files.into_iter().skip(offset.max(0) as usize).take(limit.max(0) as usize).collect())
+    }
+
+    async fn create_share_link(
+        &self,
+        token: &str,
+        file_id: Uuid,
+        created_by: i64,
+        expires_at: Option<DateTime<Utc>>,
+        max_downloads: Option<i32>,
+    ) -> Result<ShareLink> {
+        let mut tables = self.tables();
+        if tables.share_links.iter().any(|link| link.token == token) {
+            return Err(violation("share_links_pkey"));
+        }
+        if tables.file(file_id).is_none() {
+            return Err(violation("share_links_file_id_fkey"));
+        }
+        let link = ShareLink {
+            token: token.to_string(),
+            file_id,
+            created_by,
+            created_at: Utc::now(),
+            expires_at,
+            max_downloads,
+            downloads: 0,
+            expired: false,
+        };
+        tables.share_links.push(link.clone());
+        Ok(link)
+    }
+
+    async fn redeem_share_link(&self, token: &str) -> Result<ShareRedeem> {
+        let mut tables = self.tables();
+        let Some(index) = tables.share_links.iter().position(|link| link.token == token) else {
+            return Ok(ShareRedeem::NotFound);
+        };
+        let link = &mut tables.share_links[index];
+        if let Some(closed) = link.closed(Utc::now()) {
+            return Ok(closed);
+        }
+        link.downloads += 1;
+        let file_id = link.file_id;
+
+        match tables.file(file_id) {
+            Some(file) => Ok(ShareRedeem::Redeemed(file.clone())),
+            None => Err(DbError::NotFound),
+        }
+    }
+
+    async fn peek_share_link(&self, token: &str, counted: bool) -> Result<ShareRedeem> {
+        let tables = self.tables();
+        let Some(link) = tables.share_links.iter().find(|link| link.token == token) else {
+            return Ok(ShareRedeem::NotFound);
+        };
+        let now = Utc::now();
+        if counted && link.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Ok(ShareRedeem::Expired);
+        }
+        if !counted && let Some(closed) = link.closed(now) {
+            return Ok(closed);
+        }
+        Ok(match tables.file(link.file_id) {
+            Some(file) => ShareRedeem::Redeemed(file.clone()),
+            None => ShareRedeem::NotFound,
+        })
+    }
+
+    async fn expire_share_links(&self) -> Result<u64> {
+        let now = Utc::now();
+        let mut expired = 0;
+        for link in self.tables().share_links.iter_mut().filter(|link| !link.expired) {
+            let out_of_time = link.expires_at.is_some_and(|expires_at| expires_at <= now);
+            let out_of_downloads = link.max_downloads.is_some_and(|max| link.downloads >= max);
+            if out_of_time || out_of_downloads {
+                link.expired = true;
+                expired += 1;
+            }
+        }
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MessageCursor;
+
+    use super::*;
+
+    fn user(telegram_id: i64) -> User {
+        User { telegram_id, username: None, uuid: Uuid::new_v4(), role: UserRole::Default, language: None }
+    }
+
+    #[tokio::test]
+    async fn pages_follow_cursors() {
+        let store = MemoryStore::new();
+        store.add_user(&user(1)).await.unwrap();
+        assert!(matches!(store.add_message(2, "unknown user").await, Err(DbError::Constraint { .. })));
+        for text in ["a", "b", "c", "d", "e"] {
+            store.add_message(1, text).await.unwrap();
+        }
+
+        let texts = |page: &MessagePage| page.messages.iter().map(|m| m.text.clone()).collect::<Vec<_>>();
+        let first = store.get_user_messages_page(1, None, PageCursor::First, 2).await.unwrap();
+        assert_eq!(texts(&first), ["e", "d"]);
+        assert_eq!(first.total, 5);
+
+        let second = store.get_user_messages_page(1, None, PageCursor::After(first.last_cursor().unwrap()), 2).await.unwrap();
+        assert_eq!(texts(&second), ["c", "b"]);
+        let back = store.get_user_messages_page(1, None, PageCursor::Before(second.first_cursor().unwrap()), 2).await.unwrap();
+        assert_eq!(texts(&back), ["e", "d"]);
+        let from = store.get_user_messages_page(1, None, PageCursor::From(MessageCursor::from(&second.messages[0])), 2).await.unwrap();
+        assert_eq!(texts(&from), ["c", "b"]);
+
+        let answered = store.get_user_messages_page(1, Some(MessageStatus::Answered), PageCursor::First, 2).await.unwrap();
+        assert_eq!(answered.total, 0);
+    }
+
+    #[tokio::test]
+    async fn share_links_and_events() {
+        let store = MemoryStore::new();
+        let mut roles = store.listen_user_role().await.unwrap();
+        store.add_user(&user(1)).await.unwrap();
+        store.set_role(1, UserRole::Default, UserRole::Admin, 2, Some("test")).await.unwrap();
+        assert_eq!(roles.recv().await.unwrap(), 1);
+        assert_eq!(roles.recv().await.unwrap(), 1);
+        assert_eq!(store.get_role_audit(1, 5).await.unwrap()[0].new_role, UserRole::Admin);
+        // A change computed from a stale role does not undo a later one
+        assert_eq!(store.set_role(1, UserRole::Default, UserRole::Banned, 2, None).await.unwrap(), Some(UserRole::Admin));
+        assert_eq!(store.get_role(1).await.unwrap(), UserRole::Admin);
+
+        let file = store.upsert_file(NewFile {
+            id: Uuid::new_v4(),
+            owner_id: 1,
+            original_name: "report.pdf",
+            size: 10,
+            mime_type: None,
+            checksum: "00",
+        }).await.unwrap();
+        store.create_share_link("token", file.id, 1, None, Some(1)).await.unwrap();
+        assert!(store.create_share_link("token", file.id, 1, None, None).await.is_err());
+
+        assert!(matches!(store.redeem_share_link("token").await.unwrap(), ShareRedeem::Redeemed(_)));
+        assert!(matches!(store.redeem_share_link("token").await.unwrap(), ShareRedeem::LimitReached));
+        assert!(matches!(store.peek_share_link("token", false).await.unwrap(), ShareRedeem::LimitReached));
+        assert!(matches!(store.peek_share_link("token", true).await.unwrap(), ShareRedeem::Redeemed(_)));
+        assert_eq!(store.expire_share_links().await.unwrap(), 1);
+        // The cleanup marks exhausted links too, they still report the limit
+        assert!(matches!(store.redeem_share_link("token").await.unwrap(), ShareRedeem::LimitReached));
+        store.create_share_link("old", file.id, 1, Some(Utc::now()), None).await.unwrap();
+        assert!(matches!(store.redeem_share_link("old").await.unwrap(), ShareRedeem::Expired));
+    }
+
+    #[tokio::test]
+    async fn answer_is_escalated_once() {
+        let store = MemoryStore::new();
+        store.add_user(&user(1)).await.unwrap();
+        let answer_id = Uuid::new_v4();
+        store.add_ai_answer(answer_id, 1, "question", "answer").await.unwrap();
+
+        assert_eq!(store.escalate_ai_feedback(answer_id, 2).await.unwrap(), None);
+        let message_id = store.escalate_ai_feedback(answer_id, 1).await.unwrap().unwrap();
+        assert_eq!(store.escalate_ai_feedback(answer_id, 1).await.unwrap(), None);
+        assert_eq!(store.get_user_messages(1).await.unwrap().len(), 1);
+        assert_eq!(store.get_ai_feedback(answer_id).await.unwrap().unwrap().escalated_message_id, Some(message_id));
+    }
+
+    #[tokio::test]
+    async fn answer_is_refunded_once() {
+        let store = MemoryStore::new();
+        assert!(matches!(store.grant_credits(1, 10, 2, None).await, Err(DbError::NotFound)));
+        store.add_user(&user(1)).await.unwrap();
+        assert_eq!(store.grant_credits(1, 10, 2, None).await.unwrap(), 10);
+
+        let answer_id = Uuid::new_v4();
+        assert_eq!(store.charge(1, 3, answer_id).await.unwrap(), Some(7));
+        store.refund(1, answer_id).await.unwrap();
+        store.refund(1, answer_id).await.unwrap();
+        assert_eq!(store.get_balance(1).await.unwrap(), 10);
+    }
+}
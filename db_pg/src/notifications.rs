@@ -0,0 +1,81 @@
+use std::pin::Pin;
+
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use sqlx::postgres::PgListener;
+use uuid::Uuid;
+
+use crate::{DbError, MessageStatus, Result, UserRepository};
+
+/// Channel filled by `messages_status_notify` trigger
+pub const MESSAGE_STATUS_CHANNEL: &str = "message_status";
+
+/// Channel filled by `users_role_notify` trigger, the payload is `telegram_id`
+pub const USER_ROLE_CHANNEL: &str = "user_role";
+
+/// Status or answer of a request was changed
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageStatusEvent {
+    pub id: Uuid,
+    pub telegram_id: i64,
+    pub status: MessageStatus,
+}
+
+type Events<T> = Pin<Box<dyn Stream<Item = Result<T>> + Send>>;
+
+/// Next event of a subscription, a finished stream means the source is gone
+async fn next_event<T>(events: &mut Events<T>) -> Result<T> {
+    events.next().await.unwrap_or(Err(DbError::Connection(sqlx::Error::PoolClosed)))
+}
+
+/// Subscription to `message_status` channel.
+/// PgListener reconnects by itself, events sent while disconnected are lost
+pub struct MessageStatusListener {
+    events: Events<MessageStatusEvent>,
+}
+
+impl MessageStatusListener {
+    /// Listener over any source of events, e.g. the in-memory store
+    pub fn new(events: impl Stream<Item = Result<MessageStatusEvent>> + Send + 'static) -> Self {
+        Self { events: Box::pin(events) }
+    }
+
+    pub async fn recv(&mut self) -> Result<MessageStatusEvent> {
+        next_event(&mut self.events).await
+    }
+}
+
+/// Subscription to `user_role` channel, yields ids of users whose role was changed
+pub struct UserRoleListener {
+    events: Events<i64>,
+}
+
+impl UserRoleListener {
+    pub fn new(events: impl Stream<Item = Result<i64>> + Send + 'static) -> Self {
+        Self { events: Box::pin(events) }
+    }
+
+    pub async fn recv(&mut self) -> Result<i64> {
+        next_event(&mut self.events).await
+    }
+}
+
+impl UserRepository {
+    pub async fn listen_user_role(&self) -> Result<UserRoleListener> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(USER_ROLE_CHANNEL).await?;
+
+        Ok(UserRoleListener::new(listener.into_stream().map(|notification| -> Result<i64> {
+            Ok(notification?.payload().parse()?)
+        })))
+    }
+
+    pub async fn listen_message_status(&self) -> Result<MessageStatusListener> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(MESSAGE_STATUS_CHANNEL).await?;
+
+        Ok(MessageStatusListener::new(listener.into_stream().map(|notification| -> Result<MessageStatusEvent> {
+            Ok(serde_json::from_str(notification?.payload())?)
+        })))
+    }
+}
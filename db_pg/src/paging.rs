@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{Message, MessageStatus, Result, UserRepository};
+
+/// Keyset position of a request in the list ordered by (created_at, id) descending
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl From<&Message> for MessageCursor {
+    fn from(message: &Message) -> Self {
+        Self { created_at: message.created_at, id: message.id }
+    }
+}
+
+/// Which page to fetch relative to a known cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageCursor {
+    /// Newest requests
+    First,
+    /// Page starting at the cursor, cursor included
+    From(MessageCursor),
+    /// Older requests right after the cursor
+    After(MessageCursor),
+    /// Newer requests right before the cursor
+    Before(MessageCursor),
+}
+
+/// One page of requests with total count of requests matching the filter
+#[derive(Debug, Clone)]
+pub struct MessagePage {
+    pub messages: Vec<Message>,
+    pub total: i64,
+}
+
+impl MessagePage {
+    pub fn first_cursor(&self) -> Option<MessageCursor> {
+        self.messages.first().map(MessageCursor::from)
+    }
+
+    pub fn last_cursor(&self) -> Option<MessageCursor> {
+        self.messages.last().map(MessageCursor::from)
+    }
+}
+
+impl UserRepository {
+    /// Page of user requests, newest first. `status` None means all requests
+    pub async fn get_user_messages_page(
+        &self,
+        telegram_id: i64,
+        status: Option<MessageStatus>,
+        cursor: PageCursor,
+        limit: i64,
+    ) -> Result<MessagePage> {
+        let (condition, order) = match cursor {
+            PageCursor::First => ("TRUE", "DESC"),
+            PageCursor::From(_) => ("(created_at, id) <= ($3, $4)", "DESC"),
+            PageCursor::After(_) => ("(created_at, id) < ($3, $4)", "DESC"),
+            PageCursor::Before(_) => ("(created_at, id) > ($3, $4)", "ASC"),
+        };
+        let (created_at, id) = match cursor {
+            PageCursor::First => (None, None),
+            PageCursor::From(c) | PageCursor::After(c) | PageCursor::Before(c) => (Some(c.created_at), Some(c.id)),
+        };
+
+        let query = format!(
+            r#"
+            SELECT id, telegram_id, text, status, answer, created_at, updated_at
+            FROM messages
+            WHERE telegram_id = $1
+                AND ($2::message_status IS NULL OR status = $2)
+                AND {condition}
+            ORDER BY created_at {order}, id {order}
+            LIMIT $5
+            "#
+        );
+        let mut messages = sqlx::query_as::<_, Message>(&query)
+            .bind(telegram_id)
+            .bind(status)
+            .bind(created_at)
+            .bind(id)
+            .bind(limit)
+            .fetch_all(&mut *self.conn().await?)
+            .await?;
+
+        // Предыдущая страница выбирается в обратном порядке
+        if matches!(cursor, PageCursor::Before(_)) {
+            messages.reverse();
+        }
+
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*)
+            FROM messages
+            WHERE telegram_id = $1 AND ($2::message_status IS NULL OR status = $2)
+            "#,
+        )
+        .bind(telegram_id)
+        .bind(status)
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+
+        Ok(MessagePage { messages, total })
+    }
+}
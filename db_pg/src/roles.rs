@@ -0,0 +1,91 @@
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+use crate::{Result, UserRepository, UserRole};
+
+/// Entry of `role_audit`, written for every role change and never changed
+#[derive(Debug, Clone, FromRow)]
+pub struct RoleAuditEntry {
+    pub id: i64,
+    pub telegram_id: i64,
+    pub old_role: UserRole,
+    pub new_role: UserRole,
+    /// Administrator who changed the role
+    pub changed_by: i64,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl UserRepository {
+    /// Changes the role from `expected` to `role` and writes it to `role_audit` in one transaction.
+    /// Returns the role found under the row lock, None for unknown users. Nothing is written
+    /// when it is not `expected` (the role was changed concurrently) or already `role`
+    pub async fn set_role(
+        &self,
+        telegram_id: i64,
+        expected: UserRole,
+        role: UserRole,
+        changed_by: i64,
+        reason: Option<&str>,
+    ) -> Result<Option<UserRole>> {
+        let mut tx = self.begin().await?;
+
+        let old_role: Option<UserRole> = sqlx::query_scalar(
+            "SELECT role FROM users WHERE telegram_id = $1 FOR UPDATE"
+        )
+        .bind(telegram_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(old_role) = old_role else {
+            tx.rollback().await?;
+            return Ok(None);
+        };
+        if old_role != expected || old_role == role {
+            tx.rollback().await?;
+            return Ok(Some(old_role));
+        }
+
+        sqlx::query("UPDATE users SET role = $2 WHERE telegram_id = $1")
+            .bind(telegram_id)
+            .bind(role)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO role_audit (telegram_id, old_role, new_role, changed_by, reason)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(telegram_id)
+        .bind(old_role)
+        .bind(role)
+        .bind(changed_by)
+        .bind(reason)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(old_role))
+    }
+
+    /// Latest role changes of the user, newest first
+    pub async fn get_role_audit(&self, telegram_id: i64, limit: i64) -> Result<Vec<RoleAuditEntry>> {
+        let entries = sqlx::query_as::<_, RoleAuditEntry>(
+            r#"
+            SELECT id, telegram_id, old_role, new_role, changed_by, reason, created_at
+            FROM role_audit
+            WHERE telegram_id = $1
+            ORDER BY created_at DESC, id DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(telegram_id)
+        .bind(limit)
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+
+        Ok(entries)
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+use crate::{Result, UserRepository};
+
+/// How freely Qortex AI formulates answers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "answer_style", rename_all = "lowercase")]
+pub enum AnswerStyle {
+    Precise,
+    #[default]
+    Balanced,
+    Creative,
+}
+
+impl AnswerStyle {
+    pub const ALL: [AnswerStyle; 3] = [AnswerStyle::Precise, AnswerStyle::Balanced, AnswerStyle::Creative];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            AnswerStyle::Precise => "precise",
+            AnswerStyle::Balanced => "balanced",
+            AnswerStyle::Creative => "creative",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|style| style.code() == code)
+    }
+}
+
+/// Preferences of a user, users without a row get the defaults
+#[derive(Debug, Clone, FromRow)]
+pub struct UserSettings {
+    pub telegram_id: i64,
+    pub answer_style: AnswerStyle,
+    /// Notify about status changes of the user's requests
+    pub status_notifications: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UserSettings {
+    pub(crate) fn default_for(telegram_id: i64) -> Self {
+        Self {
+            telegram_id,
+            answer_style: AnswerStyle::default(),
+            status_notifications: true,
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+impl UserRepository {
+    pub async fn get_user_settings(&self, telegram_id: i64) -> Result<UserSettings> {
+        let settings = sqlx::query_as::<_, UserSettings>(
+            "SELECT telegram_id, answer_style, status_notifications, updated_at FROM user_settings WHERE telegram_id = $1"
+        )
+        .bind(telegram_id)
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+
+        Ok(settings.unwrap_or_else(|| UserSettings::default_for(telegram_id)))
+    }
+
+    pub async fn set_answer_style(&self, telegram_id: i64, style: AnswerStyle) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO user_settings (telegram_id, answer_style)
+            VALUES ($1, $2)
+            ON CONFLICT (telegram_id) DO UPDATE
+            SET answer_style = EXCLUDED.answer_style, updated_at = NOW()
+            "#,
+        )
+        .bind(telegram_id)
+        .bind(style)
+        .execute(&mut *self.conn().await?)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Flips status notifications and returns the new value
+    pub async fn toggle_status_notifications(&self, telegram_id: i64) -> Result<bool> {
+        let enabled = sqlx::query_scalar(
+            r#"
+            INSERT INTO user_settings (telegram_id, status_notifications)
+            VALUES ($1, FALSE)
+            ON CONFLICT (telegram_id) DO UPDATE
+            SET status_notifications = NOT user_settings.status_notifications, updated_at = NOW()
+            RETURNING status_notifications
+            "#,
+        )
+        .bind(telegram_id)
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+
+        Ok(enabled)
+    }
+}
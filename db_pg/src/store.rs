@@ -0,0 +1,325 @@
+use async_trait::async_trait;
+use sqlx::types::chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    AiFeedback, AnswerStyle, FileClaim, FileUsage, LedgerEntry, Message, MessagePage, MessageStatus, MessageStatusListener,
+    NewFile, PageCursor, Result, RoleAuditEntry, ShareLink, ShareRedeem, StoredFile, User, UserRepository, UserRole,
+    UserRoleListener, UserSettings,
+};
+
+/// Users with their roles, language, settings and wallet
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn add_user(&self, user: &User) -> Result<()>;
+    async fn delete_user(&self, telegram_id: i64) -> Result<()>;
+    /// Role of the user, `Default` for unknown users
+    async fn get_role(&self, telegram_id: i64) -> Result<UserRole>;
+    /// Has the user any of `allowed` roles
+    async fn check_role(&self, telegram_id: i64, allowed: &[UserRole]) -> Result<bool> {
+        Ok(allowed.contains(&self.get_role(telegram_id).await?))
+    }
+    async fn find_by_username(&self, username: &str) -> Result<Option<User>>;
+    async fn get_user(&self, user_uuid: Uuid) -> Result<Option<User>>;
+    async fn get_user_by_telegram_id(&self, telegram_id: i64) -> Result<Option<User>>;
+    async fn get_user_language(&self, telegram_id: i64) -> Result<Option<String>>;
+    async fn set_user_language(&self, telegram_id: i64, language: Option<&str>) -> Result<()>;
+    /// Changes the role from `expected` and writes the change to the audit. Returns the role found
+    /// before the change, None for unknown users; nothing changes if it is not `expected`
+    async fn set_role(&self, telegram_id: i64, expected: UserRole, role: UserRole, changed_by: i64, reason: Option<&str>) -> Result<Option<UserRole>>;
+    /// Latest role changes of the user, newest first
+    async fn get_role_audit(&self, telegram_id: i64, limit: i64) -> Result<Vec<RoleAuditEntry>>;
+    /// Ids of users whose role was changed
+    async fn listen_user_role(&self) -> Result<UserRoleListener>;
+
+    async fn get_user_settings(&self, telegram_id: i64) -> Result<UserSettings>;
+    async fn set_answer_style(&self, telegram_id: i64, style: AnswerStyle) -> Result<()>;
+    /// Flips status notifications and returns the new value
+    async fn toggle_status_notifications(&self, telegram_id: i64) -> Result<bool>;
+
+    async fn get_balance(&self, telegram_id: i64) -> Result<i64>;
+    /// Latest entries first
+    async fn get_ledger(&self, telegram_id: i64, limit: i64) -> Result<Vec<LedgerEntry>>;
+    /// Gives welcome credits once per user, returns false if they were already given
+    async fn add_welcome_credits(&self, telegram_id: i64, amount: i64) -> Result<bool>;
+    /// Credits from an administrator, returns the new balance
+    async fn grant_credits(&self, telegram_id: i64, amount: i64, admin_id: i64, comment: Option<&str>) -> Result<i64>;
+    /// Charges `amount` if the balance allows it and returns the new balance, None if it is too low
+    async fn charge(&self, telegram_id: i64, amount: i64, reference: Uuid) -> Result<Option<i64>>;
+    /// Returns the charge made for `reference`, does nothing if there was no charge
+    async fn refund(&self, telegram_id: i64, reference: Uuid) -> Result<()>;
+}
+
+/// Requests to administrators and rated answers of Qortex AI
+#[async_trait]
+pub trait MessageStore: Send + Sync {
+    async fn add_message(&self, telegram_id: i64, text: &str) -> Result<Uuid>;
+    async fn update_message_status(&self, message_id: Uuid, new_status: MessageStatus, answer: Option<&str>) -> Result<()>;
+    /// Changes only the status, the answer stays as it is
+    async fn set_message_status(&self, message_id: Uuid, new_status: MessageStatus) -> Result<()>;
+    async fn get_user_messages(&self, telegram_id: i64) -> Result<Vec<Message>>;
+    async fn get_user_messages_by_status(&self, telegram_id: i64, status: MessageStatus) -> Result<Vec<Message>>;
+    /// Page of user requests, newest first. `status` None means all requests
+    async fn get_user_messages_page(
+        &self,
+        telegram_id: i64,
+        status: Option<MessageStatus>,
+        cursor: PageCursor,
+        limit: i64,
+    ) -> Result<MessagePage>;
+    async fn get_message_by_id(&self, message_id: Uuid) -> Result<Option<Message>>;
+    async fn get_messages_by_status(&self, status: MessageStatus) -> Result<Vec<Message>>;
+    async fn get_messages_by_statuses(&self, statuses: &[MessageStatus]) -> Result<Vec<Message>>;
+    /// Status or answer changes of requests
+    async fn listen_message_status(&self) -> Result<MessageStatusListener>;
+
+    async fn add_ai_answer(&self, answer_id: Uuid, telegram_id: i64, question: &str, answer: &str) -> Result<()>;
+    /// Rates answer of the user, None if there is no such answer
+    async fn rate_ai_answer(&self, answer_id: Uuid, telegram_id: i64, helpful: bool) -> Result<Option<AiFeedback>>;
+    async fn get_ai_feedback(&self, answer_id: Uuid) -> Result<Option<AiFeedback>>;
+    /// Creates an admin request from the question of the user's answer and links them.
+    /// Returns the request id, None if the answer is not the user's or was already escalated
+    async fn escalate_ai_feedback(&self, answer_id: Uuid, telegram_id: i64) -> Result<Option<Uuid>>;
+}
+
+/// Metadata of shared files and their links, the content is kept by the bot
+#[async_trait]
+pub trait FileStore: Send + Sync {
+    /// Saves metadata of an uploaded file, a file with the same name is replaced and keeps its id
+    async fn upsert_file(&self, file: NewFile<'_>) -> Result<StoredFile>;
+    /// Claims the file name of the owner for one upload, None while another upload holds it
+    async fn claim_file_upload(&self, owner_id: i64, original_name: &str, size: i64) -> Result<Option<FileClaim>>;
+    async fn release_file_upload(&self, owner_id: i64, original_name: &str, token: Uuid) -> Result<()>;
+    async fn get_file(&self, id: Uuid) -> Result<Option<StoredFile>>;
+    async fn get_file_by_name(&self, owner_id: i64, original_name: &str) -> Result<Option<StoredFile>>;
+    async fn get_file_usage(&self, owner_id: i64) -> Result<FileUsage>;
+    /// Deletes the file of the owner together with its share links, returns the deleted file
+    async fn delete_file(&self, owner_id: i64, id: Uuid) -> Result<Option<StoredFile>>;
+    /// Files of the user by name
+    async fn get_user_files(&self, owner_id: i64, offset: i64, limit: i64) -> Result<Vec<StoredFile>>;
+    async fn create_share_link(
+        &self,
+        token: &str,
+        file_id: Uuid,
+        created_by: i64,
+        expires_at: Option<DateTime<Utc>>,
+        max_downloads: Option<i32>,
+    ) -> Result<ShareLink>;
+    /// Checks the link and counts a download, never going over `max_downloads`
+    async fn redeem_share_link(&self, token: &str) -> Result<ShareRedeem>;
+    /// Checks the lifetime of the link without counting a download
+    async fn peek_share_link(&self, token: &str, counted: bool) -> Result<ShareRedeem>;
+    /// Marks links with an expired lifetime or exhausted downloads, returns how many were marked
+    async fn expire_share_links(&self) -> Result<u64>;
+}
+
+/// Everything the bot keeps in the database, see `UserRepository` and `MemoryStore`
+pub trait Store: UserStore + MessageStore + FileStore {}
+
+impl<T: UserStore + MessageStore + FileStore> Store for T {}
+
+#[async_trait]
+impl UserStore for UserRepository {
+    async fn add_user(&self, user: &User) -> Result<()> {
+        UserRepository::add_user(self, user).await
+    }
+
+    async fn delete_user(&self, telegram_id: i64) -> Result<()> {
+        UserRepository::delete_user(self, telegram_id).await
+    }
+
+    async fn get_role(&self, telegram_id: i64) -> Result<UserRole> {
+        UserRepository::get_role(self, telegram_id).await
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<User>> {
+        UserRepository::find_by_username(self, username).await
+    }
+
+    async fn get_user(&self, user_uuid: Uuid) -> Result<Option<User>> {
+        UserRepository::get_user(self, user_uuid).await
+    }
+
+    async fn get_user_by_telegram_id(&self, telegram_id: i64) -> Result<Option<User>> {
+        UserRepository::get_user_by_telegram_id(self, telegram_id).await
+    }
+
+    async fn get_user_language(&self, telegram_id: i64) -> Result<Option<String>> {
+        UserRepository::get_user_language(self, telegram_id).await
+    }
+
+    async fn set_user_language(&self, telegram_id: i64, language: Option<&str>) -> Result<()> {
+        UserRepository::set_user_language(self, telegram_id, language).await
+    }
+
+    async fn set_role(&self, telegram_id: i64, expected: UserRole, role: UserRole, changed_by: i64, reason: Option<&str>) -> Result<Option<UserRole>> {
+        UserRepository::set_role(self, telegram_id, expected, role, changed_by, reason).await
+    }
+
+    async fn get_role_audit(&self, telegram_id: i64, limit: i64) -> Result<Vec<RoleAuditEntry>> {
+        UserRepository::get_role_audit(self, telegram_id, limit).await
+    }
+
+    async fn listen_user_role(&self) -> Result<UserRoleListener> {
+        UserRepository::listen_user_role(self).await
+    }
+
+    async fn get_user_settings(&self, telegram_id: i64) -> Result<UserSettings> {
+        UserRepository::get_user_settings(self, telegram_id).await
+    }
+
+    async fn set_answer_style(&self, telegram_id: i64, style: AnswerStyle) -> Result<()> {
+        UserRepository::set_answer_style(self, telegram_id, style).await
+    }
+
+    async fn toggle_status_notifications(&self, telegram_id: i64) -> Result<bool> {
+        UserRepository::toggle_status_notifications(self, telegram_id).await
+    }
+
+    async fn get_balance(&self, telegram_id: i64) -> Result<i64> {
+        UserRepository::get_balance(self, telegram_id).await
+    }
+
+    async fn get_ledger(&self, telegram_id: i64, limit: i64) -> Result<Vec<LedgerEntry>> {
+        UserRepository::get_ledger(self, telegram_id, limit).await
+    }
+
+    async fn add_welcome_credits(&self, telegram_id: i64, amount: i64) -> Result<bool> {
+        UserRepository::add_welcome_credits(self, telegram_id, amount).await
+    }
+
+    async fn grant_credits(&self, telegram_id: i64, amount: i64, admin_id: i64, comment: Option<&str>) -> Result<i64> {
+        UserRepository::grant_credits(self, telegram_id, amount, admin_id, comment).await
+    }
+
+    async fn charge(&self, telegram_id: i64, amount: i64, reference: Uuid) -> Result<Option<i64>> {
+        UserRepository::charge(self, telegram_id, amount, reference).await
+    }
+
+    async fn refund(&self, telegram_id: i64, reference: Uuid) -> Result<()> {
+        UserRepository::refund(self, telegram_id, reference).await
+    }
+}
+
+#[async_trait]
+impl MessageStore for UserRepository {
+    async fn add_message(&self, telegram_id: i64, text: &str) -> Result<Uuid> {
+        UserRepository::add_message(self, telegram_id, text).await
+    }
+
+    async fn update_message_status(&self, message_id: Uuid, new_status: MessageStatus, answer: Option<&str>) -> Result<()> {
+        UserRepository::update_message_status(self, message_id, new_status, answer).await
+    }
+
+    async fn set_message_status(&self, message_id: Uuid, new_status: MessageStatus) -> Result<()> {
+        UserRepository::set_message_status(self, message_id, new_status).await
+    }
+
+    async fn get_user_messages(&self, telegram_id: i64) -> Result<Vec<Message>> {
+        UserRepository::get_user_messages(self, telegram_id).await
+    }
+
+    async fn get_user_messages_by_status(&self, telegram_id: i64, status: MessageStatus) -> Result<Vec<Message>> {
+        UserRepository::get_user_messages_by_status(self, telegram_id, status).await
+    }
+
+    async fn get_user_messages_page(
+        &self,
+        telegram_id: i64,
+        status: Option<MessageStatus>,
+        cursor: PageCursor,
+        limit: i64,
+    ) -> Result<MessagePage> {
+        UserRepository::get_user_messages_page(self, telegram_id, status, cursor, limit).await
+    }
+
+    async fn get_message_by_id(&self, message_id: Uuid) -> Result<Option<Message>> {
+        UserRepository::get_message_by_id(self, message_id).await
+    }
+
+    async fn get_messages_by_status(&self, status: MessageStatus) -> Result<Vec<Message>> {
+        UserRepository::get_messages_by_status(self, status).await
+    }
+
+    async fn get_messages_by_statuses(&self, statuses: &[MessageStatus]) -> Result<Vec<Message>> {
+        UserRepository::get_messages_by_statuses(self, statuses).await
+    }
+
+    async fn listen_message_status(&self) -> Result<MessageStatusListener> {
+        UserRepository::listen_message_status(self).await
+    }
+
+    async fn add_ai_answer(&self, answer_id: Uuid, telegram_id: i64, question: &str, answer: &str) -> Result<()> {
+        UserRepository::add_ai_answer(self, answer_id, telegram_id, question, answer).await
+    }
+
+    async fn rate_ai_answer(&self, answer_id: Uuid, telegram_id: i64, helpful: bool) -> Result<Option<AiFeedback>> {
+        UserRepository::rate_ai_answer(self, answer_id, telegram_id, helpful).await
+    }
+
+    async fn get_ai_feedback(&self, answer_id: Uuid) -> Result<Option<AiFeedback>> {
+        UserRepository::get_ai_feedback(self, answer_id).await
+    }
+
+    async fn escalate_ai_feedback(&self, answer_id: Uuid, telegram_id: i64) -> Result<Option<Uuid>> {
+        UserRepository::escalate_ai_feedback(self, answer_id, telegram_id).await
+    }
+}
+
+#[async_trait]
+impl FileStore for UserRepository {
+    async fn upsert_file(&self, file: NewFile<'_>) -> Result<StoredFile> {
+        UserRepository::upsert_file(self, file).await
+    }
+
+    async fn claim_file_upload(&self, owner_id: i64, original_name: &str, size: i64) -> Result<Option<FileClaim>> {
+        UserRepository::claim_file_upload(self, owner_id, original_name, size).await
+    }
+
+    async fn release_file_upload(&self, owner_id: i64, original_name: &str, token: Uuid) -> Result<()> {
+        UserRepository::release_file_upload(self, owner_id, original_name, token).await
+    }
+
+    async fn get_file(&self, id: Uuid) -> Result<Option<StoredFile>> {
+        UserRepository::get_file(self, id).await
+    }
+
+    async fn get_file_by_name(&self, owner_id: i64, original_name: &str) -> Result<Option<StoredFile>> {
+        UserRepository::get_file_by_name(self, owner_id, original_name).await
+    }
+
+    async fn get_file_usage(&self, owner_id: i64) -> Result<FileUsage> {
+        UserRepository::get_file_usage(self, owner_id).await
+    }
+
+    async fn delete_file(&self, owner_id: i64, id: Uuid) -> Result<Option<StoredFile>> {
+        UserRepository::delete_file(self, owner_id, id).await
+    }
+
+    async fn get_user_files(&self, owner_id: i64, offset: i64, limit: i64) -> Result<Vec<StoredFile>> {
+        UserRepository::get_user_files(self, owner_id, offset, limit).await
+    }
+
+    async fn create_share_link(
+        &self,
+        token: &str,
+        file_id: Uuid,
+        created_by: i64,
+        expires_at: Option<DateTime<Utc>>,
+        max_downloads: Option<i32>,
+    ) -> Result<ShareLink> {
+        UserRepository::create_share_link(self, token, file_id, created_by, expires_at, max_downloads).await
+    }
+
+    async fn redeem_share_link(&self, token: &str) -> Result<ShareRedeem> {
+        UserRepository::redeem_share_link(self, token).await
+    }
+
+    async fn peek_share_link(&self, token: &str, counted: bool) -> Result<ShareRedeem> {
+        UserRepository::peek_share_link(self, token, counted).await
+    }
+
+    async fn expire_share_links(&self) -> Result<u64> {
+        UserRepository::expire_share_links(self).await
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::{DbError, Result, UserRepository};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "ledger_kind", rename_all = "lowercase")]
+pub enum LedgerKind {
+    /// One-time credits for a new user
+    Welcome,
+    /// Credits added by an administrator
+    Grant,
+    /// Payment for an AI answer
+    Charge,
+    /// Charge returned when AI failed to answer
+    Refund,
+}
+
+/// Entry of `wallet_ledger`. Entries are never changed, balance is the sum of `amount`
+#[derive(Debug, Clone, FromRow)]
+pub struct LedgerEntry {
+    pub id: i64,
+    pub telegram_id: i64,
+    /// Positive for credits, negative for charges
+    pub amount: i64,
+    pub kind: LedgerKind,
+    /// AI answer the charge or refund belongs to
+    pub reference: Option<Uuid>,
+    pub comment: Option<String>,
+    /// Administrator who granted the credits
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl UserRepository {
+    pub async fn get_balance(&self, telegram_id: i64) -> Result<i64> {
+        let balance = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(amount), 0)::BIGINT FROM wallet_ledger WHERE telegram_id = $1"
+        )
+        .bind(telegram_id)
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+
+        Ok(balance)
+    }
+
+    /// Latest entries first
+    pub async fn get_ledger(&self, telegram_id: i64, limit: i64) -> Result<Vec<LedgerEntry>> {
+        let entries = sqlx::query_as::<_, LedgerEntry>(
+            r#"
+            SELECT id, telegram_id, amount, kind, reference, comment, created_by, created_at
+            FROM wallet_ledger
+            WHERE telegram_id = $1
+            ORDER BY created_at DESC, id DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(telegram_id)
+        .bind(limit)
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+
+        Ok(entries)
+    }
+
+    /// Gives welcome credits once per user, returns false if they were already given
+    pub async fn add_welcome_credits(&self, telegram_id: i64, amount: i64) -> Result<bool> {
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO wallet_ledger (telegram_id, amount, kind)
+            VALUES ($1, $2, 'welcome')
+            ON CONFLICT (telegram_id) WHERE kind = 'welcome' DO NOTHING
+            "#,
+        )
+        .bind(telegram_id)
+        .bind(amount)
+        .execute(&mut *self.conn().await?)
+        .await?
+        .rows_affected();
+
+        Ok(inserted > 0)
+    }
+
+    /// Credits from an administrator, returns the new balance. NotFound if the user never started the bot
+    pub async fn grant_credits(&self, telegram_id: i64, amount: i64, admin_id: i64, comment: Option<&str>) -> Result<i64> {
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO wallet_ledger (telegram_id, amount, kind, comment, created_by)
+            SELECT telegram_id, $2, 'grant', $3, $4
+            FROM users
+            WHERE telegram_id = $1
+            "#,
+        )
+        .bind(telegram_id)
+        .bind(amount)
+        .bind(comment)
+        .bind(admin_id)
+        .execute(&mut *self.conn().await?)
+        .await?
+        .rows_affected();
+
+        if inserted == 0 {
+            return Err(DbError::NotFound);
+        }
+        self.get_balance(telegram_id).await
+    }
+
+    /// Charges `amount` if the balance allows it and returns the new balance, None if it is too low.
+    /// Charges of one user are serialized with an advisory lock, so the balance never goes negative
+    pub async fn charge(&self, telegram_id: i64, amount: i64, reference: Uuid) -> Result<Option<i64>> {
+        let mut tx = self.begin().await?;
+
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(telegram_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let balance: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(amount), 0)::BIGINT FROM wallet_ledger WHERE telegram_id = $1"
+        )
+        .bind(telegram_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if balance < amount {
+            tx.rollback().await?;
+            return Ok(None);
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO wallet_ledger (telegram_id, amount, kind, reference)
+            VALUES ($1, $2, 'charge', $3)
+            "#,
+        )
+        .bind(telegram_id)
+        .bind(-amount)
+        .bind(reference)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(balance - amount))
+    }
+
+    /// Returns the charge made for `reference`, does nothing if there was no charge
+    /// or it was already refunded
+    pub async fn refund(&self, telegram_id: i64, reference: Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO wallet_ledger (telegram_id, amount, kind, reference)
+            SELECT telegram_id, -amount, 'refund', reference
+            FROM wallet_ledger
+            WHERE telegram_id = $1 AND reference = $2 AND kind = 'charge'
+            ON CONFLICT (reference) WHERE kind = 'refund' DO NOTHING
+            "#,
+        )
+        .bind(telegram_id)
+        .bind(reference)
+        .execute(&mut *self.conn().await?)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -7,7 +7,7 @@ use tokio::fs;
 async fn get_certs_path() -> PathBuf {
     let path = std::env::current_dir().expect("Не могу получить текущий каталог");
     log_debug!("{:?}", path.to_string_lossy());
-    return path.parent().unwrap().join("tls");
+    path.parent().unwrap().join("tls")
 }
 
 pub async fn load_certs() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
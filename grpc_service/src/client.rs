@@ -1,18 +1,21 @@
-use tonic::Request;
-use tokio::{sync::oneshot, time::Instant};
+use std::env;
 
-use crate::server::proto::{ai_generation_service_client::AiGenerationServiceClient, TextGenerationRequest};
+use futures::{Stream, StreamExt};
+use logging::{log_error, log_info};
+use tonic::{Code, Request};
+use tokio::{sync::{mpsc, oneshot}, time::Instant};
 
-pub fn spawn_client_request_with_callback(
-    sender: oneshot::Sender<Result<String, String>>,
-    text: String,
-) {
-    tokio::spawn(async move {
-        let start = Instant::now();
-        let result = async move {
-            let mut client = AiGenerationServiceClient::connect("http://127.0.0.1:50052").await.unwrap();
+use crate::server::proto::{ai_generation_service_client::AiGenerationServiceClient, ChatMessage, TextGenerationRequest};
+
+/// Адрес AI сервиса по умолчанию
+pub const DEFAULT_AI_SERVICE_URL: &str = "http://127.0.0.1:50052";
+
+/// Адрес AI сервиса из env (AI_SERVICE_URL)
+pub fn ai_service_url() -> String {
+    env::var("AI_SERVICE_URL").unwrap_or(DEFAULT_AI_SERVICE_URL.to_string())
+}
 
-            let system_prompt = r#"
+const SYSTEM_PROMPT: &str = r#"
 Ты — ассистент, который отвечает в plain-тексте. Соблюдай правила:
 1. **Запрещено любое форматирование**:
    - Никаких Markdown, HTML, LaTeX.
@@ -35,19 +38,122 @@ pub fn spawn_client_request_with_callback(
    Не забудьте воду 💧
             "#;
 
-            let request = Request::new(TextGenerationRequest {
-                system_prompt: system_prompt.to_string(),
-                user_prompt: text,
-                temperature: 0.7,
-                top_p: 0.9,
-            });
+/// Параметры сэмплирования модели
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenerationParams {
+    pub temperature: f32,
+    pub top_p: f32,
+}
 
-            let response = client.generate_text(request).await.unwrap();
-            println!("Времени заняло: {} secs", start.elapsed().as_secs());
+impl Default for GenerationParams {
+    fn default() -> Self {
+        Self { temperature: 0.7, top_p: 0.9 }
+    }
+}
+
+/// Собирает запрос на генерацию для вопроса пользователя с контекстом диалога
+pub fn build_request(text: String, history: Vec<ChatMessage>, params: GenerationParams) -> TextGenerationRequest {
+    TextGenerationRequest {
+        system_prompt: SYSTEM_PROMPT.to_string(),
+        user_prompt: text,
+        temperature: params.temperature,
+        top_p: params.top_p,
+        messages: history,
+    }
+}
+
+/// Отправляет вопрос в AI сервис, результат приходит в sender.
+/// Ошибки соединения и генерации возвращаются как Err, без паники
+pub fn spawn_client_request_with_callback(
+    sender: oneshot::Sender<Result<String, String>>,
+    text: String,
+) {
+    tokio::spawn(async move {
+        let start = Instant::now();
+        let result = async move {
+            let mut client = AiGenerationServiceClient::connect(ai_service_url())
+                .await
+                .map_err(|e| format!("Не удалось подключиться к AI сервису: {}", e))?;
+
+            let response = client
+                .generate_text(Request::new(build_request(text, Vec::new(), GenerationParams::default())))
+                .await
+                .map_err(|status| format!("AI сервис вернул ошибку: {}", status.message()))?;
+
+            log_info!("Ответ от AI получен за {} secs", start.elapsed().as_secs());
             Ok::<_, String>(response.into_inner().generated_text)
         }
         .await;
 
+        if let Err(e) = &result {
+            log_error!("{}", e);
+        }
+
         let _ = sender.send(result);
     });
-}
\ No newline at end of file
+}
+
+/// Потоковая генерация: возвращает части ответа по мере готовности.
+/// Если сервер не поддерживает GenerateTextStream, весь ответ придёт одной частью
+pub async fn generate_text_stream(
+    url: String,
+    request: TextGenerationRequest,
+) -> Result<impl Stream<Item = Result<String, String>>, String> {
+    let mut client = AiGenerationServiceClient::connect(url)
+        .await
+        .map_err(|e| format!("Не удалось подключиться к AI сервису: {}", e))?;
+
+    match client.generate_text_stream(Request::new(request.clone())).await {
+        Ok(response) => {
+            let chunks = response
+                .into_inner()
+                .map(|chunk| chunk
+                    .map(|c| c.text)
+                    .map_err(|status| format!("AI сервис прервал генерацию: {}", status.message())));
+            Ok(chunks.boxed())
+        }
+        Err(status) if status.code() == Code::Unimplemented => {
+            let response = client
+                .generate_text(Request::new(request))
+                .await
+                .map_err(|status| format!("AI сервис вернул ошибку: {}", status.message()))?;
+            let text = response.into_inner().generated_text;
+            Ok(futures::stream::once(async move { Ok(text) }).boxed())
+        }
+        Err(status) => Err(format!("AI сервис вернул ошибку: {}", status.message())),
+    }
+}
+
+/// Отправляет вопрос в AI сервис `url` в потоковом режиме, части ответа приходят в sender.
+/// Канал закрывается после последней части или первой ошибки
+pub fn spawn_client_stream_request(
+    url: String,
+    sender: mpsc::Sender<Result<String, String>>,
+    text: String,
+    history: Vec<ChatMessage>,
+    params: GenerationParams,
+) {
+    tokio::spawn(async move {
+        let start = Instant::now();
+        let mut chunks = match generate_text_stream(url, build_request(text, history, params)).await {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                log_error!("{}", e);
+                let _ = sender.send(Err(e)).await;
+                return;
+            }
+        };
+
+        while let Some(chunk) = chunks.next().await {
+            let failed = chunk.is_err();
+            if let Err(e) = &chunk {
+                log_error!("{}", e);
+            }
+            if sender.send(chunk).await.is_err() || failed {
+                return;
+            }
+        }
+
+        log_info!("Потоковый ответ от AI получен за {} secs", start.elapsed().as_secs());
+    });
+}
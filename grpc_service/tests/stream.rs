@@ -0,0 +1,92 @@
+use std::pin::Pin;
+
+use futures::{Stream, StreamExt};
+use grpc_service::client::{build_request, generate_text_stream, GenerationParams};
+use grpc_service::server::proto::{
+    ai_generation_service_server::{AiGenerationService, AiGenerationServiceServer},
+    TextGenerationChunk, TextGenerationRequest, TextGenerationResponse,
+};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+const CHUNKS: [&str; 3] = ["Сегодня ", "солнечно ", "☀️"];
+
+/// AI сервис, отдающий заранее заготовленные части ответа
+struct CannedAi {
+    streaming: bool,
+}
+
+#[tonic::async_trait]
+impl AiGenerationService for CannedAi {
+    type GenerateTextStreamStream =
+        Pin<Box<dyn Stream<Item = Result<TextGenerationChunk, Status>> + Send + 'static>>;
+
+    async fn generate_text(
+        &self,
+        _request: Request<TextGenerationRequest>,
+    ) -> Result<Response<TextGenerationResponse>, Status> {
+        Ok(Response::new(TextGenerationResponse { generated_text: CHUNKS.concat() }))
+    }
+
+    async fn generate_text_stream(
+        &self,
+        _request: Request<TextGenerationRequest>,
+    ) -> Result<Response<Self::GenerateTextStreamStream>, Status> {
+        if !self.streaming {
+            return Err(Status::unimplemented("streaming is not supported"));
+        }
+        let chunks = futures::stream::iter(CHUNKS.map(|text| TextGenerationChunk { text: text.to_string() }))
+            .map(Ok);
+        Ok(Response::new(Box::pin(chunks)))
+    }
+}
+
+async fn spawn_server(streaming: bool) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(
+        Server::builder()
+            .add_service(AiGenerationServiceServer::new(CannedAi { streaming }))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    format!("http://{}", addr)
+}
+
+#[tokio::test]
+async fn stream_yields_canned_chunks_in_order() {
+    let url = spawn_server(true).await;
+
+    let chunks: Vec<_> = generate_text_stream(url, build_request("Какая погода?".to_string(), Vec::new(), GenerationParams::default()))
+        .await
+        .unwrap()
+        .collect()
+        .await;
+
+    let chunks: Vec<String> = chunks.into_iter().map(Result::unwrap).collect();
+    assert_eq!(chunks, CHUNKS);
+}
+
+#[tokio::test]
+async fn stream_falls_back_to_unary_when_unimplemented() {
+    let url = spawn_server(false).await;
+
+    let chunks: Vec<_> = generate_text_stream(url, build_request("Какая погода?".to_string(), Vec::new(), GenerationParams::default()))
+        .await
+        .unwrap()
+        .collect()
+        .await;
+
+    assert_eq!(chunks, vec![Ok(CHUNKS.concat())]);
+}
+
+#[tokio::test]
+async fn stream_reports_unreachable_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    assert!(generate_text_stream(url, build_request(String::new(), Vec::new(), GenerationParams::default())).await.is_err());
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use toml::{Table, Value};
+
+use crate::LocalizationError;
+
+const PLURAL_FORMS: [&str; 6] = ["zero", "one", "two", "few", "many", "other"];
+
+#[derive(Debug, Clone)]
+pub enum Entry {
+    Text(String),
+    /// Form by plural category, `other` is always present
+    Plural(HashMap<String, String>),
+}
+
+/// Messages of one language, nested TOML tables are flattened into dotted keys
+#[derive(Debug, Default, Clone)]
+pub struct Catalog {
+    entries: HashMap<String, Entry>,
+}
+
+impl Catalog {
+    pub fn parse(source: &str) -> Result<Self, LocalizationError> {
+        let table: Table = source.parse()?;
+        let mut catalog = Catalog::default();
+        catalog.flatten("", table)?;
+        Ok(catalog)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.get(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    fn flatten(&mut self, prefix: &str, table: Table) -> Result<(), LocalizationError> {
+        for (name, value) in table {
+            let key = if prefix.is_empty() { name } else { format!("{}.{}", prefix, name) };
+            match value {
+                Value::String(text) => {
+                    self.entries.insert(key, Entry::Text(text));
+                }
+                Value::Table(table) if is_plural(&table) => {
+                    let forms = table
+                        .into_iter()
+                        .map(|(form, text)| match text {
+                            Value::String(text) => Ok((form, text)),
+                            _ => Err(LocalizationError::InvalidEntry(format!("{}.{}", key, form))),
+                        })
+                        .collect::<Result<_, _>>()?;
+                    self.entries.insert(key, Entry::Plural(forms));
+                }
+                Value::Table(table) => self.flatten(&key, table)?,
+                _ => return Err(LocalizationError::InvalidEntry(key)),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_plural(table: &Table) -> bool {
+    table.contains_key("other") && table.keys().all(|k| PLURAL_FORMS.contains(&k.as_str()))
+}
@@ -1,3 +1,244 @@
-fn modsa() {
-    println!("lox");
-}
\ No newline at end of file
+mod catalog;
+mod plural;
+
+use std::{collections::HashMap, fs, path::Path, sync::LazyLock};
+
+use thiserror::Error;
+
+pub use catalog::{Catalog, Entry};
+
+#[derive(Debug, Error)]
+pub enum LocalizationError {
+    #[error("failed to read catalog: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse catalog: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("catalog entry {0} must be a string or a table of plural forms")]
+    InvalidEntry(String),
+}
+
+/// Languages the bot has catalogs for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Lang {
+    #[default]
+    Ru,
+    En,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 2] = [Lang::Ru, Lang::En];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::Ru => "ru",
+            Lang::En => "en",
+        }
+    }
+
+    /// Accepts codes like `ru`, `en-US`, `en_GB` as sent by Telegram in `language_code`
+    pub fn from_code(code: &str) -> Option<Lang> {
+        let primary = code.split(['-', '_']).next()?.to_ascii_lowercase();
+        Lang::ALL.into_iter().find(|lang| lang.code() == primary)
+    }
+}
+
+/// Catalogs shipped with the bot in `locales/`
+static BUILTIN: LazyLock<Localizer> = LazyLock::new(|| {
+    Localizer::from_sources(&[
+        (Lang::Ru, include_str!("../../locales/ru.toml")),
+        (Lang::En, include_str!("../../locales/en.toml")),
+    ])
+    .expect("Встроенные каталоги локализации повреждены")
+});
+
+pub struct Localizer {
+    catalogs: HashMap<Lang, Catalog>,
+    /// Used when the key is missing in the requested language
+    fallback: Lang,
+}
+
+impl Localizer {
+    pub fn builtin() -> &'static Localizer {
+        &BUILTIN
+    }
+
+    pub fn from_sources(sources: &[(Lang, &str)]) -> Result<Self, LocalizationError> {
+        let catalogs = sources
+            .iter()
+            .map(|(lang, source)| Ok((*lang, Catalog::parse(source)?)))
+            .collect::<Result<_, LocalizationError>>()?;
+        Ok(Self { catalogs, fallback: Lang::default() })
+    }
+
+    /// Loads `<code>.toml` for every known language from `dir`
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, LocalizationError> {
+        let mut catalogs = HashMap::new();
+        for lang in Lang::ALL {
+            let source = fs::read_to_string(dir.as_ref().join(format!("{}.toml", lang.code())))?;
+            catalogs.insert(lang, Catalog::parse(&source)?);
+        }
+        Ok(Self { catalogs, fallback: Lang::default() })
+    }
+
+    pub fn catalog(&self, lang: Lang) -> Option<&Catalog> {
+        self.catalogs.get(&lang)
+    }
+
+    pub fn text(&self, lang: Lang, key: &str, args: &[(&str, &str)]) -> String {
+        match self.entry(lang, key) {
+            Some(Entry::Text(text)) => substitute(text, args),
+            Some(Entry::Plural(forms)) => substitute(&forms["other"], args),
+            None => key.to_string(),
+        }
+    }
+
+    /// Picks the plural form for `count`, `{count}` is available in the text
+    pub fn plural(&self, lang: Lang, key: &str, count: u64, args: &[(&str, &str)]) -> String {
+        let count_str = count.to_string();
+        let mut all_args = vec![("count", count_str.as_str())];
+        all_args.extend_from_slice(args);
+
+        // Форма выбирается по правилам того языка, из которого взят текст
+        let (entry_lang, entry) = match self.catalog(lang).and_then(|c| c.get(key)) {
+            Some(entry) => (lang, Some(entry)),
+            None => (self.fallback, self.catalog(self.fallback).and_then(|c| c.get(key))),
+        };
+        match entry {
+            Some(Entry::Plural(forms)) => {
+                let form = forms.get(plural::category(entry_lang, count)).unwrap_or(&forms["other"]);
+                substitute(form, &all_args)
+            }
+            Some(Entry::Text(text)) => substitute(text, &all_args),
+            None => key.to_string(),
+        }
+    }
+
+    fn entry(&self, lang: Lang, key: &str) -> Option<&Entry> {
+        self.catalog(lang)
+            .and_then(|c| c.get(key))
+            .or_else(|| self.catalog(self.fallback).and_then(|c| c.get(key)))
+    }
+}
+
+/// Text from the builtin catalogs
+pub fn t(lang: Lang, key: &str) -> String {
+    Localizer::builtin().text(lang, key, &[])
+}
+
+/// Text with `{name}` placeholders from the builtin catalogs
+pub fn t_args(lang: Lang, key: &str, args: &[(&str, &str)]) -> String {
+    Localizer::builtin().text(lang, key, args)
+}
+
+/// Plural form from the builtin catalogs
+pub fn t_plural(lang: Lang, key: &str, count: u64, args: &[(&str, &str)]) -> String {
+    Localizer::builtin().plural(lang, key, count, args)
+}
+
+/// Replaces `{name}` with values from `args`, unknown placeholders are left as is
+fn substitute(text: &str, args: &[(&str, &str)]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let Some(end) = tail.find('}') else {
+            result.push_str(tail);
+            return result;
+        };
+        let name = &tail[1..end];
+        match args.iter().find(|(arg, _)| *arg == name) {
+            Some((_, value)) => result.push_str(value),
+            None => result.push_str(&tail[..=end]),
+        }
+        rest = &tail[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    const RU: &str = r#"
+        greeting = "Привет, {name}!"
+        only_ru = "Только по-русски"
+
+        [requests.count]
+        one = "{count} обращение"
+        few = "{count} обращения"
+        many = "{count} обращений"
+        other = "{count} обращения"
+    "#;
+
+    const EN: &str = r#"
+        greeting = "Hello, {name}!"
+
+        [requests.count]
+        one = "{count} request"
+        other = "{count} requests"
+    "#;
+
+    fn localizer() -> Localizer {
+        Localizer::from_sources(&[(Lang::Ru, RU), (Lang::En, EN)]).unwrap()
+    }
+
+    #[test]
+    fn language_codes() {
+        assert_eq!(Lang::from_code("ru"), Some(Lang::Ru));
+        assert_eq!(Lang::from_code("en-US"), Some(Lang::En));
+        assert_eq!(Lang::from_code("EN_gb"), Some(Lang::En));
+        assert_eq!(Lang::from_code("de"), None);
+        assert_eq!(Lang::from_code(""), None);
+    }
+
+    #[test]
+    fn placeholders() {
+        let l = localizer();
+        assert_eq!(l.text(Lang::En, "greeting", &[("name", "Ann")]), "Hello, Ann!");
+        assert_eq!(l.text(Lang::Ru, "greeting", &[]), "Привет, {name}!");
+    }
+
+    #[test]
+    fn plural_forms() {
+        let l = localizer();
+        assert_eq!(l.plural(Lang::Ru, "requests.count", 1, &[]), "1 обращение");
+        assert_eq!(l.plural(Lang::Ru, "requests.count", 3, &[]), "3 обращения");
+        assert_eq!(l.plural(Lang::Ru, "requests.count", 11, &[]), "11 обращений");
+        assert_eq!(l.plural(Lang::En, "requests.count", 1, &[]), "1 request");
+        assert_eq!(l.plural(Lang::En, "requests.count", 5, &[]), "5 requests");
+    }
+
+    #[test]
+    fn falls_back_to_default_language_then_key() {
+        let l = localizer();
+        assert_eq!(l.text(Lang::En, "only_ru", &[]), "Только по-русски");
+        assert_eq!(l.text(Lang::En, "missing.key", &[]), "missing.key");
+    }
+
+    #[test]
+    fn builtin_catalogs_have_same_keys_and_placeholders() {
+        let l = Localizer::builtin();
+        let entries = |lang| -> BTreeSet<(String, BTreeSet<String>)> {
+            let catalog = l.catalog(lang).unwrap();
+            catalog
+                .keys()
+                .map(|key| {
+                    let text = match catalog.get(key).unwrap() {
+                        Entry::Text(text) => text.clone(),
+                        Entry::Plural(forms) => forms.values().cloned().collect(),
+                    };
+                    (key.to_string(), placeholder_names(&text))
+                })
+                .collect()
+        };
+        assert_eq!(entries(Lang::Ru), entries(Lang::En));
+    }
+
+    fn placeholder_names(text: &str) -> BTreeSet<String> {
+        text.split('{').skip(1).filter_map(|s| s.split_once('}')).map(|(name, _)| name.to_string()).collect()
+    }
+}
@@ -0,0 +1,43 @@
+use crate::Lang;
+
+/// Plural category by CLDR rules for integers
+pub fn category(lang: Lang, n: u64) -> &'static str {
+    match lang {
+        Lang::Ru => {
+            let (n10, n100) = (n % 10, n % 100);
+            if n10 == 1 && n100 != 11 {
+                "one"
+            } else if (2..=4).contains(&n10) && !(12..=14).contains(&n100) {
+                "few"
+            } else {
+                "many"
+            }
+        }
+        Lang::En => {
+            if n == 1 { "one" } else { "other" }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn russian_forms() {
+        assert_eq!(category(Lang::Ru, 1), "one");
+        assert_eq!(category(Lang::Ru, 21), "one");
+        assert_eq!(category(Lang::Ru, 11), "many");
+        assert_eq!(category(Lang::Ru, 3), "few");
+        assert_eq!(category(Lang::Ru, 14), "many");
+        assert_eq!(category(Lang::Ru, 0), "many");
+        assert_eq!(category(Lang::Ru, 102), "few");
+    }
+
+    #[test]
+    fn english_forms() {
+        assert_eq!(category(Lang::En, 1), "one");
+        assert_eq!(category(Lang::En, 0), "other");
+        assert_eq!(category(Lang::En, 21), "other");
+    }
+}
@@ -1,8 +1,26 @@
-use bot::start;
+use std::{env, path::Path};
+
+use bot::{migrate, replay, start};
 
 
 
 #[tokio::main]
 async fn main() {
-    start().await;
+    match env::args().nth(1).as_deref() {
+        None => start().await,
+        // Миграции отдельно от бота, например перед деплоем
+        Some("migrate") => migrate().await,
+        // Прогон записи UPDATE_RECORD_PATH через обработчики без Telegram и Postgres
+        Some("replay") => match env::args().nth(2) {
+            Some(path) => replay(Path::new(&path)).await,
+            None => {
+                eprintln!("Использование: main_app replay <файл записи>");
+                std::process::exit(2);
+            }
+        },
+        Some(command) => {
+            eprintln!("Неизвестная команда `{}`. Использование: main_app [migrate | replay <файл>]", command);
+            std::process::exit(2);
+        }
+    }
 }